@@ -0,0 +1,83 @@
+//! Sorting a `FooSoA` against sorting the equivalent `Vec<Foo>`.
+//!
+//! Run with `cargo bench --bench sort`. Each case sorts a fresh copy of the same
+//! scrambled data and reports the best of a few runs.
+
+use dods::SoA;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+#[derive(SoA, Debug, Clone, PartialEq)]
+struct Particle {
+    position: [f32; 3],
+    velocity: [f32; 3],
+    mass: f32,
+    id: u64,
+}
+
+const LEN: u64 = 200_000;
+const RUNS: usize = 5;
+
+fn particles() -> Vec<Particle> {
+    (0..LEN)
+        .map(|i| {
+            let f = i as f32;
+            Particle {
+                position: [f, f * 0.5, -f],
+                velocity: [1.0, 0.0, f.sin()],
+                mass: 1.0 + (i % 17) as f32,
+                id: i.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 24,
+            }
+        })
+        .collect()
+}
+
+/// Runs `sort` on a fresh clone of `input` `RUNS` times and prints the fastest.
+fn bench<T: Clone>(name: &str, input: &T, mut sort: impl FnMut(&mut T)) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut data = input.clone();
+        let start = Instant::now();
+        sort(black_box(&mut data));
+        best = best.min(start.elapsed());
+        black_box(&data);
+    }
+    println!("{name:<40} {:>10.2?}", best);
+}
+
+fn main() {
+    let vec = particles();
+    let soa: ParticleSoA = vec.iter().cloned().collect();
+    println!("sorting {LEN} particles, best of {RUNS}");
+
+    bench("Vec<Particle>::sort_by_key", &vec, |v| {
+        v.sort_by_key(|p| p.id)
+    });
+    bench("ParticleSoA::sort_by_key", &soa, |s| {
+        s.sort_by_key(|p| *p.id)
+    });
+    bench("ParticleSoA::sort_by_field", &soa, |s| {
+        s.sort_by_field(|s| s.id)
+    });
+
+    bench("Vec<Particle>::sort_unstable_by_key", &vec, |v| {
+        v.sort_unstable_by_key(|p| p.id)
+    });
+    bench("ParticleSoA::sort_unstable_by_key", &soa, |s| {
+        s.sort_unstable_by_key(|p| *p.id)
+    });
+
+    bench("Vec<Particle>::sort_by_cached_key", &vec, |v| {
+        v.sort_by_cached_key(|p| p.mass.to_bits())
+    });
+    bench("ParticleSoA::sort_by_cached_key", &soa, |s| {
+        s.sort_by_cached_key(|p| p.mass.to_bits())
+    });
+
+    bench("Vec<Particle>::sort_by", &vec, |v| {
+        v.sort_by(|a, b| a.mass.total_cmp(&b.mass))
+    });
+    bench("ParticleSoA::sort_by", &soa, |s| {
+        s.sort_by(|a, b| a.mass.total_cmp(b.mass))
+    });
+}
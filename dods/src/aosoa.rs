@@ -0,0 +1,536 @@
+use crate::iter::{zip_expr, zip_pattern};
+use crate::model::Soa;
+use crate::traits::debug_record;
+use proc_macro2::{Literal, TokenStream};
+use quote::{ToTokens, quote};
+use syn::ext::IdentExt;
+
+/// The `FooAoSoA` container requested with `#[soa(aosoa = N)]`: the elements are
+/// grouped into `FooChunk`s of `N`, and each chunk stores every field as an `[T; N]`
+/// array, so a chunk's field can be loaded straight into a SIMD register.
+///
+/// The unused tail of the last chunk holds `Default::default()` values, which keeps
+/// every chunk fully initialized without any unsafe code. Every method that adds or
+/// removes elements therefore needs `T: Default` for each field. Elements move
+/// between lanes with `swap`, so `insert`, `remove` and the sorts cost a swap per
+/// element moved across every field, rather than the `memmove` of a column.
+/// `append` and `split_off` move whole chunks when the split falls on a chunk
+/// boundary, and move the elements one at a time otherwise.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    let Some(lanes) = &soa.attrs.aosoa else {
+        return quote! {};
+    };
+    // Re-emitted unsuffixed, since `#[soa(aosoa = 4u8)]` is accepted but `[T; 4u8]`
+    // is not a valid array type.
+    let lanes = Literal::usize_unsuffixed(lanes.base10_parse().expect("checked in attr"));
+    let name = soa.name();
+    let vis = &soa.vis;
+    let lt = &soa.lt;
+    let aosoa_name = &soa.names.aosoa;
+    let chunk_name = &soa.names.chunk;
+    let ref_name = &soa.names.ref_;
+    let ref_mut_name = &soa.names.ref_mut;
+    let generics = soa.generics();
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let soa_ref = soa.view_ty(ref_name);
+    let soa_ref_mut = soa.view_ty(ref_mut_name);
+    let default_where = soa.where_columns(quote! { core::default::Default }, |c| {
+        c.ty.to_token_stream()
+    });
+    let clone_where = soa.where_columns(quote! { core::clone::Clone }, |c| c.ty.to_token_stream());
+    let debug_where = soa.where_columns(quote! { core::fmt::Debug }, |c| c.ty.to_token_stream());
+    let eq_where = soa.where_columns(quote! { core::cmp::PartialEq }, |c| c.ty.to_token_stream());
+    let total_eq_where = soa.where_columns(quote! { core::cmp::Eq }, |c| c.ty.to_token_stream());
+
+    let members: Vec<_> = soa.columns.iter().map(|c| &c.member).collect();
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let types: Vec<_> = soa.columns.iter().map(|c| c.ty).collect();
+    let field_vis: Vec<_> = soa.columns.iter().map(|c| c.vis).collect();
+    let labels: Vec<_> = cols.iter().map(|c| c.unraw().to_string()).collect();
+    let chunk_label = chunk_name.unraw().to_string();
+    let zip_mut = zip_expr(&soa.columns, |col| quote! { chunk.#col.iter_mut() });
+    let pattern = zip_pattern(&soa.columns);
+    let elements: Vec<_> = cols.iter().map(|c| quote! { chunk.#c[lane] }).collect();
+    let record = debug_record(soa, &elements);
+
+    quote! {
+        /// A chunk of consecutive elements of the AoSoA, each field stored as an array.
+        #vis struct #chunk_name #generics #where_clause {
+            #( #field_vis #cols: [#types; #lanes], )*
+        }
+
+        /// Array-of-structs-of-arrays container generated for the source struct.
+        #vis struct #aosoa_name #generics #where_clause {
+            chunks: Vec<#chunk_name #ty_generics>,
+            len: usize,
+        }
+
+        impl #impl_generics #aosoa_name #ty_generics #where_clause {
+            /// Number of elements in each chunk.
+            pub const LANES: usize = #lanes;
+
+            /// Creates a new, empty AoSoA.
+            pub fn new() -> Self {
+                Self { chunks: Vec::new(), len: 0 }
+            }
+
+            /// Creates an empty AoSoA with capacity for at least `capacity` elements.
+            pub fn with_capacity(capacity: usize) -> Self {
+                Self {
+                    chunks: Vec::with_capacity(capacity.div_ceil(Self::LANES)),
+                    len: 0,
+                }
+            }
+
+            /// Returns the number of elements in the AoSoA.
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns true when the AoSoA has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// Returns the number of elements the AoSoA can hold without reallocating.
+            pub fn capacity(&self) -> usize {
+                self.chunks.capacity() * Self::LANES
+            }
+
+            /// Reserves capacity for at least `additional` more elements, rounded up to
+            /// whole chunks.
+            pub fn reserve(&mut self, additional: usize) {
+                let len = self.len.checked_add(additional).expect("capacity overflow");
+                self.chunks.reserve(len.div_ceil(Self::LANES) - self.chunks.len());
+            }
+
+            /// Reserves the minimum capacity for `additional` more elements, rounded up
+            /// to whole chunks.
+            pub fn reserve_exact(&mut self, additional: usize) {
+                let len = self.len.checked_add(additional).expect("capacity overflow");
+                self.chunks.reserve_exact(len.div_ceil(Self::LANES) - self.chunks.len());
+            }
+
+            /// Shrinks the chunk buffer as much as possible.
+            pub fn shrink_to_fit(&mut self) {
+                self.chunks.shrink_to_fit();
+            }
+
+            /// Clears all elements from the AoSoA.
+            pub fn clear(&mut self) {
+                self.chunks.clear();
+                self.len = 0;
+            }
+
+            /// Returns references to the element at `index`, or `None` if out of bounds.
+            pub fn get(&self, index: usize) -> Option<#soa_ref> {
+                if index >= self.len { return None; }
+                let (chunk, lane) = (&self.chunks[index / Self::LANES], index % Self::LANES);
+                Some(#ref_name {
+                    #( #cols: &chunk.#cols[lane], )*
+                })
+            }
+
+            /// Returns references to the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn index(&self, index: usize) -> #soa_ref {
+                let len = self.len;
+                self.get(index).unwrap_or_else(|| {
+                    panic!("index out of bounds: the len is {len} but the index is {index}")
+                })
+            }
+
+            /// Returns mutable references to the element at `index`, or `None` if out of bounds.
+            pub fn get_mut(&mut self, index: usize) -> Option<#soa_ref_mut> {
+                if index >= self.len { return None; }
+                let (chunk, lane) = (&mut self.chunks[index / Self::LANES], index % Self::LANES);
+                Some(#ref_mut_name {
+                    #( #cols: &mut chunk.#cols[lane], )*
+                })
+            }
+
+            /// Returns mutable references to the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn index_mut(&mut self, index: usize) -> #soa_ref_mut {
+                let len = self.len;
+                self.get_mut(index).unwrap_or_else(|| {
+                    panic!("index out of bounds: the len is {len} but the index is {index}")
+                })
+            }
+
+            /// Returns the first element, if any.
+            pub fn first(&self) -> Option<#soa_ref> {
+                self.get(0)
+            }
+
+            /// Returns the last element, if any.
+            pub fn last(&self) -> Option<#soa_ref> {
+                self.get(self.len.checked_sub(1)?)
+            }
+
+            /// Returns mutable references to the first element, if any.
+            pub fn first_mut(&mut self) -> Option<#soa_ref_mut> {
+                self.get_mut(0)
+            }
+
+            /// Returns mutable references to the last element, if any.
+            pub fn last_mut(&mut self) -> Option<#soa_ref_mut> {
+                self.get_mut(self.len.checked_sub(1)?)
+            }
+
+            /// Replaces the element at `index` with `element`, returning the old value.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn replace(&mut self, index: usize, element: #name #ty_generics) -> #name #ty_generics {
+                let len = self.len;
+                assert!(index < len, "replace index (is {index}) should be < len (is {len})");
+                let (chunk, lane) = (&mut self.chunks[index / Self::LANES], index % Self::LANES);
+                #name {
+                    #( #members: core::mem::replace(&mut chunk.#cols[lane], element.#members), )*
+                }
+            }
+
+            /// Swaps the elements at `a` and `b`.
+            ///
+            /// # Panics
+            /// Panics if `a` or `b` is out of bounds.
+            pub fn swap(&mut self, a: usize, b: usize) {
+                let len = self.len;
+                assert!(
+                    a < len && b < len,
+                    "swap indices {a} and {b} out of bounds for length {len}"
+                );
+                let (chunk_a, lane_a) = (a / Self::LANES, a % Self::LANES);
+                let (chunk_b, lane_b) = (b / Self::LANES, b % Self::LANES);
+                if chunk_a == chunk_b {
+                    let chunk = &mut self.chunks[chunk_a];
+                    #( chunk.#cols.swap(lane_a, lane_b); )*
+                } else {
+                    let (low, high) = self.chunks.split_at_mut(chunk_a.max(chunk_b));
+                    let (x, y) = if chunk_a < chunk_b {
+                        (&mut low[chunk_a], &mut high[0])
+                    } else {
+                        (&mut high[0], &mut low[chunk_b])
+                    };
+                    #( core::mem::swap(&mut x.#cols[lane_a], &mut y.#cols[lane_b]); )*
+                }
+            }
+
+            /// Moves each element to the position that names it in `indices`, the
+            /// new-to-old mapping, following each cycle once like the slice views do.
+            fn apply_permutation(&mut self, mut indices: Vec<usize>) {
+                debug_assert_eq!(indices.len(), self.len);
+                for start in 0..indices.len() {
+                    let mut current = start;
+                    while indices[current] != current {
+                        let next = core::mem::replace(&mut indices[current], current);
+                        if next == start {
+                            break;
+                        }
+                        self.swap(current, next);
+                        current = next;
+                    }
+                }
+            }
+
+            pub fn sort_by<F>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref, #soa_ref) -> core::cmp::Ordering,
+            {
+                let mut permutation: Vec<usize> = (0..self.len).collect();
+                permutation.sort_by(|j, k| f(self.index(*j), self.index(*k)));
+                self.apply_permutation(permutation);
+            }
+
+            pub fn sort_by_key<F, K>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref) -> K,
+                K: Ord,
+            {
+                let mut permutation: Vec<usize> = (0..self.len).collect();
+                permutation.sort_by_key(|j| f(self.index(*j)));
+                self.apply_permutation(permutation);
+            }
+
+            /// Sorts the AoSoA with a comparator, without preserving the order of equal
+            /// elements.
+            pub fn sort_unstable_by<F>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref, #soa_ref) -> core::cmp::Ordering,
+            {
+                let mut permutation: Vec<usize> = (0..self.len).collect();
+                permutation.sort_unstable_by(|j, k| f(self.index(*j), self.index(*k)));
+                self.apply_permutation(permutation);
+            }
+
+            /// Sorts the AoSoA with a key extraction function, without preserving the
+            /// order of equal elements.
+            pub fn sort_unstable_by_key<F, K>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref) -> K,
+                K: Ord,
+            {
+                let mut permutation: Vec<usize> = (0..self.len).collect();
+                permutation.sort_unstable_by_key(|j| f(self.index(*j)));
+                self.apply_permutation(permutation);
+            }
+
+            /// Returns an iterator over references to each element.
+            pub fn iter(&self) -> impl DoubleEndedIterator<Item = #soa_ref> + ExactSizeIterator {
+                (0..self.len).map(move |index| self.index(index))
+            }
+
+            /// Returns an iterator over mutable references to each element.
+            pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = #soa_ref_mut> {
+                let len = self.len;
+                self.chunks.iter_mut().enumerate().flat_map(move |(i, chunk)| {
+                    let lanes = (len - i * Self::LANES).min(Self::LANES);
+                    #zip_mut.take(lanes).map(|#pattern| #ref_mut_name { #( #cols, )* })
+                })
+            }
+
+            /// Returns an iterator over the chunks. The last chunk is padded with
+            /// default values past `len`.
+            pub fn chunks(&self) -> core::slice::Iter<'_, #chunk_name #ty_generics> {
+                self.chunks.iter()
+            }
+
+            /// Returns an iterator over the chunks that allows modifying each one.
+            pub fn chunks_mut(&mut self) -> core::slice::IterMut<'_, #chunk_name #ty_generics> {
+                self.chunks.iter_mut()
+            }
+        }
+
+        impl #impl_generics #aosoa_name #ty_generics #default_where {
+            /// Appends a single `#name` to the AoSoA, starting a new chunk when the last
+            /// one is full.
+            pub fn push(&mut self, item: #name #ty_generics) {
+                let lane = self.len % Self::LANES;
+                if lane == 0 {
+                    self.chunks.push(#chunk_name {
+                        #( #cols: core::array::from_fn(|_| core::default::Default::default()), )*
+                    });
+                }
+                let chunk = self.chunks.last_mut().unwrap();
+                #( chunk.#cols[lane] = item.#members; )*
+                self.len += 1;
+            }
+
+            /// Removes the last element and returns it, or `None` if the AoSoA is empty.
+            pub fn pop(&mut self) -> Option<#name #ty_generics> {
+                if self.len == 0 { return None; }
+                self.len -= 1;
+                let lane = self.len % Self::LANES;
+                let chunk = self.chunks.last_mut().unwrap();
+                let item = #name {
+                    #( #members: core::mem::take(&mut chunk.#cols[lane]), )*
+                };
+                if lane == 0 {
+                    self.chunks.pop();
+                }
+                Some(item)
+            }
+
+            /// Inserts `item` at `index`, shifting later elements up.
+            ///
+            /// # Panics
+            /// Panics if `index > len`.
+            pub fn insert(&mut self, index: usize, item: #name #ty_generics) {
+                let len = self.len;
+                assert!(index <= len, "insertion index (is {index}) should be <= len (is {len})");
+                self.push(item);
+                for i in (index..len).rev() {
+                    self.swap(i, i + 1);
+                }
+            }
+
+            /// Removes and returns the element at `index`, shifting later elements down.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn remove(&mut self, index: usize) -> #name #ty_generics {
+                let len = self.len;
+                assert!(index < len, "removal index (is {index}) should be < len (is {len})");
+                for i in index..len - 1 {
+                    self.swap(i, i + 1);
+                }
+                self.pop().unwrap()
+            }
+
+            /// Removes the element at `index` and returns it, moving the last element
+            /// into its place.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn swap_remove(&mut self, index: usize) -> #name #ty_generics {
+                let len = self.len;
+                assert!(index < len, "swap_remove index (is {index}) should be < len (is {len})");
+                self.swap(index, len - 1);
+                self.pop().unwrap()
+            }
+
+            /// Shortens the AoSoA to `len` elements, dropping the rest and resetting the
+            /// tail of the last chunk to default values. Does nothing if `len` is not
+            /// less than the current length.
+            pub fn truncate(&mut self, len: usize) {
+                if len >= self.len {
+                    return;
+                }
+                self.chunks.truncate(len.div_ceil(Self::LANES));
+                let lane = len % Self::LANES;
+                if lane != 0 {
+                    let chunk = self.chunks.last_mut().unwrap();
+                    #(
+                        for value in &mut chunk.#cols[lane..] {
+                            *value = core::default::Default::default();
+                        }
+                    )*
+                }
+                self.len = len;
+            }
+
+            /// Appends all elements from `other`, leaving it empty. When the last chunk
+            /// of `self` is full, the chunks of `other` are moved over as they are.
+            pub fn append(&mut self, other: &mut Self) {
+                let len = core::mem::take(&mut other.len);
+                if self.len % Self::LANES == 0 {
+                    self.chunks.append(&mut other.chunks);
+                    self.len += len;
+                    return;
+                }
+                self.reserve(len);
+                for index in 0..len {
+                    let (chunk, lane) = (&mut other.chunks[index / Self::LANES], index % Self::LANES);
+                    self.push(#name {
+                        #( #members: core::mem::take(&mut chunk.#cols[lane]), )*
+                    });
+                }
+                other.chunks.clear();
+            }
+
+            /// Splits the AoSoA into two at `at`, returning the tail. When `at` falls on
+            /// a chunk boundary, the tail chunks are moved over as they are.
+            ///
+            /// # Panics
+            /// Panics if `at > len`.
+            pub fn split_off(&mut self, at: usize) -> Self {
+                let len = self.len;
+                assert!(at <= len, "`at` split index (is {at}) should be <= len (is {len})");
+                if at % Self::LANES == 0 {
+                    let chunks = self.chunks.split_off(at / Self::LANES);
+                    self.len = at;
+                    return Self { chunks, len: len - at };
+                }
+                let mut tail = Self::with_capacity(len - at);
+                for index in at..len {
+                    let (chunk, lane) = (&mut self.chunks[index / Self::LANES], index % Self::LANES);
+                    tail.push(#name {
+                        #( #members: core::mem::take(&mut chunk.#cols[lane]), )*
+                    });
+                }
+                self.truncate(at);
+                tail
+            }
+
+            /// Clones and appends every element of `items`.
+            pub fn extend_from_slice(&mut self, items: &[#name #ty_generics])
+            where
+                for<#lt> #name #ty_generics: core::clone::Clone,
+            {
+                self.extend(items.iter().cloned());
+            }
+        }
+
+        impl #impl_generics core::clone::Clone for #chunk_name #ty_generics #clone_where {
+            fn clone(&self) -> Self {
+                Self {
+                    #( #cols: self.#cols.clone(), )*
+                }
+            }
+        }
+
+        impl #impl_generics core::fmt::Debug for #chunk_name #ty_generics #debug_where {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_struct(#chunk_label)
+                    #( .field(#labels, &self.#cols) )*
+                    .finish()
+            }
+        }
+
+        impl #impl_generics core::cmp::PartialEq for #chunk_name #ty_generics #eq_where {
+            fn eq(&self, other: &Self) -> bool {
+                true #( && self.#cols == other.#cols )*
+            }
+        }
+
+        impl #impl_generics core::cmp::Eq for #chunk_name #ty_generics #total_eq_where {}
+
+        impl #impl_generics core::clone::Clone for #aosoa_name #ty_generics #clone_where {
+            fn clone(&self) -> Self {
+                Self {
+                    chunks: self.chunks.clone(),
+                    len: self.len,
+                }
+            }
+        }
+
+        /// Formats the AoSoA as a list of records, the same way `Vec<Foo>` would.
+        impl #impl_generics core::fmt::Debug for #aosoa_name #ty_generics #debug_where {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                struct Record<F>(F);
+                impl<F> core::fmt::Debug for Record<F>
+                where
+                    F: Fn(&mut core::fmt::Formatter<'_>) -> core::fmt::Result,
+                {
+                    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                        (self.0)(f)
+                    }
+                }
+
+                f.debug_list()
+                    .entries((0..self.len).map(|index| {
+                        let (chunk, lane) = (&self.chunks[index / Self::LANES], index % Self::LANES);
+                        Record(move |f: &mut core::fmt::Formatter<'_>| #record)
+                    }))
+                    .finish()
+            }
+        }
+
+        /// Compares the elements only, not the default values padding the last chunk.
+        impl #impl_generics core::cmp::PartialEq for #aosoa_name #ty_generics #eq_where {
+            fn eq(&self, other: &Self) -> bool {
+                self.len == other.len
+                    && self.iter().zip(other.iter()).all(|(a, b)| true #( && a.#cols == b.#cols )*)
+            }
+        }
+
+        impl #impl_generics core::cmp::Eq for #aosoa_name #ty_generics #total_eq_where {}
+
+        impl #impl_generics core::default::Default for #aosoa_name #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl #impl_generics FromIterator<#name #ty_generics> for #aosoa_name #ty_generics #default_where {
+            fn from_iter<I: IntoIterator<Item = #name #ty_generics>>(iter: I) -> Self {
+                let mut aosoa = Self::new();
+                aosoa.extend(iter);
+                aosoa
+            }
+        }
+
+        impl #impl_generics Extend<#name #ty_generics> for #aosoa_name #ty_generics #default_where {
+            fn extend<I: IntoIterator<Item = #name #ty_generics>>(&mut self, iter: I) {
+                for item in iter {
+                    self.push(item);
+                }
+            }
+        }
+    }
+}
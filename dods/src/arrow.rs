@@ -0,0 +1,271 @@
+use crate::model::{Column, Soa, option_inner};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Error, Type};
+
+/// Inherent methods of `FooSoA` emitted here.
+pub(crate) const METHODS: &[&str] = &["arrow_schema", "to_record_batch"];
+
+/// Arrow support for `#[soa(arrow)]` structs, behind the `arrow` feature of this
+/// crate: `FooSoA::arrow_schema()`, `to_record_batch()` and `TryFrom<RecordBatch>`.
+/// Each column becomes an Arrow array named after it. Integers and floats map to
+/// primitive arrays, `bool` to `BooleanArray` and `String` to `StringArray`, and an
+/// `Option` of any of them to a nullable array of the same type.
+///
+/// The field types are checked here, so a struct with a field Arrow cannot hold fails
+/// to derive instead of failing at runtime. The generated code names `::arrow`
+/// directly, so the crate deriving `SoA` needs it as a dependency.
+pub(crate) fn expand(soa: &Soa) -> syn::Result<TokenStream> {
+    if !soa.attrs.arrow {
+        return Ok(quote! {});
+    }
+    if !cfg!(feature = "arrow") {
+        return Err(Error::new_spanned(
+            soa.name(),
+            "#[soa(arrow)] needs the `arrow` feature of dods",
+        ));
+    }
+
+    let mut kinds = Vec::new();
+    let mut errors: Option<Error> = None;
+    for column in &soa.columns {
+        match ArrowColumn::classify(column) {
+            Ok(kind) => kinds.push(kind),
+            Err(err) => match &mut errors {
+                Some(errors) => errors.combine(err),
+                None => errors = Some(err),
+            },
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    let name = soa.name();
+    let soa_name = &soa.names.soa;
+    let (impl_generics, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let col_names: Vec<_> = soa
+        .columns
+        .iter()
+        .map(|c| c.ident.unraw().to_string())
+        .collect();
+    let members: Vec<_> = soa.columns.iter().map(|c| &c.member).collect();
+    let data_types: Vec<_> = kinds.iter().map(|k| k.data_type()).collect();
+    let nullable: Vec<_> = kinds.iter().map(|k| k.nullable).collect();
+    let array_tys: Vec<_> = kinds.iter().map(|k| k.array_ty()).collect();
+    let to_arrays = soa.columns.iter().zip(&kinds).map(|(c, k)| k.to_array(c));
+    let owned_values: Vec<_> = kinds.iter().map(|k| k.owned_value()).collect();
+    let arrays: Vec<_> = (0..kinds.len())
+        .map(|i| format_ident!("array_{}", i))
+        .collect();
+    let iters: Vec<_> = (0..kinds.len())
+        .map(|i| format_ident!("iter_{}", i))
+        .collect();
+    let values: Vec<_> = (0..kinds.len())
+        .map(|i| format_ident!("value_{}", i))
+        .collect();
+    let null_checks = kinds
+        .iter()
+        .zip(&arrays)
+        .zip(&col_names)
+        .map(|((k, array), col_name)| {
+            if k.nullable {
+                return quote! {};
+            }
+            quote! {
+                if #array.null_count() > 0 {
+                    return Err(::arrow::error::ArrowError::SchemaError(format!(
+                        "column `{}` has nulls, but is not an Option",
+                        #col_name,
+                    )));
+                }
+            }
+        });
+
+    Ok(quote! {
+        impl #impl_generics #soa_name #ty_generics #where_clause {
+            /// The Arrow schema of `to_record_batch`, with one field per column, named
+            /// after it.
+            pub fn arrow_schema() -> ::arrow::datatypes::SchemaRef {
+                std::sync::Arc::new(::arrow::datatypes::Schema::new(vec![
+                    #( ::arrow::datatypes::Field::new(#col_names, #data_types, #nullable), )*
+                ]))
+            }
+
+            /// Copies each column into an Arrow array and returns them as a
+            /// `RecordBatch` with the schema of `arrow_schema`.
+            pub fn to_record_batch(&self) -> ::arrow::record_batch::RecordBatch {
+                let slice = self.as_slice();
+                let columns: Vec<::arrow::array::ArrayRef> = vec![
+                    #( std::sync::Arc::new(#to_arrays), )*
+                ];
+                ::arrow::record_batch::RecordBatch::try_new(Self::arrow_schema(), columns)
+                    .expect("the arrays are built to match the schema")
+            }
+        }
+
+        impl #impl_generics TryFrom<&::arrow::record_batch::RecordBatch> for #soa_name #ty_generics #where_clause {
+            type Error = ::arrow::error::ArrowError;
+
+            /// Copies the columns of `batch` out by name. Extra columns are ignored, and
+            /// a missing column, one of a different type, or nulls in a column that is
+            /// not an `Option` are reported as a `SchemaError`.
+            fn try_from(batch: &::arrow::record_batch::RecordBatch) -> Result<Self, Self::Error> {
+                use ::arrow::array::Array;
+
+                #(
+                    let #arrays = batch.column_by_name(#col_names).ok_or_else(|| {
+                        ::arrow::error::ArrowError::SchemaError(format!("missing column `{}`", #col_names))
+                    })?;
+                    let #arrays = #arrays.as_any().downcast_ref::<#array_tys>().ok_or_else(|| {
+                        ::arrow::error::ArrowError::SchemaError(format!(
+                            "column `{}` is {}, expected {}",
+                            #col_names,
+                            #arrays.data_type(),
+                            #data_types,
+                        ))
+                    })?;
+                    #null_checks
+                    let mut #iters = #arrays.iter().map(|value| #owned_values);
+                )*
+                let mut soa = Self::with_capacity(batch.num_rows());
+                while let ( #( Some(#values), )* ) = ( #( #iters.next(), )* ) {
+                    soa.push(#name { #( #members: #values, )* });
+                }
+                Ok(soa)
+            }
+        }
+
+        impl #impl_generics TryFrom<::arrow::record_batch::RecordBatch> for #soa_name #ty_generics #where_clause {
+            type Error = ::arrow::error::ArrowError;
+
+            fn try_from(batch: ::arrow::record_batch::RecordBatch) -> Result<Self, Self::Error> {
+                Self::try_from(&batch)
+            }
+        }
+    })
+}
+
+/// How a column maps to an Arrow array.
+struct ArrowColumn {
+    kind: Kind,
+    /// Whether the field is an `Option`, stored as a nullable array.
+    nullable: bool,
+    /// Whether the column is a `#[soa(nullable)]` column, whose views already give
+    /// `Option<&T>`.
+    nullable_column: bool,
+}
+
+enum Kind {
+    /// A primitive array, with the Arrow type (`Int32Type`) and data type (`Int32`).
+    Primitive(&'static str, &'static str),
+    Boolean,
+    Utf8,
+}
+
+impl ArrowColumn {
+    fn classify(column: &Column) -> syn::Result<Self> {
+        let unsupported = || {
+            Error::new_spanned(
+                column.ty,
+                "#[soa(arrow)] supports integer, float, `bool` and `String` fields, and `Option`s of them",
+            )
+        };
+        if column.nested.is_some() && !column.bitpacked && !column.nullable {
+            return Err(Error::new_spanned(
+                column.ty,
+                "#[soa(arrow)] does not support #[soa(nested)] columns",
+            ));
+        }
+        let (ty, nullable) = match option_inner(column.ty) {
+            Some(inner) => (inner, true),
+            None => (column.ty, false),
+        };
+        let Type::Path(path) = ty else {
+            return Err(unsupported());
+        };
+        let Some(ident) = path.path.get_ident().filter(|_| path.qself.is_none()) else {
+            return Err(unsupported());
+        };
+        let kind = match ident.to_string().as_str() {
+            "i8" => Kind::Primitive("Int8Type", "Int8"),
+            "i16" => Kind::Primitive("Int16Type", "Int16"),
+            "i32" => Kind::Primitive("Int32Type", "Int32"),
+            "i64" => Kind::Primitive("Int64Type", "Int64"),
+            "u8" => Kind::Primitive("UInt8Type", "UInt8"),
+            "u16" => Kind::Primitive("UInt16Type", "UInt16"),
+            "u32" => Kind::Primitive("UInt32Type", "UInt32"),
+            "u64" => Kind::Primitive("UInt64Type", "UInt64"),
+            "f32" => Kind::Primitive("Float32Type", "Float32"),
+            "f64" => Kind::Primitive("Float64Type", "Float64"),
+            "bool" => Kind::Boolean,
+            "String" => Kind::Utf8,
+            _ => return Err(unsupported()),
+        };
+        Ok(ArrowColumn {
+            kind,
+            nullable,
+            nullable_column: column.nullable,
+        })
+    }
+
+    /// `DataType::Int32`, `DataType::Boolean` or `DataType::Utf8`.
+    fn data_type(&self) -> TokenStream {
+        let variant = match self.kind {
+            Kind::Primitive(_, data_type) => format_ident!("{}", data_type),
+            Kind::Boolean => format_ident!("Boolean"),
+            Kind::Utf8 => format_ident!("Utf8"),
+        };
+        quote! { ::arrow::datatypes::DataType::#variant }
+    }
+
+    /// `PrimitiveArray<Int32Type>`, `BooleanArray` or `StringArray`.
+    fn array_ty(&self) -> TokenStream {
+        match self.kind {
+            Kind::Primitive(arrow_type, _) => {
+                let arrow_type = format_ident!("{}", arrow_type);
+                quote! { ::arrow::array::PrimitiveArray<::arrow::datatypes::#arrow_type> }
+            }
+            Kind::Boolean => quote! { ::arrow::array::BooleanArray },
+            Kind::Utf8 => quote! { ::arrow::array::StringArray },
+        }
+    }
+
+    /// Builds the array from `slice.column`, iterating over `&T`s or `Option<&T>`s.
+    fn to_array(&self, column: &Column) -> TokenStream {
+        let col = &column.ident;
+        let array_ty = self.array_ty();
+        if !self.nullable {
+            let values = quote! { slice.#col.iter() };
+            return match self.kind {
+                Kind::Primitive(..) => quote! { <#array_ty>::from_iter_values(#values.copied()) },
+                Kind::Boolean => {
+                    quote! { #values.map(|value| Some(*value)).collect::<#array_ty>() }
+                }
+                Kind::Utf8 => quote! { <#array_ty>::from_iter_values(#values) },
+            };
+        }
+        let values = if self.nullable_column {
+            quote! { slice.#col.iter() }
+        } else {
+            quote! { slice.#col.iter().map(Option::as_ref) }
+        };
+        match self.kind {
+            Kind::Primitive(..) | Kind::Boolean => {
+                quote! { #values.map(|value| value.copied()).collect::<#array_ty>() }
+            }
+            Kind::Utf8 => quote! { #values.collect::<#array_ty>() },
+        }
+    }
+
+    /// Converts an element of the array, `value: Option<T>`, into the field type.
+    fn owned_value(&self) -> TokenStream {
+        match (&self.kind, self.nullable) {
+            (Kind::Utf8, true) => quote! { value.map(String::from) },
+            (Kind::Utf8, false) => quote! { String::from(value.unwrap_or_default()) },
+            (_, true) => quote! { value },
+            (_, false) => quote! { value.unwrap_or_default() },
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use syn::meta::ParseNestedMeta;
+use syn::{Attribute, Error, Ident, LitInt, LitStr, Path, Token, Visibility};
+
+/// Options given through `#[soa(...)]` on the source struct.
+#[derive(Default)]
+pub(crate) struct ContainerAttrs {
+    /// Visibility of the generated types, overriding the source struct's.
+    pub vis: Option<Visibility>,
+    /// Name of the container, defaulting to `FooSoA`.
+    pub name: Option<Ident>,
+    pub ref_name: Option<Ident>,
+    pub ref_mut_name: Option<Ident>,
+    pub slice_name: Option<Ident>,
+    pub slice_mut_name: Option<Ident>,
+    pub ptr_name: Option<Ident>,
+    pub ptr_mut_name: Option<Ident>,
+    /// Traits listed in `derive(...)`, forwarded to the generated types.
+    pub derives: Vec<Path>,
+    /// Whether the columns of the container are private, behind slice accessors.
+    pub private_columns: bool,
+    /// How the container lays out its columns in memory.
+    pub storage: Storage,
+    /// Elements per chunk of the `FooAoSoA` container, which is only generated when set.
+    pub aosoa: Option<LitInt>,
+    /// Whether to generate rayon parallel iterators, which needs the `rayon` feature.
+    pub rayon: bool,
+    /// Shape of the serde impls, which are only generated when set and need the
+    /// `serde` feature.
+    pub serde: Option<SerdeShape>,
+    /// Whether to generate Arrow `RecordBatch` conversions, which needs the `arrow` feature.
+    pub arrow: bool,
+    /// Whether to generate `write_to` and the `FooMmap` reader, which needs the `mmap`
+    /// feature.
+    pub mmap: bool,
+    /// Whether to generate `read_csv` and `write_csv`, which needs the `csv` feature.
+    pub csv: bool,
+}
+
+/// Memory layout of the container, chosen with `#[soa(storage = "...")]`.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Storage {
+    /// One `Vec` per column.
+    #[default]
+    Vec,
+    /// Every column in one heap buffer with a shared length and capacity.
+    Single,
+}
+
+/// How `FooSoA` is serialized, chosen with `#[soa(serde = "...")]`, or the columns
+/// shape for a bare `#[soa(serde)]`.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SerdeShape {
+    /// A struct with one sequence per column, e.g. `{"x": [1, 2], "y": [3, 4]}`.
+    #[default]
+    Columns,
+    /// A sequence of elements, the same as a `Vec<Foo>`.
+    Rows,
+}
+
+impl ContainerAttrs {
+    pub fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("soa")) {
+            attr.parse_nested_meta(|meta| {
+                let slot = if meta.path.is_ident("vis") {
+                    let lit: LitStr = meta.value()?.parse()?;
+                    out.vis = Some(lit.parse()?);
+                    return Ok(());
+                } else if meta.path.is_ident("private_columns") {
+                    out.private_columns = true;
+                    return Ok(());
+                } else if meta.path.is_ident("rayon") {
+                    out.rayon = true;
+                    return Ok(());
+                } else if meta.path.is_ident("arrow") {
+                    out.arrow = true;
+                    return Ok(());
+                } else if meta.path.is_ident("mmap") {
+                    out.mmap = true;
+                    return Ok(());
+                } else if meta.path.is_ident("csv") {
+                    out.csv = true;
+                    return Ok(());
+                } else if meta.path.is_ident("storage") {
+                    let lit: LitStr = meta.value()?.parse()?;
+                    out.storage = match lit.value().as_str() {
+                        "vec" => Storage::Vec,
+                        "single" => Storage::Single,
+                        _ => {
+                            return Err(Error::new_spanned(
+                                lit,
+                                "unknown soa storage, expected \"vec\" or \"single\"",
+                            ));
+                        }
+                    };
+                    return Ok(());
+                } else if meta.path.is_ident("serde") {
+                    if !meta.input.peek(Token![=]) {
+                        out.serde = Some(SerdeShape::default());
+                        return Ok(());
+                    }
+                    let lit: LitStr = meta.value()?.parse()?;
+                    out.serde = Some(match lit.value().as_str() {
+                        "columns" => SerdeShape::Columns,
+                        "rows" => SerdeShape::Rows,
+                        _ => {
+                            return Err(Error::new_spanned(
+                                lit,
+                                "unknown soa serde shape, expected \"columns\" or \"rows\"",
+                            ));
+                        }
+                    });
+                    return Ok(());
+                } else if meta.path.is_ident("aosoa") {
+                    let lanes: LitInt = meta.value()?.parse()?;
+                    if lanes.base10_parse::<usize>()? == 0 {
+                        return Err(Error::new_spanned(
+                            lanes,
+                            "soa aosoa needs at least one element per chunk",
+                        ));
+                    }
+                    out.aosoa = Some(lanes);
+                    return Ok(());
+                } else if meta.path.is_ident("derive") {
+                    return meta.parse_nested_meta(|derive| {
+                        out.derives.push(derive.path);
+                        Ok(())
+                    });
+                } else if meta.path.is_ident("name") {
+                    &mut out.name
+                } else if meta.path.is_ident("ref_name") {
+                    &mut out.ref_name
+                } else if meta.path.is_ident("ref_mut_name") {
+                    &mut out.ref_mut_name
+                } else if meta.path.is_ident("slice_name") {
+                    &mut out.slice_name
+                } else if meta.path.is_ident("slice_mut_name") {
+                    &mut out.slice_mut_name
+                } else if meta.path.is_ident("ptr_name") {
+                    &mut out.ptr_name
+                } else if meta.path.is_ident("ptr_mut_name") {
+                    &mut out.ptr_mut_name
+                } else {
+                    return Err(meta.error("unknown soa container attribute"));
+                };
+                *slot = Some(parse_ident(&meta)?);
+                Ok(())
+            })?;
+        }
+        Ok(out)
+    }
+}
+
+/// Options given through `#[soa(...)]` on a field of the source struct.
+#[derive(Default)]
+pub(crate) struct FieldAttrs {
+    /// Name of the generated column, defaulting to the field name.
+    pub name: Option<Ident>,
+    /// Group the column belongs to, such as `hot` or `cold`.
+    pub group: Option<Ident>,
+    /// Whether the field's type derives SoA itself and is stored as its own SoA.
+    pub nested: bool,
+    /// Whether a `bool` field is stored one bit per element.
+    pub bitpacked: bool,
+    /// Whether an `Option<T>` field is stored as a validity bitmap and a `Vec<T>`.
+    pub nullable: bool,
+    /// Name of the column in CSV files, defaulting to the column name.
+    pub rename: Option<String>,
+}
+
+impl FieldAttrs {
+    pub fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut out = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("soa")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    out.name = Some(parse_ident(&meta)?);
+                    Ok(())
+                } else if meta.path.is_ident("group") {
+                    out.group = Some(parse_ident(&meta)?);
+                    Ok(())
+                } else if meta.path.is_ident("nested") {
+                    out.nested = true;
+                    Ok(())
+                } else if meta.path.is_ident("bitpacked") {
+                    out.bitpacked = true;
+                    Ok(())
+                } else if meta.path.is_ident("nullable") {
+                    out.nullable = true;
+                    Ok(())
+                } else if meta.path.is_ident("rename") {
+                    let lit: LitStr = meta.value()?.parse()?;
+                    out.rename = Some(lit.value());
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown soa field attribute"))
+                }
+            })?;
+        }
+        Ok(out)
+    }
+}
+
+/// Parses `key = "Ident"`.
+fn parse_ident(meta: &ParseNestedMeta) -> syn::Result<Ident> {
+    let lit: LitStr = meta.value()?.parse()?;
+    lit.parse()
+}
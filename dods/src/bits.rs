@@ -0,0 +1,887 @@
+use crate::model::Soa;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+/// Inherent methods of `FooSoA` emitted here: `count_where_foo` for each bitpacked
+/// column `foo`.
+pub(crate) fn methods(soa: &Soa) -> Vec<String> {
+    soa.columns
+        .iter()
+        .filter(|c| c.bitpacked)
+        .map(|c| format!("count_where_{}", c.ident))
+        .collect()
+}
+
+/// The bitset types behind `#[soa(bitpacked)]` columns and the validity bitmaps of
+/// `#[soa(nullable)]` columns, generated once per struct as
+/// `FooBitSoA`, `FooBitSlice`, `FooBitSliceMut` and so on. They mirror the `Vec<bool>`
+/// and `&[bool]` API the other generators use on a column, so a bitpacked column is
+/// handled like a nested column of type `FooBit`.
+///
+/// Bits are kept in `AtomicUsize` words and written with relaxed atomics through the
+/// views. Two halves of a split mutable slice can share a word, and atomics let them
+/// both write to it without any unsafe code. The owning `FooBitSoA` writes through
+/// `get_mut` instead, and `FooBitRef` is a plain `&bool` to a promoted constant.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    if !soa.columns.iter().any(|c| c.bitpacked || c.nullable) {
+        return quote! {};
+    }
+    let vis = &soa.vis;
+    let soa_name = &soa.names.soa;
+    let (impl_generics, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let bit = &soa.names.bit;
+    let bits = format_ident!("{}SoA", bit);
+    let bit_ref = format_ident!("{}Ref", bit);
+    let bit_ref_mut = format_ident!("{}RefMut", bit);
+    let bit_slice = format_ident!("{}Slice", bit);
+    let bit_slice_mut = format_ident!("{}SliceMut", bit);
+    let bit_ptr = format_ident!("{}Ptr", bit);
+    let bit_ptr_mut = format_ident!("{}PtrMut", bit);
+    let bit_iter = format_ident!("{}Iter", bit);
+    let bit_iter_mut = format_ident!("{}IterMut", bit);
+    let bit_into_iter = format_ident!("{}IntoIter", bit);
+
+    let counts = soa.columns.iter().filter(|c| c.bitpacked).map(|c| {
+        let col = &c.ident;
+        let method = format_ident!("count_where_{}", col);
+        let doc = format!("Returns the number of elements whose `{}` is true.", col);
+        quote! {
+            #[doc = #doc]
+            pub fn #method(&self) -> usize {
+                self.as_slice().#col.count_ones()
+            }
+        }
+    });
+
+    quote! {
+        /// Bitset column of the SoA, holding a `bool` per element in one bit.
+        #vis struct #bits {
+            words: Vec<core::sync::atomic::AtomicUsize>,
+            len: usize,
+        }
+
+        /// A bit of a bitset column. Bits have no address, so this refers to a
+        /// constant `true` or `false`.
+        #vis type #bit_ref<'a> = &'a bool;
+
+        /// Raw const pointer to the words of a bitset column.
+        #vis type #bit_ptr = *const usize;
+
+        /// Raw mut pointer to the words of a bitset column.
+        #vis type #bit_ptr_mut = *mut usize;
+
+        /// Mutable reference to a bit of a bitset column, read with `get` or `*` and
+        /// written with `set`.
+        #vis struct #bit_ref_mut<'a> {
+            word: &'a core::sync::atomic::AtomicUsize,
+            mask: usize,
+        }
+
+        /// Immutable view over a range of a bitset column.
+        #[derive(Clone, Copy)]
+        #vis struct #bit_slice<'a> {
+            words: &'a [core::sync::atomic::AtomicUsize],
+            /// Bit offset of the first element into `words[0]`.
+            start: usize,
+            len: usize,
+        }
+
+        /// Mutable view over a range of a bitset column.
+        #vis struct #bit_slice_mut<'a> {
+            words: &'a [core::sync::atomic::AtomicUsize],
+            start: usize,
+            len: usize,
+        }
+
+        /// Iterator over the bits of a bitset column.
+        #[derive(Clone)]
+        #vis struct #bit_iter<'a> {
+            slice: #bit_slice<'a>,
+            front: usize,
+            back: usize,
+        }
+
+        /// Iterator over mutable references to the bits of a bitset column.
+        #vis struct #bit_iter_mut<'a> {
+            slice: #bit_slice_mut<'a>,
+            front: usize,
+            back: usize,
+        }
+
+        /// Owning iterator over the bits of a bitset column.
+        #vis struct #bit_into_iter {
+            bits: #bits,
+            front: usize,
+            back: usize,
+        }
+
+        impl #bits {
+            const BITS: usize = usize::BITS as usize;
+
+            /// Creates a new, empty bitset.
+            pub fn new() -> Self {
+                Self { words: Vec::new(), len: 0 }
+            }
+
+            /// Creates an empty bitset with capacity for at least `capacity` bits.
+            pub fn with_capacity(capacity: usize) -> Self {
+                Self {
+                    words: Vec::with_capacity(capacity.div_ceil(Self::BITS)),
+                    len: 0,
+                }
+            }
+
+            /// Returns the number of bits.
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns true when the bitset has no bits.
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// Returns the number of bits the bitset can hold without reallocating.
+            pub fn capacity(&self) -> usize {
+                self.words.capacity() * Self::BITS
+            }
+
+            /// Returns the number of bits that are set.
+            pub fn count_ones(&self) -> usize {
+                self.as_slice().count_ones()
+            }
+
+            fn bit(&self, index: usize) -> bool {
+                let word = self.words[index / Self::BITS].load(core::sync::atomic::Ordering::Relaxed);
+                word >> (index % Self::BITS) & 1 != 0
+            }
+
+            fn set_bit(&mut self, index: usize, value: bool) {
+                let word = self.words[index / Self::BITS].get_mut();
+                let mask = 1 << (index % Self::BITS);
+                if value {
+                    *word |= mask;
+                } else {
+                    *word &= !mask;
+                }
+            }
+
+            /// Appends a bit.
+            pub fn push(&mut self, value: bool) {
+                if self.len % Self::BITS == 0 {
+                    self.words.push(core::sync::atomic::AtomicUsize::new(0));
+                }
+                self.len += 1;
+                self.set_bit(self.len - 1, value);
+            }
+
+            /// Inserts a bit at `index`, shifting later bits up.
+            ///
+            /// # Panics
+            /// Panics if `index > len`.
+            #[track_caller]
+            pub fn insert(&mut self, index: usize, value: bool) {
+                let len = self.len;
+                if index > len {
+                    panic!("insertion index (is {index}) should be <= len (is {len})");
+                }
+                self.push(false);
+                // Shift a word at a time, each word carrying its top bit into the next.
+                // Bits past `len` are clear, so the carry out of the last word is too.
+                let (first, offset) = (index / Self::BITS, index % Self::BITS);
+                let low = (1usize << offset) - 1;
+                let word = self.words[first].get_mut();
+                let mut carry = *word >> (Self::BITS - 1);
+                *word = (*word & low) | ((*word & !low) << 1) | ((value as usize) << offset);
+                for word in &mut self.words[first + 1..] {
+                    let word = word.get_mut();
+                    let next = *word >> (Self::BITS - 1);
+                    *word = (*word << 1) | carry;
+                    carry = next;
+                }
+            }
+
+            /// Replaces the bit at `index`, returning the old one.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn replace(&mut self, index: usize, value: bool) -> bool {
+                let old = *self.as_slice().index(index);
+                self.set_bit(index, value);
+                old
+            }
+
+            /// Removes and returns the bit at `index`, shifting later bits down.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn remove(&mut self, index: usize) -> bool {
+                let len = self.len;
+                if index >= len {
+                    panic!("removal index (is {index}) should be < len (is {len})");
+                }
+                // Shift a word at a time, each word taking the bottom bit of the next as
+                // its top bit. The last word takes a clear bit, keeping bits past `len` clear.
+                let (first, offset) = (index / Self::BITS, index % Self::BITS);
+                let low = (1usize << offset) - 1;
+                let removed = *self.words[first].get_mut() >> offset & 1 != 0;
+                let words = self.words.len();
+                for i in first..words {
+                    let carry = match self.words.get_mut(i + 1) {
+                        Some(next) => *next.get_mut() & 1,
+                        None => 0,
+                    };
+                    let keep = if i == first { low } else { 0 };
+                    let word = self.words[i].get_mut();
+                    *word = (*word & keep) | ((*word >> 1) & !keep) | (carry << (Self::BITS - 1));
+                }
+                self.len -= 1;
+                if self.len % Self::BITS == 0 {
+                    self.words.pop();
+                }
+                removed
+            }
+
+            /// Removes the bit at `index` and returns it, moving the last bit into
+            /// its place.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn swap_remove(&mut self, index: usize) -> bool {
+                let len = self.len;
+                if index >= len {
+                    panic!("swap_remove index (is {index}) should be < len (is {len})");
+                }
+                let last = self.pop().unwrap();
+                if index == len - 1 {
+                    last
+                } else {
+                    self.replace(index, last)
+                }
+            }
+
+            /// Removes the last bit and returns it, or `None` if the bitset is empty.
+            pub fn pop(&mut self) -> Option<bool> {
+                if self.len == 0 {
+                    return None;
+                }
+                self.len -= 1;
+                let value = self.bit(self.len);
+                // Bits past `len` are kept clear, so counting never needs to mask them.
+                self.set_bit(self.len, false);
+                if self.len % Self::BITS == 0 {
+                    self.words.pop();
+                }
+                Some(value)
+            }
+
+            /// Reserves capacity for at least `additional` more bits.
+            pub fn reserve(&mut self, additional: usize) {
+                let bits = self.len.checked_add(additional).expect("capacity overflow");
+                let words = bits.div_ceil(Self::BITS);
+                self.words.reserve(words - self.words.len());
+            }
+
+            /// Reserves capacity for exactly `additional` more bits, rounded up to a word.
+            pub fn reserve_exact(&mut self, additional: usize) {
+                let bits = self.len.checked_add(additional).expect("capacity overflow");
+                let words = bits.div_ceil(Self::BITS);
+                self.words.reserve_exact(words - self.words.len());
+            }
+
+            /// Shrinks the capacity as much as possible.
+            pub fn shrink_to_fit(&mut self) {
+                self.words.shrink_to_fit();
+            }
+
+            /// Shortens the bitset to `len` bits, doing nothing if it is already shorter.
+            pub fn truncate(&mut self, len: usize) {
+                if len >= self.len {
+                    return;
+                }
+                self.len = len;
+                self.words.truncate(len.div_ceil(Self::BITS));
+                if len % Self::BITS != 0 {
+                    *self.words[len / Self::BITS].get_mut() &= (1 << (len % Self::BITS)) - 1;
+                }
+            }
+
+            /// Clears all bits.
+            pub fn clear(&mut self) {
+                self.words.clear();
+                self.len = 0;
+            }
+
+            /// Moves all bits of `other` to the end of `self`, leaving `other` empty.
+            pub fn append(&mut self, other: &mut Self) {
+                self.reserve(other.len);
+                for i in 0..other.len {
+                    self.push(other.bit(i));
+                }
+                other.clear();
+            }
+
+            /// Splits the bitset at `at`, returning the bits from `at` onwards.
+            ///
+            /// # Panics
+            /// Panics if `at > len`.
+            #[track_caller]
+            pub fn split_off(&mut self, at: usize) -> Self {
+                let len = self.len;
+                if at > len {
+                    panic!("`at` split index (is {at}) should be <= len (is {len})");
+                }
+                let mut tail = Self::with_capacity(len - at);
+                for i in at..len {
+                    tail.push(self.bit(i));
+                }
+                self.truncate(at);
+                tail
+            }
+
+            /// Returns a view over all bits.
+            pub fn as_slice(&self) -> #bit_slice<'_> {
+                #bit_slice { words: &self.words, start: 0, len: self.len }
+            }
+
+            /// Returns a mutable view over all bits.
+            pub fn as_mut_slice(&mut self) -> #bit_slice_mut<'_> {
+                #bit_slice_mut { words: &self.words, start: 0, len: self.len }
+            }
+
+            /// Returns a raw pointer to the words.
+            pub fn as_ptr(&self) -> #bit_ptr {
+                self.words.as_ptr().cast()
+            }
+
+            /// Returns a raw mutable pointer to the words.
+            pub fn as_mut_ptr(&mut self) -> #bit_ptr_mut {
+                self.words.as_mut_ptr().cast()
+            }
+        }
+
+        impl core::default::Default for #bits {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl core::clone::Clone for #bits {
+            fn clone(&self) -> Self {
+                Self {
+                    words: self
+                        .words
+                        .iter()
+                        .map(|word| {
+                            core::sync::atomic::AtomicUsize::new(
+                                word.load(core::sync::atomic::Ordering::Relaxed),
+                            )
+                        })
+                        .collect(),
+                    len: self.len,
+                }
+            }
+        }
+
+        impl IntoIterator for #bits {
+            type Item = bool;
+            type IntoIter = #bit_into_iter;
+
+            fn into_iter(self) -> Self::IntoIter {
+                let back = self.len;
+                #bit_into_iter { bits: self, front: 0, back }
+            }
+        }
+
+        impl<'a> #bit_ref_mut<'a> {
+            /// Returns the bit.
+            pub fn get(&self) -> bool {
+                self.word.load(core::sync::atomic::Ordering::Relaxed) & self.mask != 0
+            }
+
+            /// Sets the bit to `value`.
+            pub fn set(&mut self, value: bool) {
+                if value {
+                    self.word.fetch_or(self.mask, core::sync::atomic::Ordering::Relaxed);
+                } else {
+                    self.word.fetch_and(!self.mask, core::sync::atomic::Ordering::Relaxed);
+                }
+            }
+        }
+
+        impl<'a> core::ops::Deref for #bit_ref_mut<'a> {
+            type Target = bool;
+
+            fn deref(&self) -> &bool {
+                if self.get() { &true } else { &false }
+            }
+        }
+
+        impl<'a> core::fmt::Debug for #bit_ref_mut<'a> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Debug::fmt(&self.get(), f)
+            }
+        }
+
+        impl<'a> PartialEq for #bit_ref_mut<'a> {
+            fn eq(&self, other: &Self) -> bool {
+                self.get() == other.get()
+            }
+        }
+
+        impl<'a> Eq for #bit_ref_mut<'a> {}
+
+        impl<'a> core::hash::Hash for #bit_ref_mut<'a> {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                self.get().hash(state);
+            }
+        }
+
+        impl<'a> #bit_slice<'a> {
+            /// Returns the number of bits in the view.
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns true when the view has no bits.
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// Returns a copy of the view.
+            pub fn as_slice(&self) -> Self {
+                *self
+            }
+
+            fn bit(&self, index: usize) -> bool {
+                let at = self.start + index;
+                let word = self.words[at / #bits::BITS].load(core::sync::atomic::Ordering::Relaxed);
+                word >> (at % #bits::BITS) & 1 != 0
+            }
+
+            /// Returns the bit at `index`, or `None` if out of bounds.
+            pub fn get(&self, index: usize) -> Option<&'a bool> {
+                if index >= self.len { return None; }
+                Some(if self.bit(index) { &true } else { &false })
+            }
+
+            /// Returns the bit at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn index(&self, index: usize) -> &'a bool {
+                let len = self.len;
+                self.get(index).unwrap_or_else(|| {
+                    panic!("index out of bounds: the len is {len} but the index is {index}")
+                })
+            }
+
+            /// Returns the bits in `range`.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn slice(&self, range: core::ops::Range<usize>) -> Self {
+                let core::ops::Range { start, end } = range;
+                if start > end || end > self.len {
+                    panic!("range {start}..{end} out of bounds for bits of length {}", self.len);
+                }
+                let at = self.start + start;
+                Self {
+                    words: &self.words[at / #bits::BITS..],
+                    start: at % #bits::BITS,
+                    len: end - start,
+                }
+            }
+
+            /// Splits the view into the bits before `mid` and the bits from `mid` on.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn split_at(&self, mid: usize) -> (Self, Self) {
+                (self.slice(0..mid), self.slice(mid..self.len))
+            }
+
+            /// Returns an iterator over views of `chunk_size` bits, the last one shorter
+            /// if `chunk_size` does not divide the length.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            #[track_caller]
+            pub fn chunks(&self, chunk_size: usize) -> impl DoubleEndedIterator<Item = Self> + ExactSizeIterator + use<'a> {
+                assert!(chunk_size != 0, "chunk size must be non-zero");
+                let this = *self;
+                (0..this.len.div_ceil(chunk_size)).map(move |i| {
+                    this.slice(i * chunk_size..this.len.min((i + 1) * chunk_size))
+                })
+            }
+
+            /// Returns an iterator over the bits.
+            pub fn iter(&self) -> #bit_iter<'a> {
+                #bit_iter { slice: *self, front: 0, back: self.len }
+            }
+
+            /// The words covering the view with the bits outside of it cleared, each
+            /// paired with the position of its bit 0 counted from `words[0]`.
+            fn words(&self) -> impl Iterator<Item = (usize, usize)> + use<'a> {
+                let this = *self;
+                let (start, end) = (this.start, this.start + this.len);
+                (start / #bits::BITS..end.div_ceil(#bits::BITS)).map(move |w| {
+                    let mut word = this.words[w].load(core::sync::atomic::Ordering::Relaxed);
+                    let first = w * #bits::BITS;
+                    if start > first {
+                        word &= usize::MAX << (start - first);
+                    }
+                    if end < first + #bits::BITS {
+                        word &= !(usize::MAX << (end - first));
+                    }
+                    (first, word)
+                })
+            }
+
+            /// Returns the number of bits that are set, a word at a time.
+            pub fn count_ones(&self) -> usize {
+                self.words().map(|(_, word)| word.count_ones() as usize).sum()
+            }
+
+            /// Returns an iterator over the indices of the set bits, which skips a
+            /// word of clear bits at a time.
+            pub fn iter_ones(&self) -> impl Iterator<Item = usize> + use<'a> {
+                let start = self.start;
+                self.words().flat_map(move |(first, mut word)| {
+                    core::iter::from_fn(move || {
+                        if word == 0 {
+                            return None;
+                        }
+                        let bit = word.trailing_zeros() as usize;
+                        word &= word - 1;
+                        Some(first + bit - start)
+                    })
+                })
+            }
+        }
+
+        impl<'a> core::fmt::Debug for #bit_slice<'a> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_list().entries(self.iter()).finish()
+            }
+        }
+
+        impl<'a> PartialEq for #bit_slice<'a> {
+            fn eq(&self, other: &Self) -> bool {
+                self.len == other.len && self.iter().eq(other.iter())
+            }
+        }
+
+        impl<'a> Eq for #bit_slice<'a> {}
+
+        impl<'a> core::hash::Hash for #bit_slice<'a> {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                self.len.hash(state);
+                self.iter().for_each(|bit| bit.hash(state));
+            }
+        }
+
+        impl<'a> IntoIterator for #bit_slice<'a> {
+            type Item = &'a bool;
+            type IntoIter = #bit_iter<'a>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl<'a> #bit_slice_mut<'a> {
+            /// Returns the number of bits in the view.
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns true when the view has no bits.
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// Returns an immutable view over the same bits.
+            pub fn as_slice(&self) -> #bit_slice<'_> {
+                #bit_slice { words: self.words, start: self.start, len: self.len }
+            }
+
+            /// Reborrows the view for a shorter lifetime.
+            pub fn reborrow(&mut self) -> #bit_slice_mut<'_> {
+                #bit_slice_mut { words: self.words, start: self.start, len: self.len }
+            }
+
+            fn from_slice(slice: #bit_slice<'a>) -> Self {
+                Self { words: slice.words, start: slice.start, len: slice.len }
+            }
+
+            /// Turns the view into an immutable view over the same bits.
+            pub fn into_slice(self) -> #bit_slice<'a> {
+                #bit_slice { words: self.words, start: self.start, len: self.len }
+            }
+
+            /// Returns the bit at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn index(&self, index: usize) -> &bool {
+                self.as_slice().index(index)
+            }
+
+            /// Returns a mutable reference to the bit at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn index_mut(&mut self, index: usize) -> #bit_ref_mut<'_> {
+                self.reborrow().into_index_mut(index)
+            }
+
+            /// Turns the view into a mutable reference to the bit at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn into_index_mut(self, index: usize) -> #bit_ref_mut<'a> {
+                self.as_slice().index(index);
+                let at = self.start + index;
+                #bit_ref_mut {
+                    word: &self.words[at / #bits::BITS],
+                    mask: 1 << (at % #bits::BITS),
+                }
+            }
+
+            /// Returns the bits in `range`.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn slice(&self, range: core::ops::Range<usize>) -> #bit_slice<'_> {
+                self.as_slice().slice(range)
+            }
+
+            /// Returns the bits in `range` as a mutable view.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn slice_mut(&mut self, range: core::ops::Range<usize>) -> #bit_slice_mut<'_> {
+                self.reborrow().into_slice_mut(range)
+            }
+
+            /// Turns the view into a mutable view of the bits in `range`.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn into_slice_mut(self, range: core::ops::Range<usize>) -> Self {
+                Self::from_slice(self.into_slice().slice(range))
+            }
+
+            /// Splits the view into the bits before `mid` and the bits from `mid` on.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn split_at(&self, mid: usize) -> (#bit_slice<'_>, #bit_slice<'_>) {
+                self.as_slice().split_at(mid)
+            }
+
+            /// Splits the view into mutable views of the bits before `mid` and the bits
+            /// from `mid` on.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn split_at_mut(&mut self, mid: usize) -> (#bit_slice_mut<'_>, #bit_slice_mut<'_>) {
+                let (head, tail) = self.as_slice().split_at(mid);
+                (#bit_slice_mut::from_slice(head), #bit_slice_mut::from_slice(tail))
+            }
+
+            /// Turns the view into mutable views of the bits before `mid` and the bits
+            /// from `mid` on, keeping the full lifetime of the view.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn into_split_at_mut(self, mid: usize) -> (Self, Self) {
+                let (head, tail) = self.into_slice().split_at(mid);
+                (Self::from_slice(head), Self::from_slice(tail))
+            }
+
+            /// Returns an iterator over views of `chunk_size` bits.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            #[track_caller]
+            pub fn chunks(&self, chunk_size: usize) -> impl DoubleEndedIterator<Item = #bit_slice<'_>> + ExactSizeIterator {
+                self.as_slice().chunks(chunk_size)
+            }
+
+            /// Returns an iterator over mutable views of `chunk_size` bits.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            #[track_caller]
+            pub fn chunks_mut(&mut self, chunk_size: usize) -> impl DoubleEndedIterator<Item = #bit_slice_mut<'_>> + ExactSizeIterator {
+                self.as_slice().chunks(chunk_size).map(#bit_slice_mut::from_slice)
+            }
+
+            /// Swaps the bits at `a` and `b`.
+            ///
+            /// # Panics
+            /// Panics if `a` or `b` is out of bounds.
+            #[track_caller]
+            pub fn swap(&mut self, a: usize, b: usize) {
+                let (x, y) = (*self.index(a), *self.index(b));
+                self.index_mut(a).set(y);
+                self.index_mut(b).set(x);
+            }
+
+            /// Returns an iterator over the bits.
+            pub fn iter(&self) -> #bit_iter<'_> {
+                self.as_slice().iter()
+            }
+
+            /// Returns an iterator over mutable references to the bits.
+            pub fn iter_mut(&mut self) -> #bit_iter_mut<'_> {
+                self.reborrow().into_iter()
+            }
+
+            /// Returns the number of bits that are set.
+            pub fn count_ones(&self) -> usize {
+                self.as_slice().count_ones()
+            }
+        }
+
+        impl<'a> core::fmt::Debug for #bit_slice_mut<'a> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Debug::fmt(&self.as_slice(), f)
+            }
+        }
+
+        impl<'a> PartialEq for #bit_slice_mut<'a> {
+            fn eq(&self, other: &Self) -> bool {
+                self.as_slice() == other.as_slice()
+            }
+        }
+
+        impl<'a> Eq for #bit_slice_mut<'a> {}
+
+        impl<'a> core::hash::Hash for #bit_slice_mut<'a> {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                self.as_slice().hash(state);
+            }
+        }
+
+        impl<'a> IntoIterator for #bit_slice_mut<'a> {
+            type Item = #bit_ref_mut<'a>;
+            type IntoIter = #bit_iter_mut<'a>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                let back = self.len;
+                #bit_iter_mut { slice: self, front: 0, back }
+            }
+        }
+
+        impl<'a> Iterator for #bit_iter<'a> {
+            type Item = &'a bool;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.front == self.back { return None; }
+                self.front += 1;
+                self.slice.get(self.front - 1)
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.back - self.front, Some(self.back - self.front))
+            }
+        }
+
+        impl<'a> DoubleEndedIterator for #bit_iter<'a> {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                if self.front == self.back { return None; }
+                self.back -= 1;
+                self.slice.get(self.back)
+            }
+        }
+
+        impl<'a> ExactSizeIterator for #bit_iter<'a> {}
+
+        impl<'a> core::iter::FusedIterator for #bit_iter<'a> {}
+
+        impl<'a> Iterator for #bit_iter_mut<'a> {
+            type Item = #bit_ref_mut<'a>;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.front == self.back { return None; }
+                self.front += 1;
+                let slice = #bit_slice_mut { words: self.slice.words, ..self.slice };
+                Some(slice.into_index_mut(self.front - 1))
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.back - self.front, Some(self.back - self.front))
+            }
+        }
+
+        impl<'a> DoubleEndedIterator for #bit_iter_mut<'a> {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                if self.front == self.back { return None; }
+                self.back -= 1;
+                let slice = #bit_slice_mut { words: self.slice.words, ..self.slice };
+                Some(slice.into_index_mut(self.back))
+            }
+        }
+
+        impl<'a> ExactSizeIterator for #bit_iter_mut<'a> {}
+
+        impl<'a> core::iter::FusedIterator for #bit_iter_mut<'a> {}
+
+        impl Iterator for #bit_into_iter {
+            type Item = bool;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.front == self.back { return None; }
+                self.front += 1;
+                Some(self.bits.bit(self.front - 1))
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.back - self.front, Some(self.back - self.front))
+            }
+        }
+
+        impl DoubleEndedIterator for #bit_into_iter {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                if self.front == self.back { return None; }
+                self.back -= 1;
+                Some(self.bits.bit(self.back))
+            }
+        }
+
+        impl ExactSizeIterator for #bit_into_iter {}
+
+        impl core::iter::FusedIterator for #bit_into_iter {}
+
+        impl #impl_generics #soa_name #ty_generics #where_clause {
+            #( #counts )*
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::attr::Storage;
+use crate::model::{Column, Soa, option_inner};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::Error;
+
+/// Inherent methods of `FooSoA` emitted here.
+pub(crate) const METHODS: &[&str] = &["read_csv", "write_csv"];
+
+/// CSV support for `#[soa(csv)]` structs, behind the `csv` feature of this crate:
+/// `FooSoA::read_csv` and `write_csv`, with one CSV column per field, headed by the
+/// column name or its `#[soa(rename = "...")]`.
+///
+/// Each cell is parsed with `FromStr` and written with `Display`. An `Option` field,
+/// nullable or not, reads an empty cell as `None` and writes `None` as one. Reading
+/// parses every column straight into its own column container, which become the
+/// columns of the SoA as they are with `storage = "vec"`, and reports the row and
+/// column of a cell that does not parse in `FooCsvError`.
+///
+/// The generated code names `::csv` directly, so the crate deriving `SoA` needs it as
+/// a dependency. Nested columns are rejected, since a nested field has no single cell
+/// to read or write.
+pub(crate) fn expand(soa: &Soa) -> syn::Result<TokenStream> {
+    if !soa.attrs.csv {
+        return Ok(quote! {});
+    }
+    if !cfg!(feature = "csv") {
+        return Err(Error::new_spanned(
+            soa.name(),
+            "#[soa(csv)] needs the `csv` feature of dods",
+        ));
+    }
+    let mut errors: Option<Error> = None;
+    for column in &soa.columns {
+        if column.nested.is_some() && !column.bitpacked && !column.nullable {
+            let err = Error::new_spanned(
+                column.ty,
+                "#[soa(csv)] does not support #[soa(nested)] columns",
+            );
+            match &mut errors {
+                Some(errors) => errors.combine(err),
+                None => errors = Some(err),
+            }
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+    let name = soa.name();
+    let vis = &soa.vis;
+    let soa_name = &soa.names.soa;
+    let error_name = &soa.names.csv_error;
+    let (impl_generics, ty_generics, _) = soa.generics().split_for_impl();
+    let lt = &soa.lt;
+    let members: Vec<_> = soa.columns.iter().map(|c| &c.member).collect();
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let vec_tys: Vec<_> = soa.columns.iter().map(|c| c.vec_ty()).collect();
+    let headers: Vec<_> = soa.columns.iter().map(|c| &c.header).collect();
+    let cell_tys: Vec<_> = soa.columns.iter().map(cell_ty).collect();
+    let count = soa.columns.len();
+    let positions: Vec<_> = (0..count)
+        .map(|i| format_ident!("position_{}", i))
+        .collect();
+    let values: Vec<_> = (0..count).map(|i| format_ident!("column_{}", i)).collect();
+    // The vec storage holds exactly these containers, and the single buffer is filled
+    // from them an element at a time.
+    let build = match soa.attrs.storage {
+        Storage::Vec => quote! { Ok(Self { #( #cols: #values, )* }) },
+        Storage::Single => {
+            let iters: Vec<_> = (0..count).map(|i| format_ident!("iter_{}", i)).collect();
+            let first = &values[0];
+            quote! {
+                let mut soa = Self::with_capacity(#first.len());
+                #( let mut #iters = #values.into_iter(); )*
+                while let ( #( Some(#values), )* ) = ( #( #iters.next(), )* ) {
+                    soa.push(#name { #( #members: #values, )* });
+                }
+                Ok(soa)
+            }
+        }
+    };
+
+    let read_where = soa.where_with(quote! {
+        #(
+            for<#lt> #cell_tys: core::str::FromStr,
+            for<#lt> <#cell_tys as core::str::FromStr>::Err: core::fmt::Display,
+        )*
+    });
+    let write_where = soa.where_columns(quote! { core::fmt::Display }, cell_ty);
+
+    let parses = soa
+        .columns
+        .iter()
+        .zip(&cell_tys)
+        .zip(&headers)
+        .map(|((c, ty), header)| {
+            let parse = quote! {
+                cell.parse::<#ty>().map_err(|err| #error_name::Parse {
+                    row,
+                    column: #header,
+                    message: err.to_string(),
+                })?
+            };
+            match option_inner(c.ty) {
+                Some(_) => quote! { if cell.is_empty() { None } else { Some(#parse) } },
+                None => parse,
+            }
+        });
+    let writes = soa.columns.iter().map(|c| {
+        let col = &c.ident;
+        match option_inner(c.ty) {
+            Some(_) => quote! {
+                match element.#col {
+                    Some(value) => csv_writer.write_field(value.to_string())?,
+                    None => csv_writer.write_field("")?,
+                }
+            },
+            None => quote! { csv_writer.write_field(element.#col.to_string())?; },
+        }
+    });
+
+    Ok(quote! {
+        /// Error of `FooSoA::read_csv`.
+        #[derive(Debug)]
+        #vis enum #error_name {
+            /// The CSV could not be read.
+            Csv(::csv::Error),
+            /// The header row has no column for a field.
+            MissingColumn(&'static str),
+            /// A cell did not parse as the type of its field. `row` is the line of the
+            /// record in the input, counting the header row as 1.
+            Parse {
+                row: u64,
+                column: &'static str,
+                message: String,
+            },
+        }
+
+        impl core::fmt::Display for #error_name {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match self {
+                    Self::Csv(err) => core::fmt::Display::fmt(err, f),
+                    Self::MissingColumn(column) => write!(f, "the CSV has no `{}` column", column),
+                    Self::Parse { row, column, message } => {
+                        write!(f, "row {}, column `{}`: {}", row, column, message)
+                    }
+                }
+            }
+        }
+
+        impl std::error::Error for #error_name {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                match self {
+                    Self::Csv(err) => Some(err),
+                    _ => None,
+                }
+            }
+        }
+
+        impl From<::csv::Error> for #error_name {
+            fn from(err: ::csv::Error) -> Self {
+                Self::Csv(err)
+            }
+        }
+
+        impl #impl_generics #soa_name #ty_generics #read_where {
+            /// Reads a CSV with a header row, picking each field's column by its
+            /// header. Other columns are ignored, and an empty cell reads as `None`
+            /// for an `Option` field.
+            pub fn read_csv(reader: impl std::io::Read) -> Result<Self, #error_name> {
+                let mut csv_reader = ::csv::Reader::from_reader(reader);
+                let header = csv_reader.headers()?;
+                #(
+                    let #positions = header
+                        .iter()
+                        .position(|header| header == #headers)
+                        .ok_or(#error_name::MissingColumn(#headers))?;
+                )*
+                #( let mut #values = <#vec_tys>::new(); )*
+                let mut record = ::csv::StringRecord::new();
+                while csv_reader.read_record(&mut record)? {
+                    let row = record.position().map_or(0, |position| position.line());
+                    #(
+                        let cell = &record[#positions];
+                        #values.push(#parses);
+                    )*
+                }
+                #build
+            }
+        }
+
+        impl #impl_generics #soa_name #ty_generics #write_where {
+            /// Writes the SoA as a CSV with a header row, one column per field. `None`
+            /// is written as an empty cell.
+            pub fn write_csv(&self, writer: impl std::io::Write) -> Result<(), ::csv::Error> {
+                let mut csv_writer = ::csv::Writer::from_writer(writer);
+                csv_writer.write_record([ #( #headers, )* ])?;
+                for element in self.iter() {
+                    #( #writes )*
+                    csv_writer.write_record(None::<&[u8]>)?;
+                }
+                csv_writer.flush()?;
+                Ok(())
+            }
+        }
+    })
+}
+
+/// The type of a cell of the column: the field type, or `T` for an `Option<T>`.
+fn cell_ty(column: &Column) -> TokenStream {
+    let ty = option_inner(column.ty).unwrap_or(column.ty);
+    quote! { #ty }
+}
@@ -0,0 +1,672 @@
+use crate::attr::{ContainerAttrs, FieldAttrs, Storage};
+use crate::derive_attr;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{DataEnum, DeriveInput, Error, Fields, Ident, Member, Variant, Visibility, parse_quote};
+
+/// Inherent methods of `FooSoA` for an enum, which a variant accessor must not shadow.
+const ENUM_METHODS: &[&str] = &[
+    "new",
+    "with_capacity",
+    "len",
+    "is_empty",
+    "clear",
+    "tags",
+    "push",
+    "pop",
+    "get",
+    "index",
+    "get_mut",
+    "index_mut",
+    "iter",
+    "iter_mut",
+];
+
+/// A variant of the source enum with fields, stored in its own `FooVariantSoA`.
+struct VariantColumns<'i> {
+    variant: &'i Variant,
+    /// Name of the accessor and of the field holding the variant's SoA, e.g. `click`.
+    ident: Ident,
+    /// The struct generated from the variant's fields, e.g. `EventClick`.
+    item: Ident,
+    soa: Ident,
+    ref_: Ident,
+    ref_mut: Ident,
+    slice: Ident,
+    slice_mut: Ident,
+    iter: Ident,
+    iter_mut: Ident,
+    /// The fields in a pattern or constructor, binding them to `x` or `_0`, so the
+    /// variant and its struct can be taken apart and rebuilt with the same tokens.
+    fields: Vec<TokenStream>,
+}
+
+/// A tagged-union SoA for an enum: `FooSoA` keeps a tag column and, for each element,
+/// its position in the SoA of its variant. Each variant with fields becomes a
+/// `FooVariant` struct with a regular SoA of its own, so the elements of one variant
+/// are stored densely and can be iterated without looking at the others.
+pub(crate) fn expand(input: &DeriveInput, data: &DataEnum) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() || input.generics.where_clause.is_some() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "DODS SoA does not support generic enums",
+        ));
+    }
+    let attrs = ContainerAttrs::parse(&input.attrs)?;
+    let unsupported = [
+        &attrs.slice_name,
+        &attrs.slice_mut_name,
+        &attrs.ptr_name,
+        &attrs.ptr_mut_name,
+    ];
+    if let Some(ident) = unsupported.into_iter().flatten().next() {
+        return Err(Error::new_spanned(
+            ident,
+            "enums only take the `name`, `ref_name` and `ref_mut_name` soa names",
+        ));
+    }
+    if let Some(lanes) = &attrs.aosoa {
+        return Err(Error::new_spanned(
+            lanes,
+            "DODS SoA does not support aosoa for enums",
+        ));
+    }
+    if attrs.rayon {
+        return Err(Error::new_spanned(
+            name,
+            "DODS SoA does not support rayon for enums",
+        ));
+    }
+    if attrs.serde.is_some() {
+        return Err(Error::new_spanned(
+            name,
+            "DODS SoA does not support serde for enums",
+        ));
+    }
+    if attrs.arrow {
+        return Err(Error::new_spanned(
+            name,
+            "DODS SoA does not support arrow for enums",
+        ));
+    }
+    if attrs.mmap {
+        return Err(Error::new_spanned(
+            name,
+            "DODS SoA does not support mmap for enums",
+        ));
+    }
+    if attrs.csv {
+        return Err(Error::new_spanned(
+            name,
+            "DODS SoA does not support csv for enums",
+        ));
+    }
+
+    let vis = attrs.vis.clone().unwrap_or_else(|| input.vis.clone());
+    let soa_name = attrs
+        .name
+        .clone()
+        .unwrap_or_else(|| format_ident!("{}SoA", name));
+    let ref_name = attrs
+        .ref_name
+        .clone()
+        .unwrap_or_else(|| format_ident!("{}Ref", name));
+    let ref_mut_name = attrs
+        .ref_mut_name
+        .clone()
+        .unwrap_or_else(|| format_ident!("{}RefMut", name));
+    let tag_name = format_ident!("{}Tag", name);
+    let iter_name = format_ident!("{}Iter", name);
+    let iter_mut_name = format_ident!("{}IterMut", name);
+
+    let variants = data
+        .variants
+        .iter()
+        .filter(|v| !v.fields.is_empty())
+        .map(|v| variant_columns(name, &soa_name, v))
+        .collect::<syn::Result<Vec<_>>>()?;
+    if variants.is_empty() {
+        return Err(Error::new_spanned(
+            input,
+            "DODS SoA requires at least one enum variant with fields",
+        ));
+    }
+    let variant_items = variants
+        .iter()
+        .map(|v| variant_item(v, &vis, &attrs))
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    // Forwarded derives follow the views of a struct SoA: the references have no
+    // `Default`, and the mutable references cannot be duplicated.
+    let view_derives = derive_attr(&attrs.derives, &["Default"]);
+    let view_mut_derives = derive_attr(&attrs.derives, &["Clone", "Copy", "Default"]);
+
+    let tags: Vec<_> = data.variants.iter().map(|v| &v.ident).collect();
+    let view_variants = data.variants.iter().map(|v| {
+        let ident = &v.ident;
+        match variants.iter().find(|c| c.variant.ident == *ident) {
+            Some(c) => {
+                let (ref_, ref_mut) = (&c.ref_, &c.ref_mut);
+                (
+                    quote! { #ident(#ref_<'a>) },
+                    quote! { #ident(#ref_mut<'a>) },
+                )
+            }
+            None => (quote! { #ident }, quote! { #ident }),
+        }
+    });
+    let (ref_variants, ref_mut_variants): (Vec<_>, Vec<_>) = view_variants.unzip();
+
+    // Taking an element apart and putting it back together. Unit variants and variants
+    // without fields only live in the tag column, so their index is always 0.
+    let push_arms = data.variants.iter().map(|v| {
+        let ident = &v.ident;
+        match variants.iter().find(|c| c.variant.ident == *ident) {
+            Some(c) => {
+                let (col, item) = (&c.ident, &c.item);
+                let fields = &c.fields;
+                quote! {
+                    #name::#ident { #( #fields, )* } => {
+                        self.tags.push(#tag_name::#ident);
+                        self.indices.push(self.#col.len());
+                        self.#col.push(#item { #( #fields, )* });
+                    }
+                }
+            }
+            None => quote! {
+                #name::#ident { .. } => {
+                    self.tags.push(#tag_name::#ident);
+                    self.indices.push(0);
+                }
+            },
+        }
+    });
+    let pop_arms = data.variants.iter().map(|v| {
+        let ident = &v.ident;
+        match variants.iter().find(|c| c.variant.ident == *ident) {
+            Some(c) => {
+                let (col, item) = (&c.ident, &c.item);
+                let fields = &c.fields;
+                quote! {
+                    #tag_name::#ident => {
+                        let #item { #( #fields, )* } = self.#col.pop().unwrap();
+                        #name::#ident { #( #fields, )* }
+                    }
+                }
+            }
+            None => quote! { #tag_name::#ident => #name::#ident {}, },
+        }
+    });
+    let view_arms = |view: &Ident, method: TokenStream| {
+        let arms = data.variants.iter().map(|v| {
+            let ident = &v.ident;
+            match variants.iter().find(|c| c.variant.ident == *ident) {
+                Some(c) => {
+                    let col = &c.ident;
+                    quote! { #tag_name::#ident => #view::#ident(self.#col.#method(index)), }
+                }
+                None => quote! { #tag_name::#ident => #view::#ident, },
+            }
+        });
+        quote! { #( #arms )* }
+    };
+    let get_arms = view_arms(&ref_name, quote! { index });
+    let get_mut_arms = view_arms(&ref_mut_name, quote! { index_mut });
+    let next_arms = |view: &Ident, next: TokenStream| {
+        let arms = data.variants.iter().map(|v| {
+            let ident = &v.ident;
+            match variants.iter().find(|c| c.variant.ident == *ident) {
+                Some(c) => {
+                    let col = &c.ident;
+                    quote! { #tag_name::#ident => #view::#ident(self.#col.#next().unwrap()), }
+                }
+                None => quote! { #tag_name::#ident => #view::#ident, },
+            }
+        });
+        quote! { #( #arms )* }
+    };
+    let iter_next = next_arms(&ref_name, quote! { next });
+    let iter_next_back = next_arms(&ref_name, quote! { next_back });
+    let iter_mut_next = next_arms(&ref_mut_name, quote! { next });
+    let iter_mut_next_back = next_arms(&ref_mut_name, quote! { next_back });
+
+    let cols: Vec<_> = variants.iter().map(|c| &c.ident).collect();
+    let soas: Vec<_> = variants.iter().map(|c| &c.soa).collect();
+    let iters: Vec<_> = variants.iter().map(|c| &c.iter).collect();
+    let iters_mut: Vec<_> = variants.iter().map(|c| &c.iter_mut).collect();
+    let accessors = variants.iter().map(|c| {
+        let VariantColumns {
+            variant,
+            ident,
+            slice,
+            slice_mut,
+            iter,
+            iter_mut,
+            ..
+        } = c;
+        let ident_mut = format_ident!("{}_mut", ident);
+        let iter_method = format_ident!("iter_{}", ident);
+        let iter_mut_method = format_ident!("iter_{}_mut", ident);
+        let doc = format!("Returns the columns of the `{}` elements.", variant.ident);
+        let doc_mut = format!(
+            "Returns the columns of the `{}` elements as mutable slices.",
+            variant.ident
+        );
+        let iter_doc = format!(
+            "Returns an iterator over references to each `{}` element, in order.",
+            variant.ident
+        );
+        let iter_mut_doc = format!(
+            "Returns an iterator over mutable references to each `{}` element, in order.",
+            variant.ident
+        );
+        quote! {
+            #[doc = #doc]
+            pub fn #ident(&self) -> #slice<'_> {
+                self.#ident.as_slice()
+            }
+
+            #[doc = #doc_mut]
+            pub fn #ident_mut(&mut self) -> #slice_mut<'_> {
+                self.#ident.as_mut_slice()
+            }
+
+            #[doc = #iter_doc]
+            pub fn #iter_method(&self) -> #iter<'_> {
+                self.#ident.iter()
+            }
+
+            #[doc = #iter_mut_doc]
+            pub fn #iter_mut_method(&mut self) -> #iter_mut<'_> {
+                self.#ident.iter_mut()
+            }
+        }
+    });
+
+    let ref_doc = format!("References to a single element of a `{}`.", soa_name);
+    let ref_mut_doc = format!(
+        "Mutable references to a single element of a `{}`.",
+        soa_name
+    );
+
+    Ok(quote! {
+        #( #variant_items )*
+
+        /// The variant of each element of the SoA.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        #vis enum #tag_name {
+            #( #tags, )*
+        }
+
+        #[doc = #ref_doc]
+        #view_derives
+        #vis enum #ref_name<'a> {
+            #( #ref_variants, )*
+        }
+
+        #[doc = #ref_mut_doc]
+        #view_mut_derives
+        #vis enum #ref_mut_name<'a> {
+            #( #ref_mut_variants, )*
+        }
+
+        /// Struct-of-Arrays container generated for the source enum.
+        #vis struct #soa_name {
+            tags: Vec<#tag_name>,
+            /// Position of each element in the SoA of its variant.
+            indices: Vec<usize>,
+            #( #cols: #soas, )*
+        }
+
+        impl #soa_name {
+            /// Creates a new, empty SoA.
+            pub fn new() -> Self {
+                Self {
+                    tags: Vec::new(),
+                    indices: Vec::new(),
+                    #( #cols: #soas::new(), )*
+                }
+            }
+
+            /// Creates an empty SoA whose tag and index columns have capacity for at
+            /// least `capacity` elements. The variant columns grow as elements arrive.
+            pub fn with_capacity(capacity: usize) -> Self {
+                Self {
+                    tags: Vec::with_capacity(capacity),
+                    indices: Vec::with_capacity(capacity),
+                    #( #cols: #soas::new(), )*
+                }
+            }
+
+            /// Returns the number of elements in the SoA.
+            pub fn len(&self) -> usize {
+                self.tags.len()
+            }
+
+            /// Returns true when the SoA has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.tags.is_empty()
+            }
+
+            /// Clears all elements from the SoA.
+            pub fn clear(&mut self) {
+                self.tags.clear();
+                self.indices.clear();
+                #( self.#cols.clear(); )*
+            }
+
+            /// Returns the variant of each element.
+            pub fn tags(&self) -> &[#tag_name] {
+                &self.tags
+            }
+
+            /// Appends an element to the SoA of its variant.
+            pub fn push(&mut self, element: #name) {
+                match element {
+                    #( #push_arms )*
+                }
+            }
+
+            /// Removes the last element and returns it, or `None` if the SoA is empty.
+            pub fn pop(&mut self) -> Option<#name> {
+                let tag = self.tags.pop()?;
+                self.indices.pop();
+                Some(match tag {
+                    #( #pop_arms )*
+                })
+            }
+
+            /// Returns references to the element at `index`, or `None` if out of bounds.
+            pub fn get(&self, index: usize) -> Option<#ref_name<'_>> {
+                let tag = *self.tags.get(index)?;
+                let index = self.indices[index];
+                Some(match tag {
+                    #get_arms
+                })
+            }
+
+            /// Returns references to the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn index(&self, index: usize) -> #ref_name<'_> {
+                let len = self.len();
+                self.get(index).unwrap_or_else(|| {
+                    panic!("index out of bounds: the len is {len} but the index is {index}")
+                })
+            }
+
+            /// Returns mutable references to the element at `index`, or `None` if out of bounds.
+            pub fn get_mut(&mut self, index: usize) -> Option<#ref_mut_name<'_>> {
+                let tag = *self.tags.get(index)?;
+                let index = self.indices[index];
+                Some(match tag {
+                    #get_mut_arms
+                })
+            }
+
+            /// Returns mutable references to the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn index_mut(&mut self, index: usize) -> #ref_mut_name<'_> {
+                let len = self.len();
+                self.get_mut(index).unwrap_or_else(|| {
+                    panic!("index out of bounds: the len is {len} but the index is {index}")
+                })
+            }
+
+            /// Returns an iterator over references to each element.
+            pub fn iter(&self) -> #iter_name<'_> {
+                #iter_name {
+                    tags: self.tags.iter(),
+                    #( #cols: self.#cols.iter(), )*
+                }
+            }
+
+            /// Returns an iterator over mutable references to each element.
+            pub fn iter_mut(&mut self) -> #iter_mut_name<'_> {
+                #iter_mut_name {
+                    tags: self.tags.iter(),
+                    #( #cols: self.#cols.iter_mut(), )*
+                }
+            }
+
+            #( #accessors )*
+        }
+
+        impl core::default::Default for #soa_name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl FromIterator<#name> for #soa_name {
+            fn from_iter<I: IntoIterator<Item = #name>>(iter: I) -> Self {
+                let mut soa = Self::new();
+                soa.extend(iter);
+                soa
+            }
+        }
+
+        impl Extend<#name> for #soa_name {
+            fn extend<I: IntoIterator<Item = #name>>(&mut self, iter: I) {
+                for element in iter {
+                    self.push(element);
+                }
+            }
+        }
+
+        /// Iterator over references to the elements of the SoA. It walks the tag column
+        /// and takes each element from the iterator of its variant.
+        #vis struct #iter_name<'a> {
+            tags: core::slice::Iter<'a, #tag_name>,
+            #( #cols: #iters<'a>, )*
+        }
+
+        impl<'a> Iterator for #iter_name<'a> {
+            type Item = #ref_name<'a>;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                Some(match *self.tags.next()? {
+                    #iter_next
+                })
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.tags.size_hint()
+            }
+        }
+
+        impl<'a> DoubleEndedIterator for #iter_name<'a> {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                Some(match *self.tags.next_back()? {
+                    #iter_next_back
+                })
+            }
+        }
+
+        impl<'a> ExactSizeIterator for #iter_name<'a> {}
+
+        impl<'a> core::iter::FusedIterator for #iter_name<'a> {}
+
+        /// Iterator over mutable references to the elements of the SoA.
+        #vis struct #iter_mut_name<'a> {
+            tags: core::slice::Iter<'a, #tag_name>,
+            #( #cols: #iters_mut<'a>, )*
+        }
+
+        impl<'a> Iterator for #iter_mut_name<'a> {
+            type Item = #ref_mut_name<'a>;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                Some(match *self.tags.next()? {
+                    #iter_mut_next
+                })
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.tags.size_hint()
+            }
+        }
+
+        impl<'a> DoubleEndedIterator for #iter_mut_name<'a> {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                Some(match *self.tags.next_back()? {
+                    #iter_mut_next_back
+                })
+            }
+        }
+
+        impl<'a> ExactSizeIterator for #iter_mut_name<'a> {}
+
+        impl<'a> core::iter::FusedIterator for #iter_mut_name<'a> {}
+
+        impl<'a> IntoIterator for &'a #soa_name {
+            type Item = #ref_name<'a>;
+            type IntoIter = #iter_name<'a>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl<'a> IntoIterator for &'a mut #soa_name {
+            type Item = #ref_mut_name<'a>;
+            type IntoIter = #iter_mut_name<'a>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter_mut()
+            }
+        }
+    })
+}
+
+/// Resolves the names generated for a variant with fields, e.g. `EventClick` and
+/// its `EventClickSoA`, stored under `click`.
+fn variant_columns<'i>(
+    name: &Ident,
+    soa_name: &Ident,
+    variant: &'i Variant,
+) -> syn::Result<VariantColumns<'i>> {
+    let attrs = FieldAttrs::parse(&variant.attrs)?;
+    if attrs.group.is_some() || attrs.nested || attrs.bitpacked || attrs.nullable {
+        return Err(Error::new_spanned(
+            &variant.ident,
+            "enum variants only take #[soa(name = \"...\")]",
+        ));
+    }
+    let ident = attrs
+        .name
+        .unwrap_or_else(|| snake_case(&variant.ident.to_string()));
+    for method in [
+        ident.to_string(),
+        format!("{}_mut", ident),
+        format!("iter_{}", ident),
+        format!("iter_{}_mut", ident),
+    ] {
+        if ENUM_METHODS.contains(&method.as_str()) {
+            return Err(Error::new_spanned(
+                &variant.ident,
+                format!(
+                    "variant accessor `{}` clashes with the `{}` method of the same name; \
+                     rename the variant's columns with #[soa(name = \"...\")]",
+                    method, soa_name,
+                ),
+            ));
+        }
+    }
+    let item = format_ident!("{}{}", name, variant.ident);
+    let fields = variant
+        .fields
+        .members()
+        .map(|member| match &member {
+            Member::Named(ident) => quote! { #ident },
+            Member::Unnamed(index) => {
+                let binding = format_ident!("_{}", index.index);
+                quote! { #member: #binding }
+            }
+        })
+        .collect();
+    Ok(VariantColumns {
+        variant,
+        ident,
+        soa: format_ident!("{}SoA", item),
+        ref_: format_ident!("{}Ref", item),
+        ref_mut: format_ident!("{}RefMut", item),
+        slice: format_ident!("{}Slice", item),
+        slice_mut: format_ident!("{}SliceMut", item),
+        iter: format_ident!("{}Iter", item),
+        iter_mut: format_ident!("{}IterMut", item),
+        item,
+        fields,
+    })
+}
+
+/// The struct holding the fields of a variant, e.g. `struct EventClick { x: i32 }`
+/// for `Event::Click { x: i32 }`, together with its SoA. The fields get the
+/// visibility of the generated types, since the fields of an enum are as visible
+/// as the enum itself.
+fn variant_item(
+    variant: &VariantColumns,
+    vis: &Visibility,
+    attrs: &ContainerAttrs,
+) -> syn::Result<TokenStream> {
+    let item = &variant.item;
+    let mut fields = variant.variant.fields.clone();
+    for field in fields.iter_mut() {
+        field.vis = vis.clone();
+        field
+            .attrs
+            .retain(|a| a.path().is_ident("soa") || a.path().is_ident("doc"));
+    }
+    let semi = matches!(fields, Fields::Unnamed(_)).then(|| quote! { ; });
+
+    // Only the layout options are forwarded, so the variants never get the rayon,
+    // serde, arrow, mmap or csv output that the enum itself rejects.
+    let derives = &attrs.derives;
+    let mut forwarded = Vec::new();
+    if !derives.is_empty() {
+        forwarded.push(quote! { derive( #( #derives ),* ) });
+    }
+    if attrs.private_columns {
+        forwarded.push(quote! { private_columns });
+    }
+    if attrs.storage == Storage::Single {
+        forwarded.push(quote! { storage = "single" });
+    }
+    let doc = format!("The fields of `{}`.", variant.variant.ident);
+    let input: DeriveInput = parse_quote! {
+        #[soa( #( #forwarded ),* )]
+        #vis struct #item #fields #semi
+    };
+    let soa = crate::expand(&input)?;
+    let derive = derive_attr(derives, &[]);
+    Ok(quote! {
+        #[doc = #doc]
+        #derive
+        #vis struct #item #fields #semi
+
+        #soa
+    })
+}
+
+/// `KeyPress` becomes `key_press`.
+fn snake_case(name: &str) -> Ident {
+    let mut snake = String::new();
+    for (i, ch) in name.chars().enumerate() {
+        if ch.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(ch.to_lowercase());
+    }
+    // Keywords such as `move` or `type` cannot name a method.
+    syn::parse_str(&snake).unwrap_or_else(|_| format_ident!("{}_", snake))
+}
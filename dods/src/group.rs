@@ -0,0 +1,170 @@
+use crate::iter::{iterator_impls, zip_expr, zip_type};
+use crate::model::{Group, Soa};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+/// Inherent methods of `FooSoA` emitted here: `iter_foo` and `iter_foo_mut` for each
+/// group `foo`.
+pub(crate) fn methods(soa: &Soa) -> Vec<String> {
+    soa.groups()
+        .iter()
+        .flat_map(|g| [format!("iter_{}", g.name), format!("iter_{}_mut", g.name)])
+        .collect()
+}
+
+/// Views and iterators for each `#[soa(group = "...")]`, so a loop over the hot
+/// fields of every element only touches the hot columns. `FooRef` and the other
+/// views keep covering every field.
+///
+/// A group is a view over the columns of `FooSoA`, not a sub-container of its own.
+/// Each column is already a separate allocation, so a group's iterators read exactly
+/// what a hot sub-container would hold. The columns also keep a single length to
+/// stay in sync on.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    soa.groups()
+        .iter()
+        .map(|group| expand_group(soa, group))
+        .collect()
+}
+
+fn expand_group(soa: &Soa, group: &Group) -> TokenStream {
+    let name = soa.name();
+    let vis = &soa.vis;
+    let lt = &soa.lt;
+    let soa_name = &soa.names.soa;
+    let slice_name = &soa.names.slice;
+    let slice_mut_name = &soa.names.slice_mut;
+    let (impl_generics, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let view_generics = &soa.view_generics;
+    let (_, view_ty_generics, _) = view_generics.split_for_impl();
+    let Group {
+        name: group_name,
+        columns,
+        ref_: ref_name,
+        ref_mut: ref_mut_name,
+        iter: iter_name,
+        iter_mut: iter_mut_name,
+    } = group;
+    let group_iter = soa.view_ty(iter_name);
+    let group_iter_mut = soa.view_ty(iter_mut_name);
+
+    let iter_method = format_ident!("iter_{}", group_name);
+    let iter_mut_method = format_ident!("iter_{}_mut", group_name);
+
+    let cols: Vec<_> = columns.iter().map(|c| &c.ident).collect();
+    let ref_tys: Vec<_> = columns.iter().map(|c| c.ref_ty(lt)).collect();
+    let ref_mut_tys: Vec<_> = columns.iter().map(|c| c.ref_mut_ty(lt)).collect();
+    let field_vis: Vec<_> = columns.iter().map(|c| c.vis).collect();
+
+    // A group may leave some of the source generics unused, so the views hold on to
+    // all of them through a marker.
+    let (marker, marker_init) = if soa.generics().params.is_empty() {
+        (quote! {}, quote! {})
+    } else {
+        (
+            quote! { _marker: core::marker::PhantomData<&#lt #name #ty_generics>, },
+            quote! { _marker: core::marker::PhantomData, },
+        )
+    };
+
+    let columns = columns.iter().copied();
+    let iter_ty = zip_type(columns.clone(), |c| c.iter_ty(lt));
+    let iter_mut_ty = zip_type(columns.clone(), |c| c.iter_mut_ty(lt));
+    let slice_iter = zip_expr(columns.clone(), |col| quote! { self.#col.iter() });
+    let slice_iter_mut = zip_expr(columns.clone(), |col| quote! { self.#col.iter_mut() });
+    let soa_iter_mut = zip_expr(columns.clone(), |col| quote! { slice.#col.into_iter() });
+    let iter_impls = iterator_impls(
+        columns.clone(),
+        view_generics,
+        iter_name,
+        quote! { #ref_name #view_ty_generics },
+        quote! { #ref_name { #( #cols, )* #marker_init } },
+    );
+    let iter_mut_impls = iterator_impls(
+        columns,
+        view_generics,
+        iter_mut_name,
+        quote! { #ref_mut_name #view_ty_generics },
+        quote! { #ref_mut_name { #( #cols, )* #marker_init } },
+    );
+
+    let ref_doc = format!(
+        "Immutable references to the `{}` fields of a single element of the SoA.",
+        group_name
+    );
+    let ref_mut_doc = format!(
+        "Mutable references to the `{}` fields of a single element of the SoA.",
+        group_name
+    );
+    let iter_doc = format!(
+        "Returns an iterator over references to the `{}` fields of each element.",
+        group_name
+    );
+    let iter_mut_doc = format!(
+        "Returns an iterator over mutable references to the `{}` fields of each element.",
+        group_name
+    );
+
+    quote! {
+        #[doc = #ref_doc]
+        #[allow(dead_code)]
+        #vis struct #ref_name #view_generics #where_clause {
+            #( #field_vis #cols: #ref_tys, )*
+            #marker
+        }
+
+        #[doc = #ref_mut_doc]
+        #[allow(dead_code)]
+        #vis struct #ref_mut_name #view_generics #where_clause {
+            #( #field_vis #cols: #ref_mut_tys, )*
+            #marker
+        }
+
+        #[doc = #iter_doc]
+        #vis struct #iter_name #view_generics #where_clause {
+            inner: #iter_ty,
+            #marker
+        }
+
+        #[doc = #iter_mut_doc]
+        #vis struct #iter_mut_name #view_generics #where_clause {
+            inner: #iter_mut_ty,
+            #marker
+        }
+
+        #iter_impls
+        #iter_mut_impls
+
+        impl #impl_generics #soa_name #ty_generics #where_clause {
+            #[doc = #iter_doc]
+            pub fn #iter_method(&self) -> #group_iter {
+                self.as_slice().#iter_method()
+            }
+
+            #[doc = #iter_mut_doc]
+            pub fn #iter_mut_method(&mut self) -> #group_iter_mut {
+                let slice = self.as_mut_slice();
+                #iter_mut_name { inner: #soa_iter_mut, #marker_init }
+            }
+        }
+
+        impl #view_generics #slice_name #view_ty_generics #where_clause {
+            #[doc = #iter_doc]
+            pub fn #iter_method(&self) -> #iter_name #view_ty_generics {
+                #iter_name { inner: #slice_iter, #marker_init }
+            }
+        }
+
+        impl #view_generics #slice_mut_name #view_ty_generics #where_clause {
+            #[doc = #iter_doc]
+            pub fn #iter_method(&self) -> #group_iter {
+                self.as_slice().#iter_method()
+            }
+
+            #[doc = #iter_mut_doc]
+            pub fn #iter_mut_method(&mut self) -> #group_iter_mut {
+                #iter_mut_name { inner: #slice_iter_mut, #marker_init }
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::model::{Column, Soa};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Generics, Ident};
+
+/// Borrowing iterator types over the container plus the `IntoIterator`, `FromIterator`
+/// and `Extend` impls that make `FooSoA` collect and loop like `Vec<Foo>`. The owning
+/// iterator depends on the storage, so it comes from `storage`.
+///
+/// Each iterator wraps the zip of its per-column iterators, so the standard
+/// library's specializations for zipped slice iterators still apply.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    let name = soa.name();
+    let vis = &soa.vis;
+    let lt = &soa.lt;
+    let soa_name = &soa.names.soa;
+    let ref_name = &soa.names.ref_;
+    let ref_mut_name = &soa.names.ref_mut;
+    let iter_name = &soa.names.iter;
+    let iter_mut_name = &soa.names.iter_mut;
+    let (impl_generics, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let (_, view_ty_generics, _) = soa.view_generics.split_for_impl();
+    let view_generics = &soa.view_generics;
+
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let iter_ty = zip_type(&soa.columns, |c| c.iter_ty(lt));
+    let iter_mut_ty = zip_type(&soa.columns, |c| c.iter_mut_ty(lt));
+
+    let iter_impls = iterator_impls(
+        &soa.columns,
+        view_generics,
+        iter_name,
+        quote! { #ref_name #view_ty_generics },
+        quote! { #ref_name { #( #cols, )* } },
+    );
+    let iter_mut_impls = iterator_impls(
+        &soa.columns,
+        view_generics,
+        iter_mut_name,
+        quote! { #ref_mut_name #view_ty_generics },
+        quote! { #ref_mut_name { #( #cols, )* } },
+    );
+
+    quote! {
+        /// Iterator over immutable references to the elements of the SoA.
+        #vis struct #iter_name #view_generics #where_clause {
+            inner: #iter_ty,
+        }
+
+        /// Iterator over mutable references to the elements of the SoA.
+        #vis struct #iter_mut_name #view_generics #where_clause {
+            inner: #iter_mut_ty,
+        }
+
+        #iter_impls
+        #iter_mut_impls
+
+        impl #view_generics IntoIterator for &#lt #soa_name #ty_generics #where_clause {
+            type Item = #ref_name #view_ty_generics;
+            type IntoIter = #iter_name #view_ty_generics;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl #view_generics IntoIterator for &#lt mut #soa_name #ty_generics #where_clause {
+            type Item = #ref_mut_name #view_ty_generics;
+            type IntoIter = #iter_mut_name #view_ty_generics;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter_mut()
+            }
+        }
+
+        impl #impl_generics FromIterator<#name #ty_generics> for #soa_name #ty_generics #where_clause {
+            fn from_iter<I: IntoIterator<Item = #name #ty_generics>>(iter: I) -> Self {
+                let mut soa = Self::new();
+                soa.extend(iter);
+                soa
+            }
+        }
+
+        impl #impl_generics Extend<#name #ty_generics> for #soa_name #ty_generics #where_clause {
+            /// Reserves room for the iterator's lower size bound in every column
+            /// up front, then pushes each element.
+            fn extend<I: IntoIterator<Item = #name #ty_generics>>(&mut self, iter: I) {
+                let iter = iter.into_iter();
+                self.reserve(iter.size_hint().0);
+                for item in iter {
+                    self.push(item);
+                }
+            }
+        }
+    }
+}
+
+/// `Iterator`, `DoubleEndedIterator`, `ExactSizeIterator` and `FusedIterator` for one
+/// of the generated iterators, mapping each zipped tuple to `item` via `build`.
+pub(crate) fn iterator_impls<'c, 'i: 'c>(
+    columns: impl IntoIterator<Item = &'c Column<'i>>,
+    generics: &Generics,
+    iter_name: &Ident,
+    item: TokenStream,
+    build: TokenStream,
+) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let pattern = zip_pattern(columns);
+
+    quote! {
+        impl #impl_generics Iterator for #iter_name #ty_generics #where_clause {
+            type Item = #item;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                self.inner.next().map(|#pattern| #build)
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+
+            #[inline]
+            fn nth(&mut self, n: usize) -> Option<Self::Item> {
+                self.inner.nth(n).map(|#pattern| #build)
+            }
+        }
+
+        impl #impl_generics DoubleEndedIterator for #iter_name #ty_generics #where_clause {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.inner.next_back().map(|#pattern| #build)
+            }
+        }
+
+        impl #impl_generics ExactSizeIterator for #iter_name #ty_generics #where_clause {}
+
+        impl #impl_generics core::iter::FusedIterator for #iter_name #ty_generics #where_clause {}
+    }
+}
+
+/// Zips one iterator per column, e.g. `a.iter().zip(b.iter()).zip(c.iter())`.
+pub(crate) fn zip_expr<'c, 'i: 'c>(
+    columns: impl IntoIterator<Item = &'c Column<'i>>,
+    column: impl Fn(&Ident) -> TokenStream,
+) -> TokenStream {
+    let mut parts = columns.into_iter().map(|c| column(&c.ident));
+    let first = parts.next().unwrap();
+    parts.fold(first, |acc, part| quote! { #acc.zip(#part) })
+}
+
+/// The type of a `zip_expr`, e.g. `Zip<Zip<A, B>, C>`.
+pub(crate) fn zip_type<'c, 'i: 'c>(
+    columns: impl IntoIterator<Item = &'c Column<'i>>,
+    column: impl Fn(&Column) -> TokenStream,
+) -> TokenStream {
+    let mut parts = columns.into_iter().map(column);
+    let first = parts.next().unwrap();
+    parts.fold(first, |acc, part| quote! { core::iter::Zip<#acc, #part> })
+}
+
+/// Destructures an item of a `zip_expr` into bindings named after the columns,
+/// e.g. `((a, b), c)`.
+pub(crate) fn zip_pattern<'c, 'i: 'c>(
+    columns: impl IntoIterator<Item = &'c Column<'i>>,
+) -> TokenStream {
+    let mut cols = columns.into_iter().map(|c| &c.ident);
+    let first = cols.next().unwrap();
+    cols.fold(quote! { #first }, |acc, col| quote! { (#acc, #col) })
+}
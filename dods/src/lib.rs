@@ -1,320 +1,347 @@
+mod aosoa;
+mod arrow;
+mod attr;
+mod bits;
+mod csv;
+mod enums;
+mod group;
+mod iter;
+mod mmap;
+mod model;
+mod nullable;
+mod par;
+mod project;
+mod retain;
+mod serde;
+mod slice;
+mod storage;
+mod traits;
+
+use model::{Names, Soa};
 use proc_macro::TokenStream;
+use proc_macro2::Span;
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, Data, DeriveInput, Fields};
+use syn::{Data, DeriveInput, Error, Lifetime, Path, parse_macro_input};
 
-/// Derive a Struct-of-Arrays (SoA) container for a named-field struct.
+/// Derive a Struct-of-Arrays (SoA) container for a struct with named or tuple fields.
 ///
 /// This generates `FooSoA`, `FooRef`, `FooRefMut`, `FooSlice`, `FooSliceMut`,
-/// `FooPtr`, and `FooPtrMut` for a `Foo` struct, plus a Vec-like API on `FooSoA`.
-#[proc_macro_derive(SoA)]
+/// `FooPtr`, and `FooPtrMut` for a `Foo` struct, plus a Vec-like API on `FooSoA`,
+/// and the iterators `FooIter`, `FooIterMut` and `FooIntoIter` behind `FromIterator`,
+/// `Extend` and `IntoIterator` for `FooSoA`, `&FooSoA` and `&mut FooSoA`.
+///
+/// The generated types share the visibility of `Foo` and each generated field
+/// shares the visibility of the matching field of `Foo`. Use
+/// `#[soa(vis = "pub(crate)")]` to pick a different visibility for the types.
+///
+/// `#[soa(private_columns)]` keeps the `Vec` columns of `FooSoA` private so they
+/// cannot fall out of sync, and generates `foo()`/`foo_mut()` slice accessors
+/// instead.
+///
+/// `#[soa(storage = "single")]` stores every column in one heap buffer with a shared
+/// length and capacity instead of one `Vec` each. The columns are then always private.
+///
+/// `#[soa(aosoa = 8)]` also generates `FooAoSoA`, which stores the elements in
+/// `FooChunk`s of 8 with an `[T; 8]` array per field, for SIMD-friendly access.
+///
+/// `#[soa(group = "hot")]` on fields generates `FooHotRef`/`FooHotRefMut` views and
+/// `iter_hot()`/`iter_hot_mut()` iterators over just the columns of that group.
+///
+/// `#[soa(nested)]` on a field whose type also derives `SoA` stores that field as
+/// the type's own `BarSoA`, so its fields get columns too and `FooRef` holds a
+/// `BarRef` for it.
+///
+/// `#[soa(bitpacked)]` on a `bool` field stores it one bit per element. `FooRef`
+/// then gives a `&bool`, `FooRefMut` a proxy with `get`/`set`, and
+/// `count_where_field()` counts the set bits a word at a time.
+///
+/// `#[soa(nullable)]` on an `Option<T>` field stores a validity bitmap next to a
+/// dense `Vec<T>`, with `T::default()` in the place of each `None`. `FooRef` then
+/// gives an `Option<&T>`, and `iter_valid()` on the column skips the `None`s a word
+/// at a time.
+///
+/// With the `rayon` feature, `#[soa(rayon)]` on the struct gives `FooSoA` and
+/// `FooSliceMut` `par_iter`, `par_iter_mut`, `par_chunks_mut` and `par_sort_by`,
+/// built on rayon's indexed zip of the columns. The deriving crate needs `rayon`
+/// among its own dependencies.
+///
+/// With the `serde` feature, `#[soa(serde)]` on the struct makes `FooSoA` and
+/// `FooSlice` implement `Serialize` as one sequence per column, or as a sequence of
+/// elements with `#[soa(serde = "rows")]`, and `FooSoA` implement `Deserialize` from
+/// the same shape. The deriving crate needs `serde` among its own dependencies.
+///
+/// With the `arrow` feature, `#[soa(arrow)]` on the struct adds `arrow_schema()`,
+/// `to_record_batch()` and `TryFrom<RecordBatch>` to `FooSoA`. Every field must be an
+/// integer, float, `bool`, `String` or an `Option` of one; other field types are
+/// rejected when deriving.
+///
+/// With the `mmap` feature, `#[soa(mmap)]` on a struct of integer, float and array
+/// fields adds `FooSoA::write_to(path)`, which writes the columns to a file, and
+/// `FooMmap`, which maps such a file and views it as a `FooSlice` without copying.
+///
+/// With the `csv` feature, `#[soa(csv)]` on the struct adds `FooSoA::read_csv` and
+/// `write_csv`, which read and write a CSV with one column per field, parsed with
+/// `FromStr` and written with `Display`. `#[soa(rename = "Time (s)")]` on a field
+/// changes its CSV header, and nested fields are rejected.
+///
+/// On an enum, `FooSoA` keeps a tag column and stores each variant with fields as a
+/// `FooVariant` struct in its own `FooVariantSoA`. `get` returns a `FooRef` enum of
+/// the variants' references, and `iter_variant()` walks the elements of one variant.
+///
+/// Columns are named after the fields. Tuple struct columns are named `_0`, `_1`,
+/// and so on; `#[soa(name = "x")]` on a field picks a different column name.
+///
+/// On the struct, `#[soa(name = "Particles", ref_name = "ParticleView")]` renames the
+/// generated types (`ref_mut_name`, `slice_name`, `slice_mut_name`, `ptr_name` and
+/// `ptr_mut_name` work the same way), and `#[soa(derive(Debug, Clone))]` forwards
+/// derives to the view types that can implement them.
+///
+/// `FooSoA` implements `Default`, `Clone`, `Debug`, `PartialEq`, `Eq` and `Hash`
+/// whenever all field types do.
+#[proc_macro_derive(SoA, attributes(soa))]
 pub fn dods_soa_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let name = &input.ident;
-    let soa_name = format_ident!("{}SoA", name);
-    let soa_ref_name = format_ident!("{}Ref", name);
-    let soa_ref_mut_name = format_ident!("{}RefMut", name);
-    let soa_slice_name = format_ident!("{}Slice", name);
-    let soa_slice_mut_name = format_ident!("{}SliceMut", name);
-    let soa_ptr_name = format_ident!("{}Ptr", name);
-    let soa_ptr_mut_name = format_ident!("{}PtrMut", name);
-
-    let fields = match input.data {
-        Data::Struct(ref s) => match s.fields {
-            Fields::Named(ref f) => &f.named,
-            _ => panic!("DODS SoA only supports structs with named fields"),
-        },
-        _ => panic!("DODS SoA only supports structs"),
-    };
-
-    let field_idents: Vec<_> = fields
-        .iter()
-        .map(|f| f.ident.as_ref().unwrap())
-        .collect();
-    let field_types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
-    // Use the first field to check lengths/emptiness for the whole SoA
-    let first_field = field_idents[0];
-
-    let iter_parts: Vec<_> = field_idents
-        .iter()
-        .map(|ident| quote! { self.#ident.iter() })
-        .collect();
-    let iter_mut_parts: Vec<_> = field_idents
-        .iter()
-        .map(|ident| quote! { self.#ident.iter_mut() })
-        .collect();
-
-    let mut iter_expr = iter_parts[0].clone();
-    for part in iter_parts.iter().skip(1) {
-        iter_expr = quote! { #iter_expr.zip(#part) };
-    }
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
 
-    let mut iter_mut_expr = iter_mut_parts[0].clone();
-    for part in iter_mut_parts.iter().skip(1) {
-        iter_mut_expr = quote! { #iter_mut_expr.zip(#part) };
-    }
+/// Iterates over a subset of the columns of a SoA or one of its slice views.
+///
+/// `soa_project!(readings => temperature, mut pressure)` zips only the `temperature`
+/// and `pressure` columns and yields `(&f32, &mut f32)` tuples, so the loop carries
+/// no iterators for the other columns. A single column yields the bare reference.
+#[proc_macro]
+pub fn soa_project(input: TokenStream) -> TokenStream {
+    let projection = parse_macro_input!(input as project::Projection);
+    project::expand(&projection)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
 
-    let mut tuple_pattern = quote! { #first_field };
-    for ident in field_idents.iter().skip(1) {
-        tuple_pattern = quote! { (#tuple_pattern, #ident) };
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    if let Data::Enum(data) = &input.data {
+        return enums::expand(input, data);
     }
+    let soa = Soa::parse(input)?;
+    let vis = &soa.vis;
+    let Names {
+        soa: soa_name,
+        ref_: soa_ref_name,
+        ref_mut: soa_ref_mut_name,
+        slice: soa_slice_name,
+        slice_mut: soa_slice_mut_name,
+        ptr: soa_ptr_name,
+        ptr_mut: soa_ptr_mut_name,
+        iter: soa_iter_name,
+        iter_mut: soa_iter_mut_name,
+        ..
+    } = &soa.names;
+
+    // Forwarded derives go wherever the trait can make sense: the container takes its
+    // own (see `storage`), the views hold references so they have no `Default`, the
+    // slice view is always `Copy` (see `slice`), and the mutable views cannot be
+    // duplicated.
+    let view_derives = derive_attr(&soa.attrs.derives, &["Default"]);
+    let slice_derives = derive_attr(&soa.attrs.derives, &["Clone", "Copy", "Default"]);
+    let view_mut_derives = derive_attr(&soa.attrs.derives, &["Clone", "Copy", "Default"]);
+
+    // The owning container and the pointer types carry the source generics as-is,
+    // while the borrowed views additionally get a fresh lifetime in front.
+    let generics = soa.generics();
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let lt = &soa.lt;
+    let view_generics = &soa.view_generics;
+    let (_, view_ty_generics, _) = view_generics.split_for_impl();
+    let soa_ref = soa.view_ty(soa_ref_name);
+    let soa_ref_mut = soa.view_ty(soa_ref_mut_name);
+    let soa_slice = soa.view_ty(soa_slice_name);
+    let soa_slice_mut = soa.view_ty(soa_slice_mut_name);
+    let soa_iter = soa.view_ty(soa_iter_name);
+    let soa_iter_mut = soa.view_ty(soa_iter_mut_name);
+
+    let field_idents: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let ref_tys: Vec<_> = soa.columns.iter().map(|c| c.ref_ty(lt)).collect();
+    let ref_mut_tys: Vec<_> = soa.columns.iter().map(|c| c.ref_mut_ty(lt)).collect();
+    let slice_tys: Vec<_> = soa.columns.iter().map(|c| c.slice_ty(lt)).collect();
+    let slice_mut_tys: Vec<_> = soa.columns.iter().map(|c| c.slice_mut_ty(lt)).collect();
+    let ptr_tys: Vec<_> = soa.columns.iter().map(|c| c.ptr_ty()).collect();
+    let ptr_mut_tys: Vec<_> = soa.columns.iter().map(|c| c.ptr_mut_ty()).collect();
+    let field_vis: Vec<_> = soa.columns.iter().map(|c| c.vis).collect();
+    let accessors = column_accessors(&soa)?;
 
     let expanded = quote! {
-        /// Struct-of-arrays container generated for the source struct.
-        pub struct #soa_name {
-            #( pub #field_idents: Vec<#field_types>, )*
-        }
-
         /// Immutable references to a single element of the SoA.
-        pub struct #soa_ref_name<'a> {
-            #( pub #field_idents: &'a #field_types, )*
+        #view_derives
+        #[allow(dead_code)]
+        #vis struct #soa_ref_name #view_generics #where_clause {
+            #( #field_vis #field_idents: #ref_tys, )*
         }
 
         /// Mutable references to a single element of the SoA.
-        pub struct #soa_ref_mut_name<'a> {
-            #( pub #field_idents: &'a mut #field_types, )*
+        #view_mut_derives
+        #[allow(dead_code)]
+        #vis struct #soa_ref_mut_name #view_generics #where_clause {
+            #( #field_vis #field_idents: #ref_mut_tys, )*
         }
 
         /// Immutable slices for each field over a range of the SoA.
-        pub struct #soa_slice_name<'a> {
-            #( pub #field_idents: &'a [#field_types], )*
+        #slice_derives
+        #[allow(dead_code)]
+        #vis struct #soa_slice_name #view_generics #where_clause {
+            #( #field_vis #field_idents: #slice_tys, )*
         }
 
         /// Mutable slices for each field over a range of the SoA.
-        pub struct #soa_slice_mut_name<'a> {
-            #( pub #field_idents: &'a mut [#field_types], )*
+        #view_mut_derives
+        #[allow(dead_code)]
+        #vis struct #soa_slice_mut_name #view_generics #where_clause {
+            #( #field_vis #field_idents: #slice_mut_tys, )*
         }
 
         /// Raw const pointers for each field buffer.
-        pub struct #soa_ptr_name {
-            #( pub #field_idents: *const #field_types, )*
+        #[allow(dead_code)]
+        #vis struct #soa_ptr_name #generics #where_clause {
+            #( #field_vis #field_idents: #ptr_tys, )*
         }
 
         /// Raw mut pointers for each field buffer.
-        pub struct #soa_ptr_mut_name {
-            #( pub #field_idents: *mut #field_types, )*
+        #[allow(dead_code)]
+        #vis struct #soa_ptr_mut_name #generics #where_clause {
+            #( #field_vis #field_idents: #ptr_mut_tys, )*
         }
 
-        impl #soa_name {
-            /// Creates a new, empty SoA.
-            pub fn new() -> Self {
-                Self {
-                    #( #field_idents: Vec::new(), )*
-                }
-            }
-
-            /// Creates an empty SoA with capacity for at least `capacity` elements.
-            pub fn with_capacity(capacity: usize) -> Self {
-                Self {
-                    #( #field_idents: Vec::with_capacity(capacity), )*
-                }
-            }
-
-            /// Appends a single `#name` to the SoA.
-            pub fn push(&mut self, item: #name) {
-                #( self.#field_idents.push(item.#field_idents); )*
-            }
-
-            /// Inserts `element` at `index`, shifting later elements to the right.
-            pub fn insert(&mut self, index: usize, element: #name) {
-                let #name { #( #field_idents ),* } = element;
-                #( self.#field_idents.insert(index, #field_idents); )*
-            }
-
-            /// Replaces the element at `index` with `element`, returning the old value.
-            pub fn replace(&mut self, index: usize, element: #name) -> #name {
-                let #name { #( #field_idents ),* } = element;
-                #name {
-                    #( #field_idents: std::mem::replace(&mut self.#field_idents[index], #field_idents), )*
-                }
-            }
-
-            /// Removes and returns the element at `index`, shifting later elements left.
-            pub fn remove(&mut self, index: usize) -> #name {
-                #name {
-                    #( #field_idents: self.#field_idents.remove(index), )*
-                }
-            }
-
-            /// Removes the element at `index` in O(1) time.
-            /// It swaps the element at `index` with the last element and then pops.
-            /// Note: This does NOT preserve the order of elements.
-            pub fn swap_remove(&mut self, index: usize) -> #name {
-                #name {
-                    #( #field_idents: self.#field_idents.swap_remove(index), )*
-                }
-            }
-
-            pub fn pop(&mut self) -> Option<#name> {
-                if self.#first_field.is_empty() { return None; }
-                Some(#name {
-                    #( #field_idents: self.#field_idents.pop().unwrap(), )*
-                })
-            }
-
-            /// Returns the number of elements in the SoA.
-            pub fn len(&self) -> usize {
-                self.#first_field.len()
-            }
-
+        impl #impl_generics #soa_name #ty_generics #where_clause {
             /// Returns true when the SoA has no elements.
             pub fn is_empty(&self) -> bool {
-                self.#first_field.is_empty()
-            }
-
-            /// Returns the capacity of the underlying field vectors.
-            pub fn capacity(&self) -> usize {
-                self.#first_field.capacity()
+                self.len() == 0
             }
 
-            /// Reserves capacity for at least `additional` more elements.
-            pub fn reserve(&mut self, additional: usize) {
-                #( self.#field_idents.reserve(additional); )*
-            }
-
-            /// Reserves the minimum capacity for `additional` more elements.
-            pub fn reserve_exact(&mut self, additional: usize) {
-                #( self.#field_idents.reserve_exact(additional); )*
-            }
-
-            /// Shrinks all field buffers as much as possible.
-            pub fn shrink_to_fit(&mut self) {
-                #( self.#field_idents.shrink_to_fit(); )*
-            }
-
-            /// Shortens the SoA to `len`, dropping excess elements.
-            pub fn truncate(&mut self, len: usize) {
-                #( self.#field_idents.truncate(len); )*
-            }
-
-            /// Clears all elements from the SoA.
-            pub fn clear(&mut self) {
-                #( self.#field_idents.clear(); )*
-            }
-
-            /// Appends all elements from `other`, leaving it empty.
-            pub fn append(&mut self, other: &mut Self) {
-                #( self.#field_idents.append(&mut other.#field_idents); )*
-            }
-
-            /// Splits the SoA into two at `at`, returning the tail.
-            pub fn split_off(&mut self, at: usize) -> Self {
-                Self {
-                    #( #field_idents: self.#field_idents.split_off(at), )*
-                }
-            }
-
-            /// Returns immutable slices of each field covering the full range.
-            pub fn as_slice(&self) -> #soa_slice_name<'_> {
-                #soa_slice_name {
-                    #( #field_idents: &self.#field_idents[..], )*
-                }
-            }
-
-            /// Returns mutable slices of each field covering the full range.
-            pub fn as_mut_slice(&mut self) -> #soa_slice_mut_name<'_> {
-                #soa_slice_mut_name {
-                    #( #field_idents: &mut self.#field_idents[..], )*
-                }
-            }
-
-            fn bounds_to_range(index: impl core::ops::RangeBounds<usize>, len: usize) -> (usize, usize) {
+            /// Resolves `index` to exclusive `start..end` bounds. The bounds are widened
+            /// so that `..=usize::MAX` or an excluded `usize::MAX` start cannot overflow.
+            fn range_bounds(index: &impl core::ops::RangeBounds<usize>, len: usize) -> (u128, u128) {
                 use core::ops::Bound::{Excluded, Included, Unbounded};
                 let start = match index.start_bound() {
-                    Included(&n) => n,
-                    Excluded(&n) => n + 1,
+                    Included(&n) => n as u128,
+                    Excluded(&n) => n as u128 + 1,
                     Unbounded => 0,
                 };
                 let end = match index.end_bound() {
-                    Included(&n) => n + 1,
-                    Excluded(&n) => n,
-                    Unbounded => len,
+                    Included(&n) => n as u128 + 1,
+                    Excluded(&n) => n as u128,
+                    Unbounded => len as u128,
                 };
                 (start, end)
             }
 
-            /// Returns immutable slices of each field for the given `index` range.
-            pub fn slice(&self, index: impl core::ops::RangeBounds<usize>) -> #soa_slice_name<'_> {
-                let (start, end) = Self::bounds_to_range(index, self.len());
-                #soa_slice_name {
-                    #( #field_idents: &self.#field_idents[start..end], )*
+            /// Resolves `index` against `len`, or returns `None` if the range is
+            /// inverted or reaches past `len`.
+            fn checked_bounds(index: &impl core::ops::RangeBounds<usize>, len: usize) -> Option<(usize, usize)> {
+                let (start, end) = Self::range_bounds(index, len);
+                if start <= end && end <= len as u128 {
+                    Some((start as usize, end as usize))
+                } else {
+                    None
                 }
             }
 
-            /// Returns mutable slices of each field for the given `index` range.
-            pub fn slice_mut(&mut self, index: impl core::ops::RangeBounds<usize>) -> #soa_slice_mut_name<'_> {
-                let (start, end) = Self::bounds_to_range(index, self.len());
-                #soa_slice_mut_name {
-                    #( #field_idents: &mut self.#field_idents[start..end], )*
+            /// Resolves `index` against `len`.
+            ///
+            /// # Panics
+            /// Panics with the SoA type and the requested bounds if the range is
+            /// inverted or reaches past `len`.
+            #[track_caller]
+            fn bounds_to_range(index: impl core::ops::RangeBounds<usize>, len: usize) -> (usize, usize) {
+                match Self::checked_bounds(&index, len) {
+                    Some(range) => range,
+                    None => {
+                        let (start, end) = Self::range_bounds(&index, len);
+                        panic!(
+                            "range {}..{} out of bounds for {} of length {}",
+                            start,
+                            end,
+                            core::any::type_name::<Self>(),
+                            len,
+                        )
+                    }
                 }
             }
 
-            /// Returns raw const pointers to each field buffer.
-            pub fn as_ptr(&self) -> #soa_ptr_name {
-                #soa_ptr_name {
-                    #( #field_idents: self.#field_idents.as_ptr(), )*
-                }
+            /// Returns immutable slices of each field for the given `index` range.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the SoA.
+            #[track_caller]
+            pub fn slice(&self, index: impl core::ops::RangeBounds<usize>) -> #soa_slice {
+                self.as_slice().slice(index)
             }
 
-            /// Returns raw mut pointers to each field buffer.
-            pub fn as_mut_ptr(&mut self) -> #soa_ptr_mut_name {
-                #soa_ptr_mut_name {
-                    #( #field_idents: self.#field_idents.as_mut_ptr(), )*
-                }
+            /// Returns immutable slices of each field for the given `index` range,
+            /// or `None` if the range is inverted or reaches past the end of the SoA.
+            pub fn get_slice(&self, index: impl core::ops::RangeBounds<usize>) -> Option<#soa_slice> {
+                self.as_slice().get_slice(index)
+            }
+
+            /// Returns mutable slices of each field for the given `index` range.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the SoA.
+            #[track_caller]
+            pub fn slice_mut(&mut self, index: impl core::ops::RangeBounds<usize>) -> #soa_slice_mut {
+                self.as_mut_slice().into_slice_mut(index)
+            }
+
+            /// Returns mutable slices of each field for the given `index` range,
+            /// or `None` if the range is inverted or reaches past the end of the SoA.
+            pub fn get_slice_mut(&mut self, index: impl core::ops::RangeBounds<usize>) -> Option<#soa_slice_mut> {
+                let (start, end) = Self::checked_bounds(&index, self.len())?;
+                Some(self.as_mut_slice().into_slice_mut(start..end))
             }
 
             /// Returns references to the element at `index`, or `None` if out of bounds.
-            pub fn get(&self, index: usize) -> Option<#soa_ref_name<'_>> {
-                if index >= self.#first_field.len() { return None; }
-                Some(#soa_ref_name {
-                    #( #field_idents: &self.#field_idents[index], )*
-                })
+            pub fn get(&self, index: usize) -> Option<#soa_ref> {
+                self.as_slice().get(index)
             }
 
             /// Returns references to the element at `index`.
             ///
             /// # Panics
             /// Panics if `index` is out of bounds.
-            pub fn index(&self, index: usize) -> #soa_ref_name<'_> {
-                #soa_ref_name {
-                    #( #field_idents: &self.#field_idents[index], )*
-                }
+            pub fn index(&self, index: usize) -> #soa_ref {
+                self.as_slice().index(index)
             }
 
             /// Returns mutable references to the element at `index`, or `None` if out of bounds.
-            pub fn get_mut(&mut self, index: usize) -> Option<#soa_ref_mut_name<'_>> {
-                if index >= self.#first_field.len() { return None; }
-                Some(#soa_ref_mut_name {
-                    #( #field_idents: &mut self.#field_idents[index], )*
-                })
+            pub fn get_mut(&mut self, index: usize) -> Option<#soa_ref_mut> {
+                if index >= self.len() { return None; }
+                Some(self.as_mut_slice().into_index_mut(index))
             }
 
             /// Returns mutable references to the element at `index`.
             ///
             /// # Panics
             /// Panics if `index` is out of bounds.
-            pub fn index_mut(&mut self, index: usize) -> #soa_ref_mut_name<'_> {
-                #soa_ref_mut_name {
-                    #( #field_idents: &mut self.#field_idents[index], )*
-                }
+            pub fn index_mut(&mut self, index: usize) -> #soa_ref_mut {
+                self.as_mut_slice().into_index_mut(index)
             }
 
             /// Returns the first element, if any.
-            pub fn first(&self) -> Option<#soa_ref_name<'_>> {
-                self.get(0)
+            pub fn first(&self) -> Option<#soa_ref> {
+                self.as_slice().first()
             }
 
             /// Returns the last element, if any.
-            pub fn last(&self) -> Option<#soa_ref_name<'_>> {
-                self.get(self.len().saturating_sub(1))
+            pub fn last(&self) -> Option<#soa_ref> {
+                self.as_slice().last()
             }
 
             /// Returns mutable references to the first element, if any.
-            pub fn first_mut(&mut self) -> Option<#soa_ref_mut_name<'_>> {
+            pub fn first_mut(&mut self) -> Option<#soa_ref_mut> {
                 self.get_mut(0)
             }
 
             /// Returns mutable references to the last element, if any.
-            pub fn last_mut(&mut self) -> Option<#soa_ref_mut_name<'_>> {
+            pub fn last_mut(&mut self) -> Option<#soa_ref_mut> {
                 self.get_mut(self.len().saturating_sub(1))
             }
 
@@ -326,62 +353,279 @@ pub fn dods_soa_derive(input: TokenStream) -> TokenStream {
                 if indices.len() != len {
                     panic!("index length mismatch");
                 }
-                let mut seen = vec![false; len];
-                for &idx in indices {
-                    if idx >= len || seen[idx] {
+                let mut indices = indices.to_vec();
+                if indices.iter().any(|&idx| idx >= len) {
+                    panic!("indices must be a permutation");
+                }
+                // Walk each cycle, marking the entries on it by complementing them, which
+                // puts them out of range. It is a permutation if every walk closes back
+                // at its start, so repeated indices are caught before anything moves.
+                for start in 0..len {
+                    let mut current = start;
+                    while indices[current] < len {
+                        let next = indices[current];
+                        indices[current] = !next;
+                        current = next;
+                    }
+                    if current != start {
                         panic!("indices must be a permutation");
                     }
-                    seen[idx] = true;
                 }
-                let mut permutation = vec![0usize; len];
-                for (new_pos, &old_pos) in indices.iter().enumerate() {
-                    permutation[old_pos] = new_pos;
-                }
-                for i in 0..len {
-                    while permutation[i] != i {
-                        let j = permutation[i];
-                        #( self.#field_idents.swap(i, j); )*
-                        permutation.swap(i, j);
-                    }
+                for idx in &mut indices {
+                    *idx = !*idx;
                 }
+                self.as_mut_slice().apply_permutation(indices);
             }
 
             pub fn sort_by<F>(&mut self, mut f: F)
             where
-                F: FnMut(#soa_ref_name<'_>, #soa_ref_name<'_>) -> std::cmp::Ordering,
+                F: FnMut(#soa_ref, #soa_ref) -> std::cmp::Ordering,
             {
                 let mut permutation: Vec<usize> = (0..self.len()).collect();
                 permutation.sort_by(|j, k| f(self.index(*j), self.index(*k)));
 
-                self.apply_index(&permutation);
+                self.as_mut_slice().apply_permutation(permutation);
             }
 
             pub fn sort_by_key<F, K>(&mut self, mut f: F)
             where
-                F: FnMut(#soa_ref_name<'_>) -> K,
+                F: FnMut(#soa_ref) -> K,
                 K: Ord,
             {
                 let mut permutation: Vec<usize> = (0..self.len()).collect();
                 permutation.sort_by_key(|j| f(self.index(*j)));
 
-                self.apply_index(&permutation);
+                self.as_mut_slice().apply_permutation(permutation);
+            }
+
+            /// Sorts the SoA with a comparator, without preserving the order of equal
+            /// elements.
+            pub fn sort_unstable_by<F>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref, #soa_ref) -> std::cmp::Ordering,
+            {
+                let mut permutation: Vec<usize> = (0..self.len()).collect();
+                permutation.sort_unstable_by(|j, k| f(self.index(*j), self.index(*k)));
+
+                self.as_mut_slice().apply_permutation(permutation);
+            }
+
+            /// Sorts the SoA with a key extraction function, without preserving the
+            /// order of equal elements.
+            pub fn sort_unstable_by_key<F, K>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref) -> K,
+                K: Ord,
+            {
+                let mut permutation: Vec<usize> = (0..self.len()).collect();
+                permutation.sort_unstable_by_key(|j| f(self.index(*j)));
+
+                self.as_mut_slice().apply_permutation(permutation);
+            }
+
+            /// Sorts the SoA with a key extraction function that is called once per
+            /// element, for keys that are expensive to compute.
+            pub fn sort_by_cached_key<F, K>(&mut self, f: F)
+            where
+                F: FnMut(#soa_ref) -> K,
+                K: Ord,
+            {
+                let mut keys: Vec<(K, usize)> = self.iter().map(f).zip(0..).collect();
+                // The index breaks ties, so the unstable sort gives a stable order.
+                keys.sort_unstable();
+
+                self.as_mut_slice().apply_permutation(keys.into_iter().map(|(_, index)| index).collect());
+            }
+
+            /// Sorts the SoA by the values of one column, picked by `column`, e.g.
+            /// `soa.sort_by_field(|s| s.timestamp)`. The comparisons read that column
+            /// directly instead of going through `FooRef`s. The sort is stable.
+            pub fn sort_by_field<F, K>(&mut self, column: F)
+            where
+                F: for<#lt> FnOnce(#soa_slice_name #view_ty_generics) -> &#lt [K],
+                K: Ord,
+            {
+                let column = column(self.as_slice());
+                let mut permutation: Vec<usize> = (0..column.len()).collect();
+                permutation.sort_by(|j, k| column[*j].cmp(&column[*k]));
+
+                self.as_mut_slice().apply_permutation(permutation);
+            }
+
+            /// Returns true if every pair of consecutive elements satisfies
+            /// `f(previous, next)`.
+            pub fn is_sorted_by<F>(&self, mut f: F) -> bool
+            where
+                F: FnMut(#soa_ref, #soa_ref) -> bool,
+            {
+                (1..self.len()).all(|i| f(self.index(i - 1), self.index(i)))
+            }
+
+            /// Returns true if the keys extracted by `f` are in non-decreasing order.
+            pub fn is_sorted_by_key<F, K>(&self, mut f: F) -> bool
+            where
+                F: FnMut(#soa_ref) -> K,
+                K: PartialOrd,
+            {
+                self.iter().map(&mut f).is_sorted()
             }
 
             /// Returns an iterator over immutable references to each element.
-            pub fn iter(&self) -> impl Iterator<Item = #soa_ref_name<'_>> {
-                #iter_expr.map(|#tuple_pattern| #soa_ref_name {
-                    #( #field_idents: #field_idents, )*
-                })
+            pub fn iter(&self) -> #soa_iter {
+                self.as_slice().iter()
             }
 
             /// Returns an iterator over mutable references to each element.
-            pub fn iter_mut(&mut self) -> impl Iterator<Item = #soa_ref_mut_name<'_>> {
-                #iter_mut_expr.map(|#tuple_pattern| #soa_ref_mut_name {
-                    #( #field_idents: #field_idents, )*
-                })
+            pub fn iter_mut(&mut self) -> #soa_iter_mut {
+                self.as_mut_slice().into_iter()
             }
         }
     };
 
-    TokenStream::from(expanded)
+    let storage = storage::expand(&soa);
+    let traits = traits::expand(&soa);
+    let iters = iter::expand(&soa);
+    let slices = slice::expand(&soa);
+    let aosoa = aosoa::expand(&soa);
+    let groups = group::expand(&soa);
+    let bits = bits::expand(&soa);
+    let nullables = nullable::expand(&soa);
+    let retains = retain::expand(&soa);
+    let pars = par::expand(&soa)?;
+    let serdes = serde::expand(&soa)?;
+    let arrows = arrow::expand(&soa)?;
+    let mmaps = mmap::expand(&soa)?;
+    let csvs = csv::expand(&soa)?;
+
+    Ok(quote! {
+        #expanded
+        #storage
+        #accessors
+        #traits
+        #retains
+        #iters
+        #slices
+        #aosoa
+        #groups
+        #bits
+        #nullables
+        #pars
+        #serdes
+        #arrows
+        #mmaps
+        #csvs
+    })
+}
+
+/// Inherent methods of `FooSoA` emitted here.
+const METHODS: &[&str] = &[
+    "is_empty",
+    "range_bounds",
+    "checked_bounds",
+    "bounds_to_range",
+    "slice",
+    "get_slice",
+    "slice_mut",
+    "get_slice_mut",
+    "get",
+    "index",
+    "get_mut",
+    "index_mut",
+    "first",
+    "last",
+    "first_mut",
+    "last_mut",
+    "apply_index",
+    "sort_by",
+    "sort_by_key",
+    "sort_unstable_by",
+    "sort_unstable_by_key",
+    "sort_by_cached_key",
+    "sort_by_field",
+    "is_sorted_by",
+    "is_sorted_by_key",
+    "iter",
+    "iter_mut",
+];
+
+/// Every inherent method of `FooSoA`, which a column accessor must not shadow. Each
+/// generator lists the methods it emits next to the code emitting them.
+fn soa_methods(soa: &Soa) -> Vec<String> {
+    let fixed = [
+        METHODS,
+        storage::METHODS,
+        retain::METHODS,
+        par::METHODS,
+        arrow::METHODS,
+        mmap::METHODS,
+        csv::METHODS,
+    ];
+    let mut methods: Vec<String> = fixed.concat().iter().map(|m| m.to_string()).collect();
+    methods.extend(bits::methods(soa));
+    methods.extend(group::methods(soa));
+    methods
+}
+
+/// `foo()` and `foo_mut()` slice accessors for every column when the columns are
+/// private, each as visible as the field it comes from.
+fn column_accessors(soa: &Soa) -> syn::Result<proc_macro2::TokenStream> {
+    if !soa.private_columns() {
+        return Ok(quote! {});
+    }
+    let soa_name = &soa.names.soa;
+    let (impl_generics, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let soa_methods = soa_methods(soa);
+    let mut methods = Vec::new();
+    for column in &soa.columns {
+        let (ident, vis) = (&column.ident, column.vis);
+        let elided = Lifetime::new("'_", Span::call_site());
+        let (slice_ty, slice_mut_ty) = (column.slice_ty(&elided), column.slice_mut_ty(&elided));
+        let ident_mut = format_ident!("{}_mut", ident);
+        for accessor in [ident, &ident_mut] {
+            if soa_methods.contains(&accessor.to_string()) {
+                return Err(Error::new_spanned(
+                    ident,
+                    format!(
+                        "column accessor `{}` clashes with the `{}` method of the same name; \
+                         rename the column with #[soa(name = \"...\")]",
+                        accessor, soa_name,
+                    ),
+                ));
+            }
+        }
+        let doc = format!("Returns the `{}` column.", ident);
+        let doc_mut = format!("Returns the `{}` column as a mutable slice.", ident);
+        methods.push(quote! {
+            #[doc = #doc]
+            #vis fn #ident(&self) -> #slice_ty {
+                self.as_slice().#ident
+            }
+
+            #[doc = #doc_mut]
+            #vis fn #ident_mut(&mut self) -> #slice_mut_ty {
+                self.as_mut_slice().#ident
+            }
+        });
+    }
+    Ok(quote! {
+        impl #impl_generics #soa_name #ty_generics #where_clause {
+            #( #methods )*
+        }
+    })
+}
+
+/// Builds a `#[derive(...)]` attribute from `derives`, leaving out the traits named in `skip`.
+fn derive_attr(derives: &[Path], skip: &[&str]) -> proc_macro2::TokenStream {
+    let kept: Vec<_> = derives
+        .iter()
+        .filter(|path| {
+            let last = path.segments.last().map(|s| s.ident.to_string());
+            !skip.iter().any(|s| last.as_deref() == Some(*s))
+        })
+        .collect();
+    if kept.is_empty() {
+        quote! {}
+    } else {
+        quote! { #[derive( #( #kept ),* )] }
+    }
 }
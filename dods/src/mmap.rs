@@ -0,0 +1,319 @@
+use crate::model::{Column, Soa};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Error, Expr, Lit, Type};
+
+/// Inherent methods of `FooSoA` emitted here.
+pub(crate) const METHODS: &[&str] = &["write_to"];
+
+/// Column files for `#[soa(mmap)]` structs, behind the `mmap` feature of this crate:
+/// `FooSoA::write_to(path)` writes one, and `FooMmap` maps it back read-only and
+/// hands out a `FooSlice` over the mapped columns without copying them.
+///
+/// A file holds a header followed by the raw bytes of each column:
+///
+/// ```text
+/// magic        8 bytes  b"DODSCOL\0"
+/// version      u32 LE   1
+/// byte order   u32      0x01020304 in the byte order of the writer
+/// schema hash  u64 LE   FNV-1a of "name:type;" for every column
+/// len          u64 LE   number of elements
+/// columns      u32 LE   number of columns
+/// per column:
+///   name       u32 LE length, then the UTF-8 bytes
+///   type       u32 LE length, then the UTF-8 bytes, e.g. "f32" or "[u8; 4]"
+///   offset     u64 LE   where the column starts, a multiple of 64
+/// ```
+///
+/// The columns are written in the byte order of the writer, and `FooMmap::open`
+/// rejects a file written with another byte order or for another schema. Only
+/// fixed-size integers, floats and arrays of them are supported, since the mapped
+/// bytes are read as those types directly and any bytes are a valid value of them.
+///
+/// The generated code names `::memmap2` directly, so the crate deriving `SoA` needs it
+/// as a dependency.
+pub(crate) fn expand(soa: &Soa) -> syn::Result<TokenStream> {
+    if !soa.attrs.mmap {
+        return Ok(quote! {});
+    }
+    if !cfg!(feature = "mmap") {
+        return Err(Error::new_spanned(
+            soa.name(),
+            "#[soa(mmap)] needs the `mmap` feature of dods",
+        ));
+    }
+    if !soa.generics().params.is_empty() {
+        return Err(Error::new_spanned(
+            soa.generics(),
+            "#[soa(mmap)] does not support generic structs",
+        ));
+    }
+
+    let mut type_names = Vec::new();
+    let mut errors: Option<Error> = None;
+    for column in &soa.columns {
+        match type_name(column) {
+            Ok(name) => type_names.push(name),
+            Err(err) => match &mut errors {
+                Some(errors) => errors.combine(err),
+                None => errors = Some(err),
+            },
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    let vis = &soa.vis;
+    let soa_name = &soa.names.soa;
+    let slice_name = &soa.names.slice;
+    let mmap_name = &soa.names.mmap;
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let types: Vec<_> = soa.columns.iter().map(|c| c.ty).collect();
+    let col_names: Vec<_> = cols.iter().map(|c| c.unraw().to_string()).collect();
+    let count = soa.columns.len();
+    let count_u32 = count as u32;
+    let indices: Vec<_> = (0..count).collect();
+    let offsets: Vec<_> = (0..count).map(|i| format_ident!("offset_{}", i)).collect();
+
+    let schema: String = col_names
+        .iter()
+        .zip(&type_names)
+        .map(|(name, ty)| format!("{}:{};", name, ty))
+        .collect();
+    let hash = fnv1a(schema.as_bytes());
+    // The header only depends on the names and types, so its length is known here.
+    let header_len = 36
+        + col_names
+            .iter()
+            .zip(&type_names)
+            .map(|(name, ty)| 4 + name.len() + 4 + ty.len() + 8)
+            .sum::<usize>();
+    // Each column starts at the first multiple of 64 after the end of the previous
+    // one, or of the header.
+    let prev_ends: Vec<_> = (0..count)
+        .map(|i| match i {
+            0 => quote! { #header_len },
+            _ => {
+                let (offset, col) = (&offsets[i - 1], cols[i - 1]);
+                quote! { #offset + core::mem::size_of_val(slice.#col) }
+            }
+        })
+        .collect();
+    let not_a_file = format!("not a {} column file", soa_name);
+    let wrong_schema = format!("the column file was not written by {}", soa_name);
+
+    Ok(quote! {
+        impl #soa_name {
+            /// Writes the SoA to a column file at `path`, which `FooMmap::open` maps back
+            /// without copying. The file is replaced if it exists.
+            pub fn write_to(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+                use std::io::Write;
+
+                let slice = self.as_slice();
+                let mut header = Vec::with_capacity(#header_len);
+                header.extend_from_slice(b"DODSCOL\0");
+                header.extend_from_slice(&1u32.to_le_bytes());
+                header.extend_from_slice(&0x0102_0304u32.to_ne_bytes());
+                header.extend_from_slice(&#hash.to_le_bytes());
+                header.extend_from_slice(&(self.len() as u64).to_le_bytes());
+                header.extend_from_slice(&#count_u32.to_le_bytes());
+                #(
+                    let #offsets = (#prev_ends).next_multiple_of(64);
+                    header.extend_from_slice(&(#col_names.len() as u32).to_le_bytes());
+                    header.extend_from_slice(#col_names.as_bytes());
+                    header.extend_from_slice(&(#type_names.len() as u32).to_le_bytes());
+                    header.extend_from_slice(#type_names.as_bytes());
+                    header.extend_from_slice(&(#offsets as u64).to_le_bytes());
+                )*
+
+                let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                file.write_all(&header)?;
+                #(
+                    file.write_all(&[0; 64][..#offsets - (#prev_ends)])?;
+                    // SAFETY: the column holds integers, floats or arrays of them,
+                    // which have no padding, so all of its bytes are initialized.
+                    let bytes = unsafe {
+                        core::slice::from_raw_parts(
+                            slice.#cols.as_ptr().cast::<u8>(),
+                            core::mem::size_of_val(slice.#cols),
+                        )
+                    };
+                    file.write_all(bytes)?;
+                )*
+                file.flush()
+            }
+        }
+
+        /// A column file written by `FooSoA::write_to`, mapped into memory read-only.
+        /// `as_slice` views its columns in place.
+        #vis struct #mmap_name {
+            map: ::memmap2::Mmap,
+            len: usize,
+            offsets: [usize; #count],
+        }
+
+        impl #mmap_name {
+            /// Maps the column file at `path` and checks its header against the
+            /// columns of `FooSoA`.
+            ///
+            /// # Safety
+            /// The file must not be modified or truncated while it is mapped, by this
+            /// process or any other, as the views handed out read it directly.
+            pub unsafe fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+                let file = std::fs::File::open(path)?;
+                // SAFETY: upheld by the caller.
+                let map = unsafe { ::memmap2::Mmap::map(&file)? };
+                let (len, offsets) = Self::read_header(&map)?;
+                Ok(Self { map, len, offsets })
+            }
+
+            /// Parses the header of the mapped `file`, returning the number of elements
+            /// and where each column starts, once each column is known to fit in the
+            /// file at an offset aligned for its type.
+            fn read_header(file: &[u8]) -> std::io::Result<(usize, [usize; #count])> {
+                fn invalid(message: String) -> std::io::Error {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+                }
+                fn take<'f>(rest: &mut &'f [u8], n: usize) -> std::io::Result<&'f [u8]> {
+                    let (head, tail) = rest
+                        .split_at_checked(n)
+                        .ok_or_else(|| invalid(format!("{}: the header is cut short", #not_a_file)))?;
+                    *rest = tail;
+                    Ok(head)
+                }
+                fn take_u32(rest: &mut &[u8]) -> std::io::Result<u32> {
+                    let bytes = take(rest, 4)?;
+                    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                }
+                fn take_u64(rest: &mut &[u8]) -> std::io::Result<u64> {
+                    let (low, high) = (take_u32(rest)?, take_u32(rest)?);
+                    Ok(u64::from(low) | u64::from(high) << 32)
+                }
+                fn take_str<'f>(rest: &mut &'f [u8]) -> std::io::Result<&'f [u8]> {
+                    let len = take_u32(rest)? as usize;
+                    take(rest, len)
+                }
+
+                let rest = &mut &file[..];
+                if take(rest, 8)? != b"DODSCOL\0" {
+                    return Err(invalid(#not_a_file.to_string()));
+                }
+                let version = take_u32(rest)?;
+                if version != 1 {
+                    return Err(invalid(format!("unsupported column file version {}", version)));
+                }
+                if take(rest, 4)? != 0x0102_0304u32.to_ne_bytes() {
+                    return Err(invalid("the column file was written with another byte order".to_string()));
+                }
+                if take_u64(rest)? != #hash {
+                    return Err(invalid(#wrong_schema.to_string()));
+                }
+                let len = usize::try_from(take_u64(rest)?)
+                    .map_err(|_| invalid("the column file is too long to map".to_string()))?;
+                if take_u32(rest)? != #count_u32 {
+                    return Err(invalid(#wrong_schema.to_string()));
+                }
+                #(
+                    if take_str(rest)? != #col_names.as_bytes() || take_str(rest)? != #type_names.as_bytes() {
+                        return Err(invalid(format!(
+                            "column {} of the column file is not `{}: {}`",
+                            #indices,
+                            #col_names,
+                            #type_names,
+                        )));
+                    }
+                    let #offsets = usize::try_from(take_u64(rest)?).ok().filter(|offset| {
+                        offset % core::mem::align_of::<#types>() == 0
+                            && len
+                                .checked_mul(core::mem::size_of::<#types>())
+                                .and_then(|size| offset.checked_add(size))
+                                .is_some_and(|end| end <= file.len())
+                    });
+                    let Some(#offsets) = #offsets else {
+                        return Err(invalid(format!("column `{}` does not fit in the column file", #col_names)));
+                    };
+                )*
+                Ok((len, [ #( #offsets, )* ]))
+            }
+
+            /// Returns the number of elements in the file.
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns true when the file has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// Views the mapped columns as a slice of the SoA.
+            pub fn as_slice(&self) -> #slice_name<'_> {
+                let base = self.map.as_ptr();
+                // SAFETY: `read_header` checked that each column lies within the map at
+                // an offset aligned for its type, the map is page-aligned, and any bytes
+                // are a valid integer, float or array of them. The map is read-only and
+                // lives as long as the borrow of `self`.
+                unsafe {
+                    #slice_name {
+                        #(
+                            #cols: core::slice::from_raw_parts(
+                                base.add(self.offsets[#indices]).cast::<#types>(),
+                                self.len,
+                            ),
+                        )*
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// The name a column's type is recorded under in the file header, e.g. `f32` or
+/// `[u8; 4]`, for a fixed-size integer, a float or an array of them.
+fn type_name(column: &Column) -> syn::Result<String> {
+    if column.nested.is_some() {
+        return Err(Error::new_spanned(
+            column.ty,
+            "#[soa(mmap)] does not support #[soa(nested)], #[soa(bitpacked)] or #[soa(nullable)] columns",
+        ));
+    }
+    plain_type_name(column.ty).ok_or_else(|| {
+        Error::new_spanned(
+            column.ty,
+            "#[soa(mmap)] supports fixed-size integer and float fields, and arrays of them with a literal length",
+        )
+    })
+}
+
+fn plain_type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            let ident = path.path.get_ident()?.to_string();
+            const PLAIN: &[&str] = &[
+                "i8", "i16", "i32", "i64", "i128", "u8", "u16", "u32", "u64", "u128", "f32", "f64",
+            ];
+            PLAIN.contains(&ident.as_str()).then_some(ident)
+        }
+        Type::Array(array) => {
+            let Expr::Lit(len) = &array.len else {
+                return None;
+            };
+            let Lit::Int(len) = &len.lit else {
+                return None;
+            };
+            let len: usize = len.base10_parse().ok()?;
+            Some(format!("[{}; {}]", plain_type_name(&array.elem)?, len))
+        }
+        Type::Paren(paren) => plain_type_name(&paren.elem),
+        _ => None,
+    }
+}
+
+/// 64-bit FNV-1a, which the file header keeps of the schema.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
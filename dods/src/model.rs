@@ -0,0 +1,593 @@
+use crate::attr::{ContainerAttrs, FieldAttrs, Storage};
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{
+    Data, DeriveInput, Error, Fields, GenericArgument, GenericParam, Generics, Ident, Lifetime,
+    LifetimeParam, Member, PathArguments, Type, TypePath, Visibility, parse_quote,
+};
+
+/// The source struct, resolved into the names and columns the generators work from.
+pub(crate) struct Soa<'i> {
+    pub input: &'i DeriveInput,
+    pub attrs: ContainerAttrs,
+    /// Visibility of the generated types.
+    pub vis: Visibility,
+    pub names: Names,
+    /// The source generics, plus `T: Default` for the `Option<T>` of each nullable
+    /// column of a generic struct, which the column stores in place of a `None`.
+    generics: Generics,
+    /// Lifetime added in front of the source generics on the view types.
+    pub lt: Lifetime,
+    /// The generics with `lt` prepended.
+    pub view_generics: Generics,
+    pub columns: Vec<Column<'i>>,
+    /// Whether the source is a tuple struct.
+    pub tuple: bool,
+}
+
+/// Names of the generated types.
+pub(crate) struct Names {
+    pub soa: Ident,
+    pub ref_: Ident,
+    pub ref_mut: Ident,
+    pub slice: Ident,
+    pub slice_mut: Ident,
+    pub ptr: Ident,
+    pub ptr_mut: Ident,
+    pub iter: Ident,
+    pub iter_mut: Ident,
+    pub into_iter: Ident,
+    /// Parallel iterators generated with the `rayon` feature.
+    pub par_iter: Ident,
+    pub par_iter_mut: Ident,
+    pub par_chunks_mut: Ident,
+    pub aosoa: Ident,
+    pub chunk: Ident,
+    /// The memory-mapped reader generated with `#[soa(mmap)]`.
+    pub mmap: Ident,
+    /// Error of `read_csv`, generated with the `csv` feature.
+    pub csv_error: Ident,
+    /// Prefix of the bitset types behind `#[soa(bitpacked)]` columns, e.g. `FooBitSoA`.
+    pub bit: Ident,
+    /// Prefix of the types behind `#[soa(nullable)]` columns, e.g. `FooNullableSoA<T>`.
+    pub nullable: Ident,
+}
+
+/// One field of the source struct and the column generated for it.
+pub(crate) struct Column<'i> {
+    /// How the field is accessed on the source struct (`x` or `0`).
+    pub member: Member,
+    /// Name of the column on the generated types.
+    pub ident: Ident,
+    pub ty: &'i Type,
+    pub vis: &'i Visibility,
+    /// Group from `#[soa(group = "...")]`, if any.
+    pub group: Option<Ident>,
+    /// The type whose SoA types hold the column: the field type with `#[soa(nested)]`,
+    /// the generated `FooBit` with `#[soa(bitpacked)]`, or `FooNullable<T>` with
+    /// `#[soa(nullable)]`.
+    pub nested: Option<TypePath>,
+    /// Whether the column is a `bool` stored one bit per element.
+    pub bitpacked: bool,
+    /// Whether the column is an `Option<T>` stored as a validity bitmap and a `Vec<T>`.
+    pub nullable: bool,
+    /// Header of the column in CSV files, from `#[soa(rename = "...")]` or the column
+    /// name.
+    pub header: String,
+}
+
+/// Columns sharing a `#[soa(group = "...")]`, which get their own views and iterators.
+pub(crate) struct Group<'s, 'i> {
+    pub name: Ident,
+    pub columns: Vec<&'s Column<'i>>,
+    /// Type names of the group's views, e.g. `FooHotRef` for group `hot` of `Foo`.
+    pub ref_: Ident,
+    pub ref_mut: Ident,
+    pub iter: Ident,
+    pub iter_mut: Ident,
+}
+
+impl<'i> Soa<'i> {
+    pub fn parse(input: &'i DeriveInput) -> syn::Result<Self> {
+        let attrs = ContainerAttrs::parse(&input.attrs)?;
+        let name = &input.ident;
+        let type_name = |chosen: &Option<Ident>, suffix: &str| {
+            chosen
+                .clone()
+                .unwrap_or_else(|| format_ident!("{}{}", name, suffix))
+        };
+        let names = Names {
+            soa: type_name(&attrs.name, "SoA"),
+            ref_: type_name(&attrs.ref_name, "Ref"),
+            ref_mut: type_name(&attrs.ref_mut_name, "RefMut"),
+            slice: type_name(&attrs.slice_name, "Slice"),
+            slice_mut: type_name(&attrs.slice_mut_name, "SliceMut"),
+            ptr: type_name(&attrs.ptr_name, "Ptr"),
+            ptr_mut: type_name(&attrs.ptr_mut_name, "PtrMut"),
+            iter: format_ident!("{}Iter", name),
+            iter_mut: format_ident!("{}IterMut", name),
+            into_iter: format_ident!("{}IntoIter", name),
+            par_iter: format_ident!("{}ParIter", name),
+            par_iter_mut: format_ident!("{}ParIterMut", name),
+            par_chunks_mut: format_ident!("{}ParChunksMut", name),
+            aosoa: format_ident!("{}AoSoA", name),
+            chunk: format_ident!("{}Chunk", name),
+            mmap: format_ident!("{}Mmap", name),
+            csv_error: format_ident!("{}CsvError", name),
+            bit: format_ident!("{}Bit", name),
+            nullable: format_ident!("{}Nullable", name),
+        };
+
+        let fields = match input.data {
+            Data::Struct(ref s) => match s.fields {
+                Fields::Named(_) | Fields::Unnamed(_) if s.fields.is_empty() => {
+                    return Err(Error::new_spanned(
+                        &s.fields,
+                        "DODS SoA requires at least one field",
+                    ));
+                }
+                Fields::Named(_) | Fields::Unnamed(_) => &s.fields,
+                Fields::Unit => {
+                    let semi = &s.semi_token;
+                    return Err(Error::new_spanned(
+                        quote! { #name #semi },
+                        "DODS SoA does not support unit structs",
+                    ));
+                }
+            },
+            Data::Enum(ref e) => {
+                return Err(Error::new(
+                    e.enum_token.span,
+                    "DODS SoA only supports structs, not enums",
+                ));
+            }
+            Data::Union(ref u) => {
+                return Err(Error::new(
+                    u.union_token.span,
+                    "DODS SoA only supports structs, not unions",
+                ));
+            }
+        };
+
+        // Named fields keep their name as the column name, tuple fields become `_0`, `_1`, ...
+        // unless `#[soa(name = "...")]` says otherwise.
+        let columns = fields
+            .iter()
+            .zip(fields.members())
+            .map(|(f, member)| {
+                let field_attrs = FieldAttrs::parse(&f.attrs)?;
+                let ident = match (field_attrs.name, &member) {
+                    (Some(name), _) => name,
+                    (None, Member::Named(ident)) => ident.clone(),
+                    (None, Member::Unnamed(index)) => format_ident!("_{}", index.index),
+                };
+                let nested = if field_attrs.nested as u8
+                    + field_attrs.bitpacked as u8
+                    + field_attrs.nullable as u8
+                    > 1
+                {
+                    return Err(Error::new_spanned(
+                        &f.ty,
+                        "#[soa(nested)], #[soa(bitpacked)] and #[soa(nullable)] cannot be combined",
+                    ));
+                } else if field_attrs.bitpacked {
+                    if !is_bool(&f.ty) {
+                        return Err(Error::new_spanned(
+                            &f.ty,
+                            "#[soa(bitpacked)] only applies to `bool` fields",
+                        ));
+                    }
+                    let bit = &names.bit;
+                    Some(parse_quote!(#bit))
+                } else if field_attrs.nullable {
+                    let Some(inner) = option_inner(&f.ty) else {
+                        return Err(Error::new_spanned(
+                            &f.ty,
+                            "#[soa(nullable)] only applies to `Option<T>` fields",
+                        ));
+                    };
+                    let nullable = &names.nullable;
+                    Some(parse_quote!(#nullable<#inner>))
+                } else if field_attrs.nested {
+                    match &f.ty {
+                        Type::Path(path) if path.qself.is_none() => Some(path.clone()),
+                        ty => {
+                            return Err(Error::new_spanned(
+                                ty,
+                                "#[soa(nested)] needs a struct type such as `Vec3` that also derives SoA",
+                            ));
+                        }
+                    }
+                } else {
+                    None
+                };
+                Ok(Column {
+                    member,
+                    ty: &f.ty,
+                    vis: &f.vis,
+                    group: field_attrs.group,
+                    nested,
+                    bitpacked: field_attrs.bitpacked,
+                    nullable: field_attrs.nullable,
+                    header: field_attrs
+                        .rename
+                        .unwrap_or_else(|| ident.unraw().to_string()),
+                    ident,
+                })
+            })
+            .collect::<syn::Result<Vec<_>>>()?;
+
+        // The other layouts store the elements of every column themselves.
+        if let Some(column) = columns.iter().find(|c| c.nested.is_some()) {
+            let attr = if column.bitpacked {
+                "bitpacked"
+            } else if column.nullable {
+                "nullable"
+            } else {
+                "nested"
+            };
+            let layout = if attrs.storage == Storage::Single {
+                Some("storage = \"single\"")
+            } else if attrs.aosoa.is_some() {
+                Some("aosoa")
+            } else {
+                None
+            };
+            if let Some(layout) = layout {
+                return Err(Error::new_spanned(
+                    column.ty,
+                    format!("#[soa({})] columns are not supported with {}", attr, layout),
+                ));
+            }
+        }
+
+        let mut generics = input.generics.clone();
+        if generics.type_params().next().is_some() && columns.iter().any(|c| c.nullable) {
+            let where_clause = generics.make_where_clause();
+            for column in columns.iter().filter(|c| c.nullable) {
+                let inner = option_inner(column.ty).unwrap_or(column.ty);
+                where_clause
+                    .predicates
+                    .push(parse_quote!(#inner: core::default::Default));
+            }
+        }
+        let lt = fresh_lifetime(&generics);
+        let view_generics = with_lifetime(&generics, &lt);
+        Ok(Soa {
+            input,
+            generics,
+            vis: attrs.vis.clone().unwrap_or_else(|| input.vis.clone()),
+            attrs,
+            names,
+            lt,
+            view_generics,
+            columns,
+            tuple: matches!(fields, Fields::Unnamed(_)),
+        })
+    }
+}
+
+impl Soa<'_> {
+    /// Name of the source struct.
+    pub fn name(&self) -> &Ident {
+        &self.input.ident
+    }
+
+    pub fn generics(&self) -> &Generics {
+        &self.generics
+    }
+
+    /// Names a view type with an elided lifetime, e.g. `FooRef<'_, T>`.
+    pub fn view_ty(&self, view: &Ident) -> TokenStream {
+        self.view_ty_with(view, &Lifetime::new("'_", Span::call_site()))
+    }
+
+    /// Names a view type with the lifetime `lt`, e.g. `FooRef<'b, T>`.
+    pub fn view_ty_with(&self, view: &Ident, lt: &Lifetime) -> TokenStream {
+        let args = generic_args(self.generics());
+        quote! { #view<#lt, #( #args, )*> }
+    }
+
+    /// Whether the columns are hidden behind accessors. The single-buffer storage has
+    /// no per-column fields to expose, so it always is.
+    pub fn private_columns(&self) -> bool {
+        self.attrs.private_columns || self.attrs.storage == Storage::Single
+    }
+
+    /// The column groups in order of first appearance.
+    pub fn groups(&self) -> Vec<Group<'_, '_>> {
+        let mut groups: Vec<Group> = Vec::new();
+        for column in &self.columns {
+            let Some(name) = &column.group else { continue };
+            match groups.iter_mut().find(|g| g.name == *name) {
+                Some(group) => group.columns.push(column),
+                None => {
+                    let camel = upper_camel(&name.unraw().to_string());
+                    let type_name = |suffix| format_ident!("{}{}{}", self.name(), camel, suffix);
+                    groups.push(Group {
+                        name: name.clone(),
+                        columns: vec![column],
+                        ref_: type_name("Ref"),
+                        ref_mut: type_name("RefMut"),
+                        iter: type_name("Iter"),
+                        iter_mut: type_name("IterMut"),
+                    });
+                }
+            }
+        }
+        groups
+    }
+
+    /// The source where-clause extended with `ty(column): bound` for every column,
+    /// where `ty` picks the type that carries the trait, such as `Column::ref_ty`.
+    ///
+    /// The added predicates go through a higher-ranked binder so that a column type
+    /// which does not implement `bound` makes the impl unavailable instead of being
+    /// rejected as a trivially false bound.
+    pub fn where_columns(
+        &self,
+        bound: TokenStream,
+        ty: impl Fn(&Column) -> TokenStream,
+    ) -> TokenStream {
+        let existing = self
+            .generics()
+            .where_clause
+            .iter()
+            .flat_map(|w| w.predicates.iter());
+        let lt = &self.lt;
+        let tys = self.columns.iter().map(ty);
+        quote! { where #( #existing, )* #( for<#lt> #tys: #bound, )* }
+    }
+
+    /// The source where-clause with `predicate` added.
+    pub fn where_with(&self, predicate: TokenStream) -> TokenStream {
+        let existing = self
+            .generics()
+            .where_clause
+            .iter()
+            .flat_map(|w| w.predicates.iter());
+        quote! { where #( #existing, )* #predicate }
+    }
+}
+
+/// The types and expressions that differ between a plain column, held in a `Vec<T>`,
+/// and a nested column, held in the `TSoA` of its type and viewed through `TRef`,
+/// `TSlice` and so on, which mirror what `Vec<T>`, `&T` and `&[T]` offer. Bitpacked
+/// and nullable columns are nested columns of the generated `FooBit` and
+/// `FooNullable<T>` types.
+impl Column<'_> {
+    /// `Vec<T>`, or `TSoA`.
+    pub fn vec_ty(&self) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("SoA", None)
+            .unwrap_or_else(|| quote! { Vec<#ty> })
+    }
+
+    /// `&'a T`, or `TRef<'a>`.
+    pub fn ref_ty(&self, lt: &Lifetime) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("Ref", Some(lt))
+            .unwrap_or_else(|| quote! { &#lt #ty })
+    }
+
+    /// `&'a mut T`, or `TRefMut<'a>`.
+    pub fn ref_mut_ty(&self, lt: &Lifetime) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("RefMut", Some(lt))
+            .unwrap_or_else(|| quote! { &#lt mut #ty })
+    }
+
+    /// `&'a [T]`, or `TSlice<'a>`.
+    pub fn slice_ty(&self, lt: &Lifetime) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("Slice", Some(lt))
+            .unwrap_or_else(|| quote! { &#lt [#ty] })
+    }
+
+    /// `&'a mut [T]`, or `TSliceMut<'a>`.
+    pub fn slice_mut_ty(&self, lt: &Lifetime) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("SliceMut", Some(lt))
+            .unwrap_or_else(|| quote! { &#lt mut [#ty] })
+    }
+
+    /// `*const T`, or `TPtr`.
+    pub fn ptr_ty(&self) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("Ptr", None)
+            .unwrap_or_else(|| quote! { *const #ty })
+    }
+
+    /// `*mut T`, or `TPtrMut`.
+    pub fn ptr_mut_ty(&self) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("PtrMut", None)
+            .unwrap_or_else(|| quote! { *mut #ty })
+    }
+
+    /// `slice::Iter<'a, T>`, or `TIter<'a>`.
+    pub fn iter_ty(&self, lt: &Lifetime) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("Iter", Some(lt))
+            .unwrap_or_else(|| quote! { core::slice::Iter<#lt, #ty> })
+    }
+
+    /// `slice::IterMut<'a, T>`, or `TIterMut<'a>`.
+    pub fn iter_mut_ty(&self, lt: &Lifetime) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("IterMut", Some(lt))
+            .unwrap_or_else(|| quote! { core::slice::IterMut<#lt, #ty> })
+    }
+
+    /// `vec::IntoIter<T>`, or `TIntoIter`.
+    pub fn owned_iter_ty(&self) -> TokenStream {
+        let ty = self.ty;
+        self.nested_ty("IntoIter", None)
+            .unwrap_or_else(|| quote! { std::vec::IntoIter<#ty> })
+    }
+
+    /// Element `index` of the column view `slice`.
+    pub fn index(&self, slice: TokenStream, index: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &#slice[#index] },
+            Some(_) => quote! { #slice.index(#index) },
+        }
+    }
+
+    /// Element `index` of the mutable column view `slice`, borrowed from `slice`.
+    pub fn index_mut(&self, slice: TokenStream, index: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &mut #slice[#index] },
+            Some(_) => quote! { #slice.index_mut(#index) },
+        }
+    }
+
+    /// Element `index` of the mutable column view `slice`, consuming `slice`.
+    pub fn index_mut_owned(&self, slice: TokenStream, index: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &mut #slice[#index] },
+            Some(_) => quote! { #slice.into_index_mut(#index) },
+        }
+    }
+
+    /// The `range` of the column view `slice`.
+    pub fn range(&self, slice: TokenStream, range: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &#slice[#range] },
+            Some(_) => quote! { #slice.slice(#range) },
+        }
+    }
+
+    /// The `range` of the mutable column view `slice`, borrowed from `slice`.
+    pub fn range_mut(&self, slice: TokenStream, range: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &mut #slice[#range] },
+            Some(_) => quote! { #slice.slice_mut(#range) },
+        }
+    }
+
+    /// The `range` of the mutable column view `slice`, consuming `slice`.
+    pub fn range_mut_owned(&self, slice: TokenStream, range: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &mut #slice[#range] },
+            Some(_) => quote! { #slice.into_slice_mut(#range) },
+        }
+    }
+
+    /// The mutable column view `slice` split in two at `mid`, consuming `slice`.
+    pub fn split_at_mut_owned(&self, slice: TokenStream, mid: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { #slice.split_at_mut(#mid) },
+            Some(_) => quote! { #slice.into_split_at_mut(#mid) },
+        }
+    }
+
+    /// The mutable column view `slice` reborrowed as an immutable view.
+    pub fn reborrow(&self, slice: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &*#slice },
+            Some(_) => quote! { #slice.as_slice() },
+        }
+    }
+
+    /// The mutable column view `slice` reborrowed for a shorter lifetime.
+    pub fn reborrow_mut(&self, slice: TokenStream) -> TokenStream {
+        match self.nested {
+            None => quote! { &mut *#slice },
+            Some(_) => quote! { #slice.reborrow() },
+        }
+    }
+
+    /// For a nested column of type `path::Vec3<T>`, the generated type
+    /// `path::Vec3{suffix}<T>`, with `lt` in front of the generic arguments if given.
+    fn nested_ty(&self, suffix: &str, lt: Option<&Lifetime>) -> Option<TokenStream> {
+        let mut path = self.nested.as_ref()?.path.clone();
+        let last = path.segments.last_mut()?;
+        last.ident = format_ident!("{}{}", last.ident, suffix);
+        if let Some(lt) = lt {
+            let lt = GenericArgument::Lifetime(lt.clone());
+            match &mut last.arguments {
+                PathArguments::AngleBracketed(args) => args.args.insert(0, lt),
+                arguments => *arguments = PathArguments::AngleBracketed(parse_quote!(<#lt>)),
+            }
+        }
+        Some(quote! { #path })
+    }
+}
+
+/// Whether `ty` is spelled `bool`.
+fn is_bool(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.qself.is_none() && path.path.is_ident("bool"))
+}
+
+/// The `T` of a type spelled `Option<T>`.
+pub fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let last = path.path.segments.last()?;
+    match &last.arguments {
+        PathArguments::AngleBracketed(args) if last.ident == "Option" && args.args.len() == 1 => {
+            match &args.args[0] {
+                GenericArgument::Type(inner) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `snake_case` to `UpperCamelCase`, for naming types after a group.
+fn upper_camel(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Picks a lifetime for the generated views that does not clash with the
+/// lifetimes already declared on the source struct.
+pub fn fresh_lifetime(generics: &Generics) -> Lifetime {
+    let taken = |name: &str| generics.lifetimes().any(|l| l.lifetime.ident == name);
+    let mut name = String::from("a");
+    while taken(&name) {
+        name.push('_');
+    }
+    Lifetime::new(&format!("'{}", name), Span::call_site())
+}
+
+/// Returns `generics` with `lt` prepended as the first lifetime parameter.
+fn with_lifetime(generics: &Generics, lt: &Lifetime) -> Generics {
+    let mut generics = generics.clone();
+    generics
+        .params
+        .insert(0, GenericParam::Lifetime(LifetimeParam::new(lt.clone())));
+    generics
+}
+
+/// Returns the bare generic arguments (`'b`, `T`, `N`) for naming a type that
+/// shares the source struct's parameters.
+fn generic_args(generics: &Generics) -> Vec<TokenStream> {
+    generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Lifetime(l) => {
+                let lt = &l.lifetime;
+                quote! { #lt }
+            }
+            GenericParam::Type(t) => {
+                let ident = &t.ident;
+                quote! { #ident }
+            }
+            GenericParam::Const(c) => {
+                let ident = &c.ident;
+                quote! { #ident }
+            }
+        })
+        .collect()
+}
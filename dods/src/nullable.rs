@@ -0,0 +1,733 @@
+use crate::model::Soa;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+/// The types behind `#[soa(nullable)]` columns, generated once per struct as
+/// `FooNullableSoA<T>`, `FooNullableSlice<'a, T>` and so on, generic over the `T` of
+/// the `Option<T>` field. Like the bitset types, they mirror the `Vec<Option<T>>` API
+/// the other generators use on a column, so a nullable column is handled like a
+/// nested column of type `FooNullable<T>`.
+///
+/// Arrow-style, a column keeps a `FooBitSoA` validity bitmap next to a dense
+/// `Vec<T>`, with `T::default()` in the place of each `None`. `FooNullableRef` is a
+/// plain `Option<&T>` and `FooNullableRefMut` an `Option<&mut T>`.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    if !soa.columns.iter().any(|c| c.nullable) {
+        return quote! {};
+    }
+    let vis = &soa.vis;
+    let bit = &soa.names.bit;
+    let bits = format_ident!("{}SoA", bit);
+    let bit_slice = format_ident!("{}Slice", bit);
+    let bit_slice_mut = format_ident!("{}SliceMut", bit);
+    let bit_iter = format_ident!("{}Iter", bit);
+    let bit_into_iter = format_ident!("{}IntoIter", bit);
+    let nullable = &soa.names.nullable;
+    let column = format_ident!("{}SoA", nullable);
+    let column_ref = format_ident!("{}Ref", nullable);
+    let column_ref_mut = format_ident!("{}RefMut", nullable);
+    let column_slice = format_ident!("{}Slice", nullable);
+    let column_slice_mut = format_ident!("{}SliceMut", nullable);
+    let column_ptr = format_ident!("{}Ptr", nullable);
+    let column_ptr_mut = format_ident!("{}PtrMut", nullable);
+    let column_iter = format_ident!("{}Iter", nullable);
+    let column_iter_mut = format_ident!("{}IterMut", nullable);
+    let column_into_iter = format_ident!("{}IntoIter", nullable);
+
+    quote! {
+        /// Nullable column of the SoA: a validity bitmap and the values, with
+        /// `T::default()` in the place of each `None`.
+        #vis struct #column<T> {
+            valid: #bits,
+            values: Vec<T>,
+        }
+
+        /// An element of a nullable column.
+        #vis type #column_ref<'a, T> = Option<&'a T>;
+
+        /// A mutable element of a nullable column. Only the value of a `Some` can be
+        /// changed through it; use `replace` on the container to change validity.
+        #vis type #column_ref_mut<'a, T> = Option<&'a mut T>;
+
+        /// Raw const pointer to the values of a nullable column.
+        #vis type #column_ptr<T> = *const T;
+
+        /// Raw mut pointer to the values of a nullable column.
+        #vis type #column_ptr_mut<T> = *mut T;
+
+        /// Immutable view over a range of a nullable column.
+        #vis struct #column_slice<'a, T> {
+            valid: #bit_slice<'a>,
+            values: &'a [T],
+        }
+
+        /// Mutable view over a range of a nullable column.
+        #vis struct #column_slice_mut<'a, T> {
+            valid: #bit_slice_mut<'a>,
+            values: &'a mut [T],
+        }
+
+        /// Iterator over the elements of a nullable column.
+        #vis struct #column_iter<'a, T> {
+            valid: #bit_iter<'a>,
+            values: core::slice::Iter<'a, T>,
+        }
+
+        /// Iterator over mutable references to the elements of a nullable column.
+        #vis struct #column_iter_mut<'a, T> {
+            valid: #bit_iter<'a>,
+            values: core::slice::IterMut<'a, T>,
+        }
+
+        /// Owning iterator over the elements of a nullable column.
+        #vis struct #column_into_iter<T> {
+            valid: #bit_into_iter,
+            values: std::vec::IntoIter<T>,
+        }
+
+        impl<T> #column<T> {
+            /// Creates a new, empty column.
+            pub fn new() -> Self {
+                Self { valid: #bits::new(), values: Vec::new() }
+            }
+
+            /// Creates an empty column with capacity for at least `capacity` elements.
+            pub fn with_capacity(capacity: usize) -> Self {
+                Self {
+                    valid: #bits::with_capacity(capacity),
+                    values: Vec::with_capacity(capacity),
+                }
+            }
+
+            /// Returns the number of elements.
+            pub fn len(&self) -> usize {
+                self.values.len()
+            }
+
+            /// Returns true when the column has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.values.is_empty()
+            }
+
+            /// Returns the number of elements the column can hold without reallocating.
+            pub fn capacity(&self) -> usize {
+                self.values.capacity()
+            }
+
+            /// Returns the number of `None` elements.
+            pub fn null_count(&self) -> usize {
+                self.as_slice().null_count()
+            }
+
+            /// Removes and returns the element at `index`, shifting later elements down.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn remove(&mut self, index: usize) -> Option<T> {
+                let valid = self.valid.remove(index);
+                let value = self.values.remove(index);
+                valid.then_some(value)
+            }
+
+            /// Removes the element at `index` and returns it, moving the last element
+            /// into its place.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn swap_remove(&mut self, index: usize) -> Option<T> {
+                let valid = self.valid.swap_remove(index);
+                let value = self.values.swap_remove(index);
+                valid.then_some(value)
+            }
+
+            /// Removes the last element and returns it, or `None` if the column is
+            /// empty.
+            pub fn pop(&mut self) -> Option<Option<T>> {
+                let value = self.values.pop()?;
+                Some(self.valid.pop()?.then_some(value))
+            }
+
+            /// Reserves capacity for at least `additional` more elements.
+            pub fn reserve(&mut self, additional: usize) {
+                self.valid.reserve(additional);
+                self.values.reserve(additional);
+            }
+
+            /// Reserves capacity for exactly `additional` more elements.
+            pub fn reserve_exact(&mut self, additional: usize) {
+                self.valid.reserve_exact(additional);
+                self.values.reserve_exact(additional);
+            }
+
+            /// Shrinks the capacity as much as possible.
+            pub fn shrink_to_fit(&mut self) {
+                self.valid.shrink_to_fit();
+                self.values.shrink_to_fit();
+            }
+
+            /// Shortens the column to `len` elements.
+            pub fn truncate(&mut self, len: usize) {
+                self.valid.truncate(len);
+                self.values.truncate(len);
+            }
+
+            /// Clears all elements.
+            pub fn clear(&mut self) {
+                self.valid.clear();
+                self.values.clear();
+            }
+
+            /// Moves all elements of `other` to the end of `self`, leaving `other` empty.
+            pub fn append(&mut self, other: &mut Self) {
+                self.valid.append(&mut other.valid);
+                self.values.append(&mut other.values);
+            }
+
+            /// Splits the column at `at`, returning the elements from `at` onwards.
+            ///
+            /// # Panics
+            /// Panics if `at > len`.
+            #[track_caller]
+            pub fn split_off(&mut self, at: usize) -> Self {
+                Self {
+                    valid: self.valid.split_off(at),
+                    values: self.values.split_off(at),
+                }
+            }
+
+            /// Returns a view over all elements.
+            pub fn as_slice(&self) -> #column_slice<'_, T> {
+                #column_slice { valid: self.valid.as_slice(), values: &self.values }
+            }
+
+            /// Returns a mutable view over all elements.
+            pub fn as_mut_slice(&mut self) -> #column_slice_mut<'_, T> {
+                #column_slice_mut { valid: self.valid.as_mut_slice(), values: &mut self.values }
+            }
+
+            /// Returns a raw pointer to the values.
+            pub fn as_ptr(&self) -> #column_ptr<T> {
+                self.values.as_ptr()
+            }
+
+            /// Returns a raw mutable pointer to the values.
+            pub fn as_mut_ptr(&mut self) -> #column_ptr_mut<T> {
+                self.values.as_mut_ptr()
+            }
+        }
+
+        impl<T: core::default::Default> #column<T> {
+            /// Appends an element, storing `T::default()` for a `None`.
+            pub fn push(&mut self, value: Option<T>) {
+                self.valid.push(value.is_some());
+                self.values.push(value.unwrap_or_default());
+            }
+
+            /// Inserts an element at `index`, shifting later elements up.
+            ///
+            /// # Panics
+            /// Panics if `index > len`.
+            #[track_caller]
+            pub fn insert(&mut self, index: usize, value: Option<T>) {
+                self.valid.insert(index, value.is_some());
+                self.values.insert(index, value.unwrap_or_default());
+            }
+
+            /// Replaces the element at `index`, returning the old one.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn replace(&mut self, index: usize, value: Option<T>) -> Option<T> {
+                let valid = self.valid.replace(index, value.is_some());
+                let old = core::mem::replace(&mut self.values[index], value.unwrap_or_default());
+                valid.then_some(old)
+            }
+        }
+
+        impl<T> core::default::Default for #column<T> {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl<T: core::clone::Clone> core::clone::Clone for #column<T> {
+            fn clone(&self) -> Self {
+                Self { valid: self.valid.clone(), values: self.values.clone() }
+            }
+        }
+
+        impl<T> IntoIterator for #column<T> {
+            type Item = Option<T>;
+            type IntoIter = #column_into_iter<T>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                #column_into_iter {
+                    valid: self.valid.into_iter(),
+                    values: self.values.into_iter(),
+                }
+            }
+        }
+
+        impl<'a, T> core::clone::Clone for #column_slice<'a, T> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<'a, T> Copy for #column_slice<'a, T> {}
+
+        impl<'a, T> #column_slice<'a, T> {
+            /// Returns the number of elements in the view.
+            pub fn len(&self) -> usize {
+                self.values.len()
+            }
+
+            /// Returns true when the view has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.values.is_empty()
+            }
+
+            /// Returns a copy of the view.
+            pub fn as_slice(&self) -> Self {
+                *self
+            }
+
+            /// Returns the validity bitmap, with a set bit for each `Some`.
+            pub fn validity(&self) -> #bit_slice<'a> {
+                self.valid
+            }
+
+            /// Returns the values, with `T::default()` in the place of each `None`.
+            pub fn values(&self) -> &'a [T] {
+                self.values
+            }
+
+            /// Returns the number of `None` elements.
+            pub fn null_count(&self) -> usize {
+                self.len() - self.valid.count_ones()
+            }
+
+            /// Returns the element at `index`, or `None` if out of bounds.
+            pub fn get(&self, index: usize) -> Option<Option<&'a T>> {
+                let valid = *self.valid.get(index)?;
+                Some(valid.then(|| &self.values[index]))
+            }
+
+            /// Returns the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn index(&self, index: usize) -> Option<&'a T> {
+                let values = self.values;
+                self.valid.index(index).then(|| &values[index])
+            }
+
+            /// Returns the elements in `range`.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn slice(&self, range: core::ops::Range<usize>) -> Self {
+                Self {
+                    valid: self.valid.slice(range.clone()),
+                    values: &self.values[range],
+                }
+            }
+
+            /// Splits the view into the elements before `mid` and the elements from
+            /// `mid` on.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn split_at(&self, mid: usize) -> (Self, Self) {
+                let (valid_head, valid_tail) = self.valid.split_at(mid);
+                let (head, tail) = self.values.split_at(mid);
+                (
+                    Self { valid: valid_head, values: head },
+                    Self { valid: valid_tail, values: tail },
+                )
+            }
+
+            /// Returns an iterator over views of `chunk_size` elements.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            #[track_caller]
+            pub fn chunks(&self, chunk_size: usize) -> impl DoubleEndedIterator<Item = Self> + ExactSizeIterator + use<'a, T> {
+                self.valid
+                    .chunks(chunk_size)
+                    .zip(self.values.chunks(chunk_size))
+                    .map(|(valid, values)| Self { valid, values })
+            }
+
+            /// Returns an iterator over the elements.
+            pub fn iter(&self) -> #column_iter<'a, T> {
+                #column_iter { valid: self.valid.iter(), values: self.values.iter() }
+            }
+
+            /// Returns an iterator over the index and value of each `Some`, which skips
+            /// a word of `None`s at a time.
+            pub fn iter_valid(&self) -> impl Iterator<Item = (usize, &'a T)> + use<'a, T> {
+                let values = self.values;
+                self.valid.iter_ones().map(move |index| (index, &values[index]))
+            }
+        }
+
+        impl<'a, T: core::fmt::Debug> core::fmt::Debug for #column_slice<'a, T> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_list().entries(self.iter()).finish()
+            }
+        }
+
+        impl<'a, T: PartialEq> PartialEq for #column_slice<'a, T> {
+            fn eq(&self, other: &Self) -> bool {
+                self.len() == other.len() && self.iter().eq(other.iter())
+            }
+        }
+
+        impl<'a, T: Eq> Eq for #column_slice<'a, T> {}
+
+        impl<'a, T: core::hash::Hash> core::hash::Hash for #column_slice<'a, T> {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                self.len().hash(state);
+                self.iter().for_each(|element| element.hash(state));
+            }
+        }
+
+        impl<'a, T> IntoIterator for #column_slice<'a, T> {
+            type Item = Option<&'a T>;
+            type IntoIter = #column_iter<'a, T>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl<'a, T> #column_slice_mut<'a, T> {
+            /// Returns the number of elements in the view.
+            pub fn len(&self) -> usize {
+                self.values.len()
+            }
+
+            /// Returns true when the view has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.values.is_empty()
+            }
+
+            /// Returns an immutable view over the same elements.
+            pub fn as_slice(&self) -> #column_slice<'_, T> {
+                #column_slice { valid: self.valid.as_slice(), values: &*self.values }
+            }
+
+            /// Reborrows the view for a shorter lifetime.
+            pub fn reborrow(&mut self) -> #column_slice_mut<'_, T> {
+                #column_slice_mut { valid: self.valid.reborrow(), values: &mut *self.values }
+            }
+
+            /// Returns the validity bitmap, with a set bit for each `Some`.
+            pub fn validity(&self) -> #bit_slice<'_> {
+                self.valid.as_slice()
+            }
+
+            /// Returns the values, with `T::default()` in the place of each `None`.
+            pub fn values(&self) -> &[T] {
+                self.values
+            }
+
+            /// Returns the values as a mutable slice. Writing to the value of a `None`
+            /// leaves it `None`.
+            pub fn values_mut(&mut self) -> &mut [T] {
+                self.values
+            }
+
+            /// Returns the number of `None` elements.
+            pub fn null_count(&self) -> usize {
+                self.as_slice().null_count()
+            }
+
+            /// Returns the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn index(&self, index: usize) -> Option<&T> {
+                self.as_slice().index(index)
+            }
+
+            /// Returns the element at `index` with a mutable reference to its value.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn index_mut(&mut self, index: usize) -> Option<&mut T> {
+                self.reborrow().into_index_mut(index)
+            }
+
+            /// Turns the view into the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            #[track_caller]
+            pub fn into_index_mut(self, index: usize) -> Option<&'a mut T> {
+                let values = self.values;
+                self.valid.index(index).then(|| &mut values[index])
+            }
+
+            /// Returns the elements in `range`.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn slice(&self, range: core::ops::Range<usize>) -> #column_slice<'_, T> {
+                self.as_slice().slice(range)
+            }
+
+            /// Returns the elements in `range` as a mutable view.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn slice_mut(&mut self, range: core::ops::Range<usize>) -> #column_slice_mut<'_, T> {
+                self.reborrow().into_slice_mut(range)
+            }
+
+            /// Turns the view into a mutable view of the elements in `range`.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the view.
+            #[track_caller]
+            pub fn into_slice_mut(self, range: core::ops::Range<usize>) -> Self {
+                Self {
+                    valid: self.valid.into_slice_mut(range.clone()),
+                    values: &mut self.values[range],
+                }
+            }
+
+            /// Splits the view into the elements before `mid` and the elements from
+            /// `mid` on.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn split_at(&self, mid: usize) -> (#column_slice<'_, T>, #column_slice<'_, T>) {
+                self.as_slice().split_at(mid)
+            }
+
+            /// Splits the view into mutable views of the elements before `mid` and
+            /// the elements from `mid` on.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn split_at_mut(&mut self, mid: usize) -> (#column_slice_mut<'_, T>, #column_slice_mut<'_, T>) {
+                let (valid_head, valid_tail) = self.valid.split_at_mut(mid);
+                let (head, tail) = self.values.split_at_mut(mid);
+                (
+                    #column_slice_mut { valid: valid_head, values: head },
+                    #column_slice_mut { valid: valid_tail, values: tail },
+                )
+            }
+
+            /// Turns the view into mutable views of the elements before `mid` and the
+            /// elements from `mid` on, keeping the full lifetime of the view.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            #[track_caller]
+            pub fn into_split_at_mut(self, mid: usize) -> (Self, Self) {
+                let (valid_head, valid_tail) = self.valid.into_split_at_mut(mid);
+                let (head, tail) = self.values.split_at_mut(mid);
+                (
+                    Self { valid: valid_head, values: head },
+                    Self { valid: valid_tail, values: tail },
+                )
+            }
+
+            /// Returns an iterator over views of `chunk_size` elements.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            #[track_caller]
+            pub fn chunks(&self, chunk_size: usize) -> impl DoubleEndedIterator<Item = #column_slice<'_, T>> + ExactSizeIterator {
+                self.as_slice().chunks(chunk_size)
+            }
+
+            /// Returns an iterator over mutable views of `chunk_size` elements.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            #[track_caller]
+            pub fn chunks_mut(&mut self, chunk_size: usize) -> impl DoubleEndedIterator<Item = #column_slice_mut<'_, T>> + ExactSizeIterator {
+                self.valid
+                    .chunks_mut(chunk_size)
+                    .zip(self.values.chunks_mut(chunk_size))
+                    .map(|(valid, values)| #column_slice_mut { valid, values })
+            }
+
+            /// Swaps the elements at `a` and `b`.
+            ///
+            /// # Panics
+            /// Panics if `a` or `b` is out of bounds.
+            #[track_caller]
+            pub fn swap(&mut self, a: usize, b: usize) {
+                self.valid.swap(a, b);
+                self.values.swap(a, b);
+            }
+
+            /// Returns an iterator over the elements.
+            pub fn iter(&self) -> #column_iter<'_, T> {
+                self.as_slice().iter()
+            }
+
+            /// Returns an iterator over mutable references to the elements.
+            pub fn iter_mut(&mut self) -> #column_iter_mut<'_, T> {
+                self.reborrow().into_iter()
+            }
+
+            /// Returns an iterator over the index and value of each `Some`, which skips
+            /// a word of `None`s at a time.
+            pub fn iter_valid(&self) -> impl Iterator<Item = (usize, &T)> {
+                self.as_slice().iter_valid()
+            }
+
+            /// Returns an iterator over the index and a mutable reference to the value
+            /// of each `Some`, which skips a word of `None`s at a time.
+            pub fn iter_valid_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+                let mut rest = &mut *self.values;
+                let mut next = 0;
+                self.valid.as_slice().iter_ones().map(move |index| {
+                    let (value, tail) = core::mem::take(&mut rest)[index - next..]
+                        .split_first_mut()
+                        .unwrap();
+                    rest = tail;
+                    next = index + 1;
+                    (index, value)
+                })
+            }
+        }
+
+        impl<'a, T: core::fmt::Debug> core::fmt::Debug for #column_slice_mut<'a, T> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Debug::fmt(&self.as_slice(), f)
+            }
+        }
+
+        impl<'a, T: PartialEq> PartialEq for #column_slice_mut<'a, T> {
+            fn eq(&self, other: &Self) -> bool {
+                self.as_slice() == other.as_slice()
+            }
+        }
+
+        impl<'a, T: Eq> Eq for #column_slice_mut<'a, T> {}
+
+        impl<'a, T: core::hash::Hash> core::hash::Hash for #column_slice_mut<'a, T> {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                self.as_slice().hash(state);
+            }
+        }
+
+        impl<'a, T> IntoIterator for #column_slice_mut<'a, T> {
+            type Item = Option<&'a mut T>;
+            type IntoIter = #column_iter_mut<'a, T>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                #column_iter_mut {
+                    valid: self.valid.into_slice().iter(),
+                    values: self.values.iter_mut(),
+                }
+            }
+        }
+
+        impl<'a, T> Iterator for #column_iter<'a, T> {
+            type Item = Option<&'a T>;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                let valid = *self.valid.next()?;
+                let value = self.values.next()?;
+                Some(valid.then_some(value))
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.values.size_hint()
+            }
+        }
+
+        impl<'a, T> DoubleEndedIterator for #column_iter<'a, T> {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                let valid = *self.valid.next_back()?;
+                let value = self.values.next_back()?;
+                Some(valid.then_some(value))
+            }
+        }
+
+        impl<'a, T> ExactSizeIterator for #column_iter<'a, T> {}
+
+        impl<'a, T> core::iter::FusedIterator for #column_iter<'a, T> {}
+
+        impl<'a, T> Iterator for #column_iter_mut<'a, T> {
+            type Item = Option<&'a mut T>;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                let valid = *self.valid.next()?;
+                let value = self.values.next()?;
+                Some(valid.then_some(value))
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.values.size_hint()
+            }
+        }
+
+        impl<'a, T> DoubleEndedIterator for #column_iter_mut<'a, T> {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                let valid = *self.valid.next_back()?;
+                let value = self.values.next_back()?;
+                Some(valid.then_some(value))
+            }
+        }
+
+        impl<'a, T> ExactSizeIterator for #column_iter_mut<'a, T> {}
+
+        impl<'a, T> core::iter::FusedIterator for #column_iter_mut<'a, T> {}
+
+        impl<T> Iterator for #column_into_iter<T> {
+            type Item = Option<T>;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                let valid = self.valid.next()?;
+                let value = self.values.next()?;
+                Some(valid.then_some(value))
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.values.size_hint()
+            }
+        }
+
+        impl<T> DoubleEndedIterator for #column_into_iter<T> {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                let valid = self.valid.next_back()?;
+                let value = self.values.next_back()?;
+                Some(valid.then_some(value))
+            }
+        }
+
+        impl<T> ExactSizeIterator for #column_into_iter<T> {}
+
+        impl<T> core::iter::FusedIterator for #column_into_iter<T> {}
+    }
+}
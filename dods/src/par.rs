@@ -0,0 +1,548 @@
+use crate::model::{Column, Soa, fresh_lifetime};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, Generics, Ident, Lifetime, Visibility};
+
+/// Inherent methods of `FooSoA` emitted here.
+pub(crate) const METHODS: &[&str] = &["par_iter", "par_iter_mut", "par_chunks_mut", "par_sort_by"];
+
+/// Rayon support for `#[soa(rayon)]` structs, behind the `rayon` feature of this
+/// crate: `FooSlice` and `FooSliceMut` implement `IntoParallelIterator` by zipping
+/// one indexed parallel iterator per column, and `par_iter`, `par_iter_mut`,
+/// `par_chunks_mut` and `par_sort_by` on `FooSoA` and `FooSliceMut` build on those.
+///
+/// The generated code names `::rayon` directly, so the crate deriving `SoA` needs it
+/// as a dependency. Nested, bitpacked and nullable columns go through the
+/// `IntoParallelIterator` impls of their own views, which their derives provide, so
+/// the type of a nested field needs `#[soa(rayon)]` as well.
+pub(crate) fn expand(soa: &Soa) -> syn::Result<TokenStream> {
+    if !soa.attrs.rayon {
+        return Ok(quote! {});
+    }
+    if !cfg!(feature = "rayon") {
+        return Err(Error::new_spanned(
+            soa.name(),
+            "#[soa(rayon)] needs the `rayon` feature of dods",
+        ));
+    }
+    let soa_name = &soa.names.soa;
+    let ref_name = &soa.names.ref_;
+    let ref_mut_name = &soa.names.ref_mut;
+    let slice_name = &soa.names.slice;
+    let slice_mut_name = &soa.names.slice_mut;
+    let (impl_generics, ty_generics, _) = soa.generics().split_for_impl();
+    let view_generics = &soa.view_generics;
+    let (_, view_ty_generics, _) = view_generics.split_for_impl();
+    let lt = &soa.lt;
+    // The views already take `lt`, so their methods bound their reborrows with another.
+    let reborrow_lt = fresh_lifetime(view_generics);
+
+    let slice_impl = into_par_iter_impl(
+        soa,
+        slice_name,
+        &soa.names.par_iter,
+        ref_name,
+        |c| c.slice_ty(lt),
+        |c| c.ref_ty(lt),
+    );
+    let slice_mut_impl = into_par_iter_impl(
+        soa,
+        slice_mut_name,
+        &soa.names.par_iter_mut,
+        ref_mut_name,
+        |c| c.slice_mut_ty(lt),
+        |c| c.ref_mut_ty(lt),
+    );
+
+    let soa_ref = soa.view_ty(ref_name);
+    let soa_ref_mut = soa.view_ty(ref_mut_name);
+    let soa_slice_mut = soa.view_ty(slice_mut_name);
+    let par_where = |view_lt: &Lifetime| {
+        let slice = soa.view_ty_with(slice_name, view_lt);
+        let bound = into_par_iter_bound(soa.view_ty_with(ref_name, view_lt));
+        soa.where_with(quote! { for<#view_lt> #slice: #bound })
+    };
+    let par_mut_where = |view_lt: &Lifetime| {
+        let slice_mut = soa.view_ty_with(slice_mut_name, view_lt);
+        let bound = into_par_iter_bound(soa.view_ty_with(ref_mut_name, view_lt));
+        soa.where_with(quote! { for<#view_lt> #slice_mut: #bound + Send })
+    };
+    let sort_where = |view_lt: &Lifetime| {
+        let slice = soa.view_ty_with(slice_name, view_lt);
+        soa.where_with(quote! { for<#view_lt> #slice: Sync })
+    };
+    let slice_par_where = {
+        let slice = soa.view_ty_with(slice_name, lt);
+        let bound = into_par_iter_bound(soa.view_ty_with(ref_name, lt));
+        soa.where_with(quote! { #slice: #bound })
+    };
+    let (soa_par_where, soa_par_mut_where, soa_sort_where) =
+        (par_where(lt), par_mut_where(lt), sort_where(lt));
+    let (view_par_where, view_par_mut_where, view_sort_where) = (
+        par_where(&reborrow_lt),
+        par_mut_where(&reborrow_lt),
+        sort_where(&reborrow_lt),
+    );
+
+    let columns = column_impls(soa);
+    let chunks = par_chunks_mut_impl(soa);
+    let par_chunks_mut_name = &soa.names.par_chunks_mut;
+
+    Ok(quote! {
+        #slice_impl
+        #slice_mut_impl
+        #columns
+        #chunks
+
+        impl #impl_generics #soa_name #ty_generics #soa_par_where {
+            /// Returns a parallel iterator over immutable references to each element.
+            pub fn par_iter(&self) -> impl ::rayon::iter::IndexedParallelIterator<Item = #soa_ref> {
+                ::rayon::iter::IntoParallelIterator::into_par_iter(self.as_slice())
+            }
+        }
+
+        impl #impl_generics #soa_name #ty_generics #soa_par_mut_where {
+            /// Returns a parallel iterator over mutable references to each element.
+            pub fn par_iter_mut(&mut self) -> impl ::rayon::iter::IndexedParallelIterator<Item = #soa_ref_mut> {
+                ::rayon::iter::IntoParallelIterator::into_par_iter(self.as_mut_slice())
+            }
+
+            /// Returns a parallel iterator over mutable `chunk_size`-element views of
+            /// the SoA.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            pub fn par_chunks_mut(
+                &mut self,
+                chunk_size: usize,
+            ) -> impl ::rayon::iter::IndexedParallelIterator<Item = #soa_slice_mut> {
+                self.as_mut_slice().into_par_chunks_mut(chunk_size)
+            }
+        }
+
+        impl #impl_generics #soa_name #ty_generics #soa_sort_where {
+            /// Sorts the SoA with a comparator, comparing on the rayon thread pool.
+            /// The sort is stable.
+            pub fn par_sort_by<F>(&mut self, f: F)
+            where
+                F: Fn(#soa_ref, #soa_ref) -> core::cmp::Ordering + Sync,
+            {
+                self.as_mut_slice().par_sort_by(f);
+            }
+        }
+
+        impl #view_generics #slice_name #view_ty_generics #slice_par_where {
+            /// Returns a parallel iterator over immutable references to each element.
+            pub fn par_iter(&self) -> impl ::rayon::iter::IndexedParallelIterator<Item = #ref_name #view_ty_generics> {
+                ::rayon::iter::IntoParallelIterator::into_par_iter(*self)
+            }
+        }
+
+        impl #view_generics #slice_mut_name #view_ty_generics #view_par_where {
+            /// Returns a parallel iterator over immutable references to each element.
+            pub fn par_iter(&self) -> impl ::rayon::iter::IndexedParallelIterator<Item = #soa_ref> {
+                ::rayon::iter::IntoParallelIterator::into_par_iter(self.as_slice())
+            }
+        }
+
+        impl #view_generics #slice_mut_name #view_ty_generics #view_par_mut_where {
+            /// Returns a parallel iterator over mutable references to each element.
+            pub fn par_iter_mut(&mut self) -> impl ::rayon::iter::IndexedParallelIterator<Item = #soa_ref_mut> {
+                ::rayon::iter::IntoParallelIterator::into_par_iter(self.reborrow())
+            }
+
+            /// Returns a parallel iterator over mutable `chunk_size`-element views of
+            /// the slice.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            pub fn par_chunks_mut(
+                &mut self,
+                chunk_size: usize,
+            ) -> impl ::rayon::iter::IndexedParallelIterator<Item = #soa_slice_mut> {
+                self.reborrow().into_par_chunks_mut(chunk_size)
+            }
+
+            /// Turns the view into a parallel iterator over `chunk_size`-element views.
+            fn into_par_chunks_mut(self, chunk_size: usize) -> #par_chunks_mut_name #view_ty_generics {
+                assert!(chunk_size != 0, "chunk size must be non-zero");
+                #par_chunks_mut_name::new(self, chunk_size)
+            }
+        }
+
+        impl #view_generics #slice_mut_name #view_ty_generics #view_sort_where {
+            /// Sorts the slice with a comparator, comparing on the rayon thread pool.
+            /// The sort is stable.
+            pub fn par_sort_by<F>(&mut self, f: F)
+            where
+                F: Fn(#soa_ref, #soa_ref) -> core::cmp::Ordering + Sync,
+            {
+                let mut permutation: Vec<usize> = (0..self.len()).collect();
+                let slice = self.as_slice();
+                ::rayon::slice::ParallelSliceMut::par_sort_by(&mut permutation[..], |j, k| {
+                    f(slice.index(*j), slice.index(*k))
+                });
+
+                self.apply_permutation(permutation);
+            }
+        }
+    })
+}
+
+/// `FooParChunksMut`, the parallel iterator behind `par_chunks_mut`. It is its own
+/// rayon `Producer`, and cuts the chunks off the view only as rayon splits the work
+/// and drains the halves, so no chunk is cut before a thread is ready for it.
+fn par_chunks_mut_impl(soa: &Soa) -> TokenStream {
+    let vis = &soa.vis;
+    let slice_mut_name = &soa.names.slice_mut;
+    let chunks_name = &soa.names.par_chunks_mut;
+    let view_generics = &soa.view_generics;
+    let (impl_generics, view_ty_generics, where_clause) = view_generics.split_for_impl();
+    let item = quote! { #slice_mut_name #view_ty_generics };
+    let send_where = soa.where_with(quote! { #item: Send });
+    let doc = format!(
+        "Parallel iterator over mutable `chunk_size`-element views of a `{}`.",
+        slice_mut_name
+    );
+
+    quote! {
+        #[doc = #doc]
+        #vis struct #chunks_name #view_generics #where_clause {
+            /// The chunks still to hand out, `None` once there are none left.
+            slice: Option<#item>,
+            chunk_size: usize,
+        }
+
+        impl #impl_generics #chunks_name #view_ty_generics #where_clause {
+            fn new(slice: #item, chunk_size: usize) -> Self {
+                let slice = (!slice.is_empty()).then_some(slice);
+                Self { slice, chunk_size }
+            }
+        }
+
+        impl #impl_generics Iterator for #chunks_name #view_ty_generics #where_clause {
+            type Item = #item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let slice = self.slice.take()?;
+                let mid = self.chunk_size.min(slice.len());
+                let (head, tail) = slice.into_split_at_mut(mid);
+                *self = Self::new(tail, self.chunk_size);
+                Some(head)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let len = self.slice.as_ref().map_or(0, |s| s.len().div_ceil(self.chunk_size));
+                (len, Some(len))
+            }
+        }
+
+        impl #impl_generics DoubleEndedIterator for #chunks_name #view_ty_generics #where_clause {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                let slice = self.slice.take()?;
+                let len = slice.len();
+                let last = match len % self.chunk_size {
+                    0 => self.chunk_size,
+                    rem => rem,
+                };
+                let (head, tail) = slice.into_split_at_mut(len - last);
+                *self = Self::new(head, self.chunk_size);
+                Some(tail)
+            }
+        }
+
+        impl #impl_generics ExactSizeIterator for #chunks_name #view_ty_generics #where_clause {}
+
+        impl #impl_generics ::rayon::iter::plumbing::Producer for #chunks_name #view_ty_generics #send_where {
+            type Item = #item;
+            type IntoIter = Self;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self
+            }
+
+            fn split_at(self, index: usize) -> (Self, Self) {
+                let chunk_size = self.chunk_size;
+                match self.slice {
+                    Some(slice) => {
+                        let mid = index.saturating_mul(chunk_size).min(slice.len());
+                        let (head, tail) = slice.into_split_at_mut(mid);
+                        (Self::new(head, chunk_size), Self::new(tail, chunk_size))
+                    }
+                    None => (Self { slice: None, chunk_size }, Self { slice: None, chunk_size }),
+                }
+            }
+        }
+
+        impl #impl_generics ::rayon::iter::ParallelIterator for #chunks_name #view_ty_generics #send_where {
+            type Item = #item;
+
+            fn drive_unindexed<C>(self, consumer: C) -> C::Result
+            where
+                C: ::rayon::iter::plumbing::UnindexedConsumer<Self::Item>,
+            {
+                ::rayon::iter::plumbing::bridge(self, consumer)
+            }
+
+            fn opt_len(&self) -> Option<usize> {
+                Some(ExactSizeIterator::len(self))
+            }
+        }
+
+        impl #impl_generics ::rayon::iter::IndexedParallelIterator for #chunks_name #view_ty_generics #send_where {
+            fn drive<C>(self, consumer: C) -> C::Result
+            where
+                C: ::rayon::iter::plumbing::Consumer<Self::Item>,
+            {
+                ::rayon::iter::plumbing::bridge(self, consumer)
+            }
+
+            fn len(&self) -> usize {
+                ExactSizeIterator::len(self)
+            }
+
+            fn with_producer<CB>(self, callback: CB) -> CB::Output
+            where
+                CB: ::rayon::iter::plumbing::ProducerCallback<Self::Item>,
+            {
+                callback.callback(self)
+            }
+        }
+    }
+}
+
+/// `IntoParallelIterator` for one of the views, with `iter_name` as its `Iter`: a
+/// wrapper around the zip of the parallel iterators of the columns, mapped to
+/// `item_name` through a function pointer. The wrapper keeps the field types, which
+/// may be private, out of the associated type.
+fn into_par_iter_impl(
+    soa: &Soa,
+    view_name: &Ident,
+    iter_name: &Ident,
+    item_name: &Ident,
+    column_view: impl Fn(&Column) -> TokenStream,
+    column_item: impl Fn(&Column) -> TokenStream,
+) -> TokenStream {
+    let vis = &soa.vis;
+    let view_generics = &soa.view_generics;
+    let (impl_generics, view_ty_generics, _) = view_generics.split_for_impl();
+    let views: Vec<_> = soa.columns.iter().map(&column_view).collect();
+    let items: Vec<_> = soa.columns.iter().map(&column_item).collect();
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let item = quote! { #item_name #view_ty_generics };
+    let bounds = views.iter().zip(&items).map(|(view, item)| {
+        let bound = into_par_iter_bound(item.clone());
+        quote! { #view: #bound, #item: Send }
+    });
+    let where_clause = soa.where_with(quote! { #( #bounds, )* });
+
+    let (first, rest) = (&views[0], &views[1..]);
+    let zip_ty = rest.iter().fold(
+        quote! { <#first as ::rayon::iter::IntoParallelIterator>::Iter },
+        |acc, view| {
+            quote! { ::rayon::iter::Zip<#acc, <#view as ::rayon::iter::IntoParallelIterator>::Iter> }
+        },
+    );
+    let (first, rest) = (&items[0], &items[1..]);
+    let tuple_ty = rest
+        .iter()
+        .fold(quote! { #first }, |acc, item| quote! { (#acc, #item) });
+    let (first, rest) = (cols[0], &cols[1..]);
+    let pattern = rest
+        .iter()
+        .fold(quote! { #first }, |acc, col| quote! { (#acc, #col) });
+    let zipped = rest.iter().fold(
+        quote! { ::rayon::iter::IntoParallelIterator::into_par_iter(self.#first) },
+        |acc, col| quote! { ::rayon::iter::IndexedParallelIterator::zip(#acc, self.#col) },
+    );
+    let doc = format!("Parallel iterator over the elements of a `{}`.", view_name);
+
+    quote! {
+        #[doc = #doc]
+        #[allow(private_bounds)]
+        #vis struct #iter_name #view_generics #where_clause {
+            inner: ::rayon::iter::Map<#zip_ty, fn(#tuple_ty) -> #item>,
+        }
+
+        impl #impl_generics ::rayon::iter::IntoParallelIterator for #view_name #view_ty_generics #where_clause {
+            type Item = #item;
+            type Iter = #iter_name #view_ty_generics;
+
+            fn into_par_iter(self) -> Self::Iter {
+                let build: fn(#tuple_ty) -> #item = |#pattern| #item_name { #( #cols, )* };
+                #iter_name { inner: ::rayon::iter::ParallelIterator::map(#zipped, build) }
+            }
+        }
+
+        impl #impl_generics ::rayon::iter::ParallelIterator for #iter_name #view_ty_generics #where_clause {
+            type Item = #item;
+
+            fn drive_unindexed<C>(self, consumer: C) -> C::Result
+            where
+                C: ::rayon::iter::plumbing::UnindexedConsumer<Self::Item>,
+            {
+                self.inner.drive_unindexed(consumer)
+            }
+
+            fn opt_len(&self) -> Option<usize> {
+                self.inner.opt_len()
+            }
+        }
+
+        impl #impl_generics ::rayon::iter::IndexedParallelIterator for #iter_name #view_ty_generics #where_clause {
+            fn drive<C>(self, consumer: C) -> C::Result
+            where
+                C: ::rayon::iter::plumbing::Consumer<Self::Item>,
+            {
+                self.inner.drive(consumer)
+            }
+
+            fn len(&self) -> usize {
+                self.inner.len()
+            }
+
+            fn with_producer<CB>(self, callback: CB) -> CB::Output
+            where
+                CB: ::rayon::iter::plumbing::ProducerCallback<Self::Item>,
+            {
+                self.inner.with_producer(callback)
+            }
+        }
+    }
+}
+
+/// `IntoParallelIterator` for the views of the bitpacked and nullable column types,
+/// whose elements have no parallel slice iterators to zip. Each view is a rayon
+/// `Producer` instead, split in two with `split_at` and drained with its own iterator.
+fn column_impls(soa: &Soa) -> TokenStream {
+    let vis = &soa.vis;
+    let mut impls = TokenStream::new();
+    if soa.columns.iter().any(|c| c.bitpacked || c.nullable) {
+        let bit = &soa.names.bit;
+        let name = |suffix| format_ident!("{}{}", bit, suffix);
+        impls.extend(producer_impls(
+            vis,
+            quote! { <'a> },
+            name("Slice"),
+            name("ParIter"),
+            quote! { &'a bool },
+            quote! { Self::split_at(&self, index) },
+        ));
+        let bit_ref_mut = name("RefMut");
+        impls.extend(producer_impls(
+            vis,
+            quote! { <'a> },
+            name("SliceMut"),
+            name("ParIterMut"),
+            quote! { #bit_ref_mut<'a> },
+            quote! { Self::into_split_at_mut(self, index) },
+        ));
+    }
+    if soa.columns.iter().any(|c| c.nullable) {
+        let nullable = &soa.names.nullable;
+        let name = |suffix| format_ident!("{}{}", nullable, suffix);
+        impls.extend(producer_impls(
+            vis,
+            quote! { <'a, T: Sync> },
+            name("Slice"),
+            name("ParIter"),
+            quote! { Option<&'a T> },
+            quote! { Self::split_at(&self, index) },
+        ));
+        impls.extend(producer_impls(
+            vis,
+            quote! { <'a, T: Send> },
+            name("SliceMut"),
+            name("ParIterMut"),
+            quote! { Option<&'a mut T> },
+            quote! { Self::into_split_at_mut(self, index) },
+        ));
+    }
+    impls
+}
+
+/// Makes the view `view_name` a rayon `Producer` over `item`s, and wraps it in
+/// `iter_name` as its indexed parallel iterator.
+fn producer_impls(
+    vis: &Visibility,
+    generics: TokenStream,
+    view_name: Ident,
+    iter_name: Ident,
+    item: TokenStream,
+    split: TokenStream,
+) -> TokenStream {
+    let generics: Generics = syn::parse2(generics).unwrap();
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let doc = format!("Parallel iterator over the elements of a `{}`.", view_name);
+    quote! {
+        #[doc = #doc]
+        #vis struct #iter_name #impl_generics {
+            view: #view_name #ty_generics,
+        }
+
+        impl #impl_generics ::rayon::iter::plumbing::Producer for #view_name #ty_generics {
+            type Item = #item;
+            type IntoIter = <Self as IntoIterator>::IntoIter;
+
+            fn into_iter(self) -> Self::IntoIter {
+                IntoIterator::into_iter(self)
+            }
+
+            fn split_at(self, index: usize) -> (Self, Self) {
+                #split
+            }
+        }
+
+        impl #impl_generics ::rayon::iter::IntoParallelIterator for #view_name #ty_generics {
+            type Item = #item;
+            type Iter = #iter_name #ty_generics;
+
+            fn into_par_iter(self) -> Self::Iter {
+                #iter_name { view: self }
+            }
+        }
+
+        impl #impl_generics ::rayon::iter::ParallelIterator for #iter_name #ty_generics {
+            type Item = #item;
+
+            fn drive_unindexed<C>(self, consumer: C) -> C::Result
+            where
+                C: ::rayon::iter::plumbing::UnindexedConsumer<Self::Item>,
+            {
+                ::rayon::iter::plumbing::bridge(self, consumer)
+            }
+
+            fn opt_len(&self) -> Option<usize> {
+                Some(self.view.len())
+            }
+        }
+
+        impl #impl_generics ::rayon::iter::IndexedParallelIterator for #iter_name #ty_generics {
+            fn drive<C>(self, consumer: C) -> C::Result
+            where
+                C: ::rayon::iter::plumbing::Consumer<Self::Item>,
+            {
+                ::rayon::iter::plumbing::bridge(self, consumer)
+            }
+
+            fn len(&self) -> usize {
+                self.view.len()
+            }
+
+            fn with_producer<CB>(self, callback: CB) -> CB::Output
+            where
+                CB: ::rayon::iter::plumbing::ProducerCallback<Self::Item>,
+            {
+                callback.callback(self.view)
+            }
+        }
+    }
+}
+
+/// `IntoParallelIterator<Item = item>` with an indexed `Iter`, so the iterators can be
+/// zipped.
+fn into_par_iter_bound(item: TokenStream) -> TokenStream {
+    quote! {
+        ::rayon::iter::IntoParallelIterator<Item = #item, Iter: ::rayon::iter::IndexedParallelIterator>
+    }
+}
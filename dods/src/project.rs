@@ -0,0 +1,93 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Error, Expr, Ident, Token};
+
+/// Input of `soa_project!`: `soa => a, mut b, c`.
+pub(crate) struct Projection {
+    soa: Expr,
+    columns: Punctuated<ProjectedColumn, Token![,]>,
+}
+
+/// A column to project, borrowed mutably when written as `mut name`.
+struct ProjectedColumn {
+    mutability: Option<Token![mut]>,
+    ident: Ident,
+}
+
+impl Parse for Projection {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let soa = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let columns = Punctuated::parse_terminated(input)?;
+        if columns.is_empty() {
+            return Err(input.error("soa_project! needs at least one column"));
+        }
+        Ok(Projection { soa, columns })
+    }
+}
+
+impl Parse for ProjectedColumn {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(ProjectedColumn {
+            mutability: input.parse()?,
+            ident: input.parse()?,
+        })
+    }
+}
+
+/// Zips the iterators of just the projected columns, so a loop over a few columns of
+/// a wide SoA does not pay for the others. Items are flat tuples of `&T`/`&mut T`,
+/// or the bare reference for a single column.
+///
+/// The columns are taken from `as_slice()`, or `as_mut_slice()` when any of them is
+/// `mut`, so the container and both slice views can be projected.
+pub(crate) fn expand(projection: &Projection) -> syn::Result<TokenStream> {
+    let soa = &projection.soa;
+    let columns: Vec<_> = projection.columns.iter().collect();
+    for (i, column) in columns.iter().enumerate() {
+        if columns[..i].iter().any(|c| c.ident == column.ident) {
+            return Err(Error::new_spanned(
+                &column.ident,
+                format!("column `{}` is projected more than once", column.ident),
+            ));
+        }
+    }
+
+    let slice = format_ident!("__soa_slice");
+    let borrow = if columns.iter().any(|c| c.mutability.is_some()) {
+        quote! { as_mut_slice }
+    } else {
+        quote! { as_slice }
+    };
+    let bindings = columns.iter().map(|c| {
+        let ident = &c.ident;
+        match c.mutability {
+            Some(_) => quote! { let #ident: &mut [_] = #slice.#ident; },
+            None => quote! { let #ident: &[_] = #slice.#ident; },
+        }
+    });
+    let idents: Vec<_> = columns.iter().map(|c| &c.ident).collect();
+    let first = idents[0];
+    let zipped = idents[1..].iter().fold(
+        quote! { core::iter::IntoIterator::into_iter(#first) },
+        |acc, ident| quote! { #acc.zip(#ident) },
+    );
+    let pattern = idents[1..]
+        .iter()
+        .fold(quote! { #first }, |acc, ident| quote! { (#acc, #ident) });
+    let iter = if idents.len() == 1 {
+        zipped
+    } else {
+        quote! { #zipped.map(|#pattern| ( #( #idents, )* )) }
+    };
+
+    Ok(quote! {
+        {
+            let #slice = (#soa).#borrow();
+            #( #bindings )*
+            #iter
+        }
+    })
+}
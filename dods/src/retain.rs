@@ -0,0 +1,221 @@
+use crate::model::Soa;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// Inherent methods of `FooSoA` emitted here.
+pub(crate) const METHODS: &[&str] = &[
+    "retain_mask",
+    "rotate_to_end",
+    "retain",
+    "retain_mut",
+    "dedup_by",
+    "dedup_by_key",
+    "drain",
+    "extract_if",
+    "dedup",
+];
+
+/// `retain`, `dedup` and friends on `FooSoA`, plus `drain` and `extract_if`.
+///
+/// The predicates run over the whole container first and record which elements to
+/// keep, so a panicking predicate leaves the container untouched. The survivors are
+/// then moved to the front one column at a time, and the rest truncated away.
+/// `drain` and `extract_if` move the affected elements behind the others in place,
+/// shifting the elements after them down, and split them off into a `FooIntoIter`.
+/// Like `Vec::drain`, that moves the `len - start` elements from the start of the
+/// range on, but the removed elements also get a container of their own.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    let soa_name = &soa.names.soa;
+    let into_iter_name = &soa.names.into_iter;
+    let (impl_generics, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let soa_ref = soa.view_ty(&soa.names.ref_);
+    let soa_ref_mut = soa.view_ty(&soa.names.ref_mut);
+    let lt = &soa.lt;
+    let eq_where = soa.where_columns(quote! { core::cmp::PartialEq }, |c| c.ref_ty(lt));
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let this: Vec<_> = soa
+        .columns
+        .iter()
+        .map(|c| {
+            let col = &c.ident;
+            c.index(quote! { slice.#col }, quote! { i })
+        })
+        .collect();
+    // Plain columns rotate as slices do; the views of nested columns only swap, so
+    // theirs rotate by three reversals.
+    let rotates = soa.columns.iter().map(|c| {
+        let col = &c.ident;
+        if c.nested.is_some() {
+            quote! {
+                let reverse = |from: usize, to: usize| (from..to).zip((from..to).rev()).take((to - from) / 2);
+                for (a, b) in reverse(start, end).chain(reverse(end, len)).chain(reverse(start, len)) {
+                    slice.#col.swap(a, b);
+                }
+            }
+        } else {
+            quote! { slice.#col[start..].rotate_left(end - start); }
+        }
+    });
+    let last: Vec<_> = soa
+        .columns
+        .iter()
+        .map(|c| {
+            let col = &c.ident;
+            c.index(quote! { slice.#col }, quote! { last })
+        })
+        .collect();
+
+    quote! {
+        impl #impl_generics #soa_name #ty_generics #where_clause {
+            /// Moves the elements flagged in `keep` to the front, in order, column by
+            /// column, and drops the others.
+            fn retain_mask(&mut self, keep: &[bool]) {
+                let kept = keep.iter().filter(|keep| **keep).count();
+                if kept == keep.len() {
+                    return;
+                }
+                let slice = &mut self.as_mut_slice();
+                #(
+                    let kept_indices = keep.iter().enumerate().filter_map(|(i, keep)| keep.then_some(i));
+                    for (to, from) in kept_indices.enumerate() {
+                        slice.#cols.swap(to, from);
+                    }
+                )*
+                self.truncate(kept);
+            }
+
+            /// Moves the elements in `start..end` to the end of the SoA, in order,
+            /// shifting the elements after them down, column by column.
+            fn rotate_to_end(&mut self, start: usize, end: usize) {
+                let len = self.len();
+                let slice = &mut self.as_mut_slice();
+                #( #rotates )*
+            }
+
+            /// Retains only the elements for which `f` returns true, preserving their
+            /// order.
+            pub fn retain<F>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref) -> bool,
+            {
+                let keep: Vec<bool> = self.iter().map(&mut f).collect();
+                self.retain_mask(&keep);
+            }
+
+            /// Retains only the elements for which `f` returns true, preserving their
+            /// order. `f` may modify the elements it visits.
+            pub fn retain_mut<F>(&mut self, mut f: F)
+            where
+                F: FnMut(#soa_ref_mut) -> bool,
+            {
+                let keep: Vec<bool> = self.iter_mut().map(&mut f).collect();
+                self.retain_mask(&keep);
+            }
+
+            /// Removes consecutive elements for which `same_bucket(element, previous)`
+            /// returns true, where `previous` is the last element kept.
+            pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+            where
+                F: FnMut(#soa_ref_mut, #soa_ref_mut) -> bool,
+            {
+                let len = self.len();
+                let mut keep = vec![true; len];
+                let mut slice = self.as_mut_slice();
+                let mut last = 0;
+                for i in 1..len {
+                    let (head, tail) = slice.split_at_mut(i);
+                    if same_bucket(tail.into_index_mut(0), head.into_index_mut(last)) {
+                        keep[i] = false;
+                    } else {
+                        last = i;
+                    }
+                }
+                self.retain_mask(&keep);
+            }
+
+            /// Removes consecutive elements that map to the same key.
+            pub fn dedup_by_key<F, K>(&mut self, mut key: F)
+            where
+                F: FnMut(#soa_ref_mut) -> K,
+                K: PartialEq,
+            {
+                self.dedup_by(|a, b| key(a) == key(b));
+            }
+
+            /// Removes the elements in `range` and returns them by value. The range is
+            /// removed even if the iterator is not consumed.
+            ///
+            /// The elements after the range are shifted down in place, which moves the
+            /// `len - start` elements from the start of the range on once per column,
+            /// and the removed ones are moved into a new SoA of `range.len()` elements.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the SoA.
+            #[track_caller]
+            pub fn drain(&mut self, range: impl core::ops::RangeBounds<usize>) -> #into_iter_name #ty_generics {
+                let len = self.len();
+                let (start, end) = Self::bounds_to_range(range, len);
+                self.rotate_to_end(start, end);
+                self.split_off(len - (end - start)).into_iter()
+            }
+
+            /// Removes the elements in `range` for which `filter` returns true and
+            /// returns them by value, in order. Unlike `Vec::extract_if`, the elements
+            /// are removed up front, whether or not the iterator is consumed.
+            ///
+            /// The kept elements are moved into place with one permutation of the
+            /// `len - start` elements from the start of the range on, which allocates
+            /// its indices, and the extracted ones are moved into a new SoA of their
+            /// own. Nothing is moved when `filter` extracts nothing.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the SoA.
+            #[track_caller]
+            pub fn extract_if<F>(
+                &mut self,
+                range: impl core::ops::RangeBounds<usize>,
+                mut filter: F,
+            ) -> #into_iter_name #ty_generics
+            where
+                F: FnMut(#soa_ref_mut) -> bool,
+            {
+                let len = self.len();
+                let (start, end) = Self::bounds_to_range(range, len);
+                let extract: Vec<bool> = self.slice_mut(start..end).into_iter().map(&mut filter).collect();
+                let count = extract.iter().filter(|extract| **extract).count();
+                if count == 0 {
+                    return Self::new().into_iter();
+                }
+                // The kept elements of the range, then the tail, then the extracted
+                // elements, as positions from `start`.
+                let in_range = |keep: bool| {
+                    extract.iter().enumerate().filter_map(move |(i, extract)| (*extract != keep).then_some(i))
+                };
+                let permutation = in_range(true)
+                    .chain(end - start..len - start)
+                    .chain(in_range(false))
+                    .collect();
+                self.slice_mut(start..).apply_permutation(permutation);
+                self.split_off(len - count).into_iter()
+            }
+        }
+
+        impl #impl_generics #soa_name #ty_generics #eq_where {
+            /// Removes consecutive repeated elements.
+            pub fn dedup(&mut self) {
+                let len = self.len();
+                let mut keep = vec![true; len];
+                let slice = self.as_slice();
+                let mut last = 0;
+                for i in 1..len {
+                    if true #( && &#this == &#last )* {
+                        keep[i] = false;
+                    } else {
+                        last = i;
+                    }
+                }
+                self.retain_mask(&keep);
+            }
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::attr::{SerdeShape, Storage};
+use crate::model::Soa;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Error, Member};
+
+/// Serde support for `#[soa(serde)]` structs, behind the `serde` feature of this
+/// crate. `FooRef` serializes like `Foo` would, and `FooSlice` and `FooSoA` in the
+/// shape picked with `#[soa(serde = "...")]`: a struct of one sequence per column by
+/// default, or a sequence of elements with `"rows"`. Deserializing the columns shape
+/// checks that every column has the same length, then moves the columns into the SoA
+/// as they are with `storage = "vec"`.
+///
+/// The generated code names `::serde` directly, so the crate deriving `SoA` needs it
+/// as a dependency. Nested columns go through the impls of their own derive, in their
+/// own shape, so the type of a nested field needs `#[soa(serde)]` as well.
+pub(crate) fn expand(soa: &Soa) -> syn::Result<TokenStream> {
+    let Some(shape) = soa.attrs.serde else {
+        return Ok(quote! {});
+    };
+    if !cfg!(feature = "serde") {
+        return Err(Error::new_spanned(
+            soa.name(),
+            "#[soa(serde)] needs the `serde` feature of dods",
+        ));
+    }
+    let name = soa.name();
+    let soa_name = &soa.names.soa;
+    let ref_name = &soa.names.ref_;
+    let slice_name = &soa.names.slice;
+    let (impl_generics, ty_generics, _) = soa.generics().split_for_impl();
+    let view_generics = &soa.view_generics;
+    let (view_impl_generics, view_ty_generics, _) = view_generics.split_for_impl();
+    let lt = &soa.lt;
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let col_names: Vec<_> = cols.iter().map(|c| c.unraw().to_string()).collect();
+    let count = soa.columns.len();
+
+    let ref_tys = soa.columns.iter().map(|c| c.ref_ty(lt));
+    let ref_where = soa.where_with(quote! { #( #ref_tys: ::serde::Serialize, )* });
+    let name_str = name.to_string();
+    let ref_body = if soa.tuple {
+        quote! {
+            let mut state = ::serde::Serializer::serialize_tuple_struct(serializer, #name_str, #count)?;
+            #( ::serde::ser::SerializeTupleStruct::serialize_field(&mut state, &self.#cols)?; )*
+            ::serde::ser::SerializeTupleStruct::end(state)
+        }
+    } else {
+        let fields = soa.columns.iter().map(|c| match &c.member {
+            Member::Named(ident) => ident.unraw().to_string(),
+            Member::Unnamed(index) => index.index.to_string(),
+        });
+        quote! {
+            let mut state = ::serde::Serializer::serialize_struct(serializer, #name_str, #count)?;
+            #( ::serde::ser::SerializeStruct::serialize_field(&mut state, #fields, &self.#cols)?; )*
+            ::serde::ser::SerializeStruct::end(state)
+        }
+    };
+
+    let soa_str = soa_name.to_string();
+    let slice_ty = soa.view_ty_with(slice_name, lt);
+    let (slice_where, slice_body) = match shape {
+        SerdeShape::Columns => {
+            let slice_tys = soa.columns.iter().map(|c| c.slice_ty(lt));
+            (
+                soa.where_with(quote! { #( #slice_tys: ::serde::Serialize, )* }),
+                quote! {
+                    let mut state = ::serde::Serializer::serialize_struct(serializer, #soa_str, #count)?;
+                    #( ::serde::ser::SerializeStruct::serialize_field(&mut state, #col_names, &self.#cols)?; )*
+                    ::serde::ser::SerializeStruct::end(state)
+                },
+            )
+        }
+        SerdeShape::Rows => {
+            let ref_ty = soa.view_ty_with(ref_name, lt);
+            (
+                soa.where_with(quote! { #ref_ty: ::serde::Serialize }),
+                quote! { ::serde::Serializer::collect_seq(serializer, self.iter()) },
+            )
+        }
+    };
+    let soa_ser_where = soa.where_with(quote! { for<#lt> #slice_ty: ::serde::Serialize });
+
+    // The visitor is declared inside `deserialize` so it does not take a name in the
+    // user's module; it repeats the generics of the impl around it.
+    let (de_where, visitor_body, deserialize_call) = match shape {
+        SerdeShape::Columns => {
+            let vec_tys: Vec<_> = soa.columns.iter().map(|c| c.vec_ty()).collect();
+            let values: Vec<_> = (0..count).map(|i| format_ident!("column_{}", i)).collect();
+            let iters: Vec<_> = (0..count).map(|i| format_ident!("iter_{}", i)).collect();
+            let members: Vec<_> = soa.columns.iter().map(|c| &c.member).collect();
+            let (first, first_name) = (&values[0], &col_names[0]);
+            // The deserialized columns are the columns of a `storage = "vec"` SoA as they
+            // are, while a single buffer has to take the elements one by one.
+            let into_soa = match soa.attrs.storage {
+                Storage::Vec => quote! { Ok(#soa_name { #( #cols: #values, )* }) },
+                Storage::Single => quote! {
+                    let mut soa = #soa_name::with_capacity(len);
+                    #( let mut #iters = #values.into_iter(); )*
+                    while let ( #( Some(#values), )* ) = ( #( #iters.next(), )* ) {
+                        soa.push(#name { #( #members: #values, )* });
+                    }
+                    Ok(soa)
+                },
+            };
+            let build = quote! {
+                let len = #first.len();
+                #(
+                    if #values.len() != len {
+                        return Err(::serde::de::Error::custom(format_args!(
+                            "column `{}` has {} elements, but column `{}` has {}",
+                            #col_names,
+                            #values.len(),
+                            #first_name,
+                            len,
+                        )));
+                    }
+                )*
+                #into_soa
+            };
+            let indices = 0..count;
+            (
+                soa.where_with(quote! { #( #vec_tys: ::serde::Deserialize<#lt>, )* }),
+                quote! {
+                    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                        formatter.write_str(concat!("struct ", #soa_str))
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: ::serde::de::SeqAccess<#lt>,
+                    {
+                        #(
+                            let Some(#values) = seq.next_element::<#vec_tys>()? else {
+                                return Err(::serde::de::Error::invalid_length(#indices, &self));
+                            };
+                        )*
+                        #build
+                    }
+
+                    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: ::serde::de::MapAccess<#lt>,
+                    {
+                        #( let mut #values: Option<#vec_tys> = None; )*
+                        while let Some(key) = map.next_key::<String>()? {
+                            match key.as_str() {
+                                #(
+                                    #col_names => {
+                                        if #values.is_some() {
+                                            return Err(::serde::de::Error::duplicate_field(#col_names));
+                                        }
+                                        #values = Some(map.next_value()?);
+                                    }
+                                )*
+                                _ => {
+                                    map.next_value::<::serde::de::IgnoredAny>()?;
+                                }
+                            }
+                        }
+                        #(
+                            let Some(#values) = #values else {
+                                return Err(::serde::de::Error::missing_field(#col_names));
+                            };
+                        )*
+                        #build
+                    }
+                },
+                quote! {
+                    ::serde::Deserializer::deserialize_struct(
+                        deserializer,
+                        #soa_str,
+                        &[ #( #col_names, )* ],
+                        visitor,
+                    )
+                },
+            )
+        }
+        SerdeShape::Rows => {
+            let expecting = format!("a sequence of {}", name);
+            (
+                soa.where_with(quote! { #name #ty_generics: ::serde::Deserialize<#lt> }),
+                quote! {
+                    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                        formatter.write_str(#expecting)
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: ::serde::de::SeqAccess<#lt>,
+                    {
+                        let mut soa = #soa_name::new();
+                        while let Some(element) = seq.next_element()? {
+                            soa.push(element);
+                        }
+                        Ok(soa)
+                    }
+                },
+                quote! { ::serde::Deserializer::deserialize_seq(deserializer, visitor) },
+            )
+        }
+    };
+
+    let columns = column_impls(soa);
+
+    Ok(quote! {
+        #columns
+
+        impl #view_impl_generics ::serde::Serialize for #ref_name #view_ty_generics #ref_where {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                #ref_body
+            }
+        }
+
+        impl #view_impl_generics ::serde::Serialize for #slice_name #view_ty_generics #slice_where {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                #slice_body
+            }
+        }
+
+        impl #impl_generics ::serde::Serialize for #soa_name #ty_generics #soa_ser_where {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                ::serde::Serialize::serialize(&self.as_slice(), serializer)
+            }
+        }
+
+        impl #view_impl_generics ::serde::Deserialize<#lt> for #soa_name #ty_generics #de_where {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<#lt>,
+            {
+                struct Visitor #view_impl_generics #de_where {
+                    marker: core::marker::PhantomData<fn() -> (&#lt (), #soa_name #ty_generics)>,
+                }
+
+                impl #view_impl_generics ::serde::de::Visitor<#lt> for Visitor #view_ty_generics #de_where {
+                    type Value = #soa_name #ty_generics;
+
+                    #visitor_body
+                }
+
+                let visitor = Visitor { marker: core::marker::PhantomData };
+                #deserialize_call
+            }
+        }
+    })
+}
+
+/// Serialize for the slices of the bitpacked and nullable column types, as sequences
+/// of their elements, and Deserialize for their containers from the same.
+fn column_impls(soa: &Soa) -> TokenStream {
+    let mut impls = TokenStream::new();
+    if soa.columns.iter().any(|c| c.bitpacked || c.nullable) {
+        let bit = &soa.names.bit;
+        let bit_soa = format_ident!("{}SoA", bit);
+        let bit_slice = format_ident!("{}Slice", bit);
+        impls.extend(quote! {
+            impl<'a> ::serde::Serialize for #bit_slice<'a> {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: ::serde::Serializer,
+                {
+                    ::serde::Serializer::collect_seq(serializer, self.iter())
+                }
+            }
+
+            impl<'de> ::serde::Deserialize<'de> for #bit_soa {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::serde::Deserializer<'de>,
+                {
+                    let values = <Vec<bool> as ::serde::Deserialize>::deserialize(deserializer)?;
+                    let mut bits = Self::with_capacity(values.len());
+                    for value in values {
+                        bits.push(value);
+                    }
+                    Ok(bits)
+                }
+            }
+        });
+    }
+    if soa.columns.iter().any(|c| c.nullable) {
+        let nullable = &soa.names.nullable;
+        let nullable_soa = format_ident!("{}SoA", nullable);
+        let nullable_slice = format_ident!("{}Slice", nullable);
+        impls.extend(quote! {
+            impl<'a, T: ::serde::Serialize> ::serde::Serialize for #nullable_slice<'a, T> {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: ::serde::Serializer,
+                {
+                    ::serde::Serializer::collect_seq(serializer, self.iter())
+                }
+            }
+
+            impl<'de, T: ::serde::Deserialize<'de> + Default> ::serde::Deserialize<'de> for #nullable_soa<T> {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::serde::Deserializer<'de>,
+                {
+                    let values = <Vec<Option<T>> as ::serde::Deserialize>::deserialize(deserializer)?;
+                    let mut column = Self::with_capacity(values.len());
+                    for value in values {
+                        column.push(value);
+                    }
+                    Ok(column)
+                }
+            }
+        });
+    }
+    impls
+}
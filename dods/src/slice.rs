@@ -0,0 +1,418 @@
+use crate::iter::{zip_expr, zip_pattern};
+use crate::model::{Column, Soa};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// The slice-level API on `FooSlice` and `FooSliceMut`, mirroring what `&[Foo]` and
+/// `&mut [Foo]` offer, so algorithms can take a view instead of the container.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    let soa_name = &soa.names.soa;
+    let ref_name = &soa.names.ref_;
+    let ref_mut_name = &soa.names.ref_mut;
+    let slice_name = &soa.names.slice;
+    let slice_mut_name = &soa.names.slice_mut;
+    let iter_name = &soa.names.iter;
+    let iter_mut_name = &soa.names.iter_mut;
+    let (_, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let soa_path = ty_generics.as_turbofish();
+    let view_generics = &soa.view_generics;
+    let (_, view_ty_generics, _) = view_generics.split_for_impl();
+    let soa_ref = soa.view_ty(ref_name);
+    let soa_ref_mut = soa.view_ty(ref_mut_name);
+    let soa_slice = soa.view_ty(slice_name);
+    let soa_slice_mut = soa.view_ty(slice_mut_name);
+    let soa_iter = soa.view_ty(iter_name);
+    let soa_iter_mut = soa.view_ty(iter_mut_name);
+
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let each = |f: &dyn Fn(&Column, TokenStream) -> TokenStream| -> Vec<TokenStream> {
+        soa.columns
+            .iter()
+            .map(|c| {
+                let col = &c.ident;
+                f(c, quote! { self.#col })
+            })
+            .collect()
+    };
+    let index = each(&|c, col| c.index(col, quote! { index }));
+    let index_mut = each(&|c, col| c.index_mut(col, quote! { index }));
+    let into_index_mut = each(&|c, col| c.index_mut_owned(col, quote! { index }));
+    let range = each(&|c, col| c.range(col, quote! { start..end }));
+    let range_mut = each(&|c, col| c.range_mut(col, quote! { start..end }));
+    let into_range_mut = each(&|c, col| c.range_mut_owned(col, quote! { start..end }));
+    let into_split_at_mut = each(&|c, col| c.split_at_mut_owned(col, quote! { mid }));
+    let reborrow = each(&|c, col| c.reborrow(col));
+    let reborrow_mut = each(&|c, col| c.reborrow_mut(col));
+    let first = cols[0];
+    let pattern = zip_pattern(&soa.columns);
+    let iter_expr = zip_expr(&soa.columns, |col| quote! { self.#col.iter() });
+    let iter_mut_expr = zip_expr(&soa.columns, |col| quote! { self.#col.iter_mut() });
+    let into_iter_mut_expr = zip_expr(&soa.columns, |col| quote! { self.#col.into_iter() });
+    let chunks_expr = zip_expr(&soa.columns, |col| quote! { self.#col.chunks(chunk_size) });
+    let chunks_mut_expr = zip_expr(
+        &soa.columns,
+        |col| quote! { self.#col.chunks_mut(chunk_size) },
+    );
+
+    quote! {
+        impl #view_generics Clone for #slice_name #view_ty_generics #where_clause {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl #view_generics Copy for #slice_name #view_ty_generics #where_clause {}
+
+        impl #view_generics #slice_name #view_ty_generics #where_clause {
+            /// Returns the number of elements in the slice.
+            pub fn len(&self) -> usize {
+                self.#first.len()
+            }
+
+            /// Returns true when the slice has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.#first.is_empty()
+            }
+
+            /// Returns a copy of the view, for code that works on the container and
+            /// its views alike, such as `soa_project!`.
+            pub fn as_slice(&self) -> Self {
+                *self
+            }
+
+            /// Returns references to the element at `index`, or `None` if out of bounds.
+            pub fn get(&self, index: usize) -> Option<#ref_name #view_ty_generics> {
+                if index >= self.len() { return None; }
+                Some(#ref_name {
+                    #( #cols: #index, )*
+                })
+            }
+
+            /// Returns references to the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn index(&self, index: usize) -> #ref_name #view_ty_generics {
+                #ref_name {
+                    #( #cols: #index, )*
+                }
+            }
+
+            /// Returns the first element, if any.
+            pub fn first(&self) -> Option<#ref_name #view_ty_generics> {
+                self.get(0)
+            }
+
+            /// Returns the last element, if any.
+            pub fn last(&self) -> Option<#ref_name #view_ty_generics> {
+                self.len().checked_sub(1).and_then(|last| self.get(last))
+            }
+
+            /// Returns an iterator over references to each element.
+            pub fn iter(&self) -> #iter_name #view_ty_generics {
+                #iter_name { inner: #iter_expr }
+            }
+
+            /// Returns the sub-slice covering the `index` range.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the slice.
+            #[track_caller]
+            pub fn slice(&self, index: impl core::ops::RangeBounds<usize>) -> Self {
+                let (start, end) = #soa_name #soa_path::bounds_to_range(index, self.len());
+                #slice_name {
+                    #( #cols: #range, )*
+                }
+            }
+
+            /// Returns the sub-slice covering the `index` range, or `None` if the
+            /// range is inverted or reaches past the end of the slice.
+            pub fn get_slice(&self, index: impl core::ops::RangeBounds<usize>) -> Option<Self> {
+                let (start, end) = #soa_name #soa_path::checked_bounds(&index, self.len())?;
+                Some(#slice_name {
+                    #( #cols: #range, )*
+                })
+            }
+
+            /// Divides the slice into two at `mid`.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            pub fn split_at(&self, mid: usize) -> (Self, Self) {
+                #( let #cols = self.#cols.split_at(mid); )*
+                (
+                    #slice_name { #( #cols: #cols.0, )* },
+                    #slice_name { #( #cols: #cols.1, )* },
+                )
+            }
+
+            /// Returns an iterator over `chunk_size` elements of the slice at a time.
+            /// The last chunk is shorter if `chunk_size` does not divide the length.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            pub fn chunks(
+                &self,
+                chunk_size: usize,
+            ) -> impl DoubleEndedIterator<Item = Self> + ExactSizeIterator {
+                #chunks_expr.map(|#pattern| #slice_name { #( #cols, )* })
+            }
+        }
+
+        impl #view_generics IntoIterator for #slice_name #view_ty_generics #where_clause {
+            type Item = #ref_name #view_ty_generics;
+            type IntoIter = #iter_name #view_ty_generics;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl #view_generics #slice_mut_name #view_ty_generics #where_clause {
+            /// Returns the number of elements in the slice.
+            pub fn len(&self) -> usize {
+                self.#first.len()
+            }
+
+            /// Returns true when the slice has no elements.
+            pub fn is_empty(&self) -> bool {
+                self.#first.is_empty()
+            }
+
+            /// Reborrows the slice as an immutable view.
+            pub fn as_slice(&self) -> #soa_slice {
+                #slice_name {
+                    #( #cols: #reborrow, )*
+                }
+            }
+
+            /// Reborrows the slice for a shorter lifetime, leaving `self` usable afterwards.
+            pub fn reborrow(&mut self) -> #soa_slice_mut {
+                #slice_mut_name {
+                    #( #cols: #reborrow_mut, )*
+                }
+            }
+
+            /// Same as `reborrow`, for code that works on the container and its views
+            /// alike, such as `soa_project!`.
+            pub fn as_mut_slice(&mut self) -> #soa_slice_mut {
+                self.reborrow()
+            }
+
+            /// Returns references to the element at `index`, or `None` if out of bounds.
+            pub fn get(&self, index: usize) -> Option<#soa_ref> {
+                self.as_slice().get(index)
+            }
+
+            /// Returns references to the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn index(&self, index: usize) -> #soa_ref {
+                self.as_slice().index(index)
+            }
+
+            /// Returns mutable references to the element at `index`, or `None` if out of bounds.
+            pub fn get_mut(&mut self, index: usize) -> Option<#soa_ref_mut> {
+                if index >= self.len() { return None; }
+                Some(#ref_mut_name {
+                    #( #cols: #index_mut, )*
+                })
+            }
+
+            /// Returns mutable references to the element at `index`.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn index_mut(&mut self, index: usize) -> #soa_ref_mut {
+                #ref_mut_name {
+                    #( #cols: #index_mut, )*
+                }
+            }
+
+            /// Returns the first element, if any.
+            pub fn first(&self) -> Option<#soa_ref> {
+                self.get(0)
+            }
+
+            /// Returns the last element, if any.
+            pub fn last(&self) -> Option<#soa_ref> {
+                self.len().checked_sub(1).and_then(|last| self.get(last))
+            }
+
+            /// Returns mutable references to the first element, if any.
+            pub fn first_mut(&mut self) -> Option<#soa_ref_mut> {
+                self.get_mut(0)
+            }
+
+            /// Returns mutable references to the last element, if any.
+            pub fn last_mut(&mut self) -> Option<#soa_ref_mut> {
+                let last = self.len().checked_sub(1)?;
+                self.get_mut(last)
+            }
+
+            /// Returns an iterator over references to each element.
+            pub fn iter(&self) -> #soa_iter {
+                #iter_name { inner: #iter_expr }
+            }
+
+            /// Returns an iterator over mutable references to each element.
+            pub fn iter_mut(&mut self) -> #soa_iter_mut {
+                #iter_mut_name { inner: #iter_mut_expr }
+            }
+
+            /// Returns the sub-slice covering the `index` range.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the slice.
+            #[track_caller]
+            pub fn slice(&self, index: impl core::ops::RangeBounds<usize>) -> #soa_slice {
+                self.as_slice().slice(index)
+            }
+
+            /// Returns the sub-slice covering the `index` range, or `None` if the
+            /// range is inverted or reaches past the end of the slice.
+            pub fn get_slice(&self, index: impl core::ops::RangeBounds<usize>) -> Option<#soa_slice> {
+                self.as_slice().get_slice(index)
+            }
+
+            /// Returns the mutable sub-slice covering the `index` range.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the slice.
+            #[track_caller]
+            pub fn slice_mut(&mut self, index: impl core::ops::RangeBounds<usize>) -> #soa_slice_mut {
+                let (start, end) = #soa_name #soa_path::bounds_to_range(index, self.len());
+                #slice_mut_name {
+                    #( #cols: #range_mut, )*
+                }
+            }
+
+            /// Turns the slice into the mutable sub-slice covering the `index` range,
+            /// keeping the full lifetime of the view.
+            ///
+            /// # Panics
+            /// Panics if the range is inverted or reaches past the end of the slice.
+            #[track_caller]
+            pub fn into_slice_mut(self, index: impl core::ops::RangeBounds<usize>) -> Self {
+                let (start, end) = #soa_name #soa_path::bounds_to_range(index, self.len());
+                #slice_mut_name {
+                    #( #cols: #into_range_mut, )*
+                }
+            }
+
+            /// Turns the slice into mutable references to the element at `index`,
+            /// keeping the full lifetime of the view.
+            ///
+            /// # Panics
+            /// Panics if `index` is out of bounds.
+            pub fn into_index_mut(self, index: usize) -> #ref_mut_name #view_ty_generics {
+                #ref_mut_name {
+                    #( #cols: #into_index_mut, )*
+                }
+            }
+
+            /// Returns the mutable sub-slice covering the `index` range, or `None` if
+            /// the range is inverted or reaches past the end of the slice.
+            pub fn get_slice_mut(&mut self, index: impl core::ops::RangeBounds<usize>) -> Option<#soa_slice_mut> {
+                let (start, end) = #soa_name #soa_path::checked_bounds(&index, self.len())?;
+                Some(#slice_mut_name {
+                    #( #cols: #range_mut, )*
+                })
+            }
+
+            /// Divides the slice into two immutable views at `mid`.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            pub fn split_at(&self, mid: usize) -> (#soa_slice, #soa_slice) {
+                self.as_slice().split_at(mid)
+            }
+
+            /// Divides the slice into two mutable views at `mid`.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            pub fn split_at_mut(&mut self, mid: usize) -> (#soa_slice_mut, #soa_slice_mut) {
+                #( let #cols = self.#cols.split_at_mut(mid); )*
+                (
+                    #slice_mut_name { #( #cols: #cols.0, )* },
+                    #slice_mut_name { #( #cols: #cols.1, )* },
+                )
+            }
+
+            /// Divides the slice into two mutable views at `mid`, keeping the full
+            /// lifetime of the view.
+            ///
+            /// # Panics
+            /// Panics if `mid > len`.
+            pub fn into_split_at_mut(self, mid: usize) -> (Self, Self) {
+                #( let #cols = #into_split_at_mut; )*
+                (
+                    #slice_mut_name { #( #cols: #cols.0, )* },
+                    #slice_mut_name { #( #cols: #cols.1, )* },
+                )
+            }
+
+            /// Returns an iterator over `chunk_size` elements of the slice at a time.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            pub fn chunks(
+                &self,
+                chunk_size: usize,
+            ) -> impl DoubleEndedIterator<Item = #soa_slice> + ExactSizeIterator {
+                #chunks_expr.map(|#pattern| #slice_name { #( #cols, )* })
+            }
+
+            /// Returns an iterator over mutable `chunk_size`-element views of the slice.
+            ///
+            /// # Panics
+            /// Panics if `chunk_size` is 0.
+            pub fn chunks_mut(
+                &mut self,
+                chunk_size: usize,
+            ) -> impl DoubleEndedIterator<Item = #soa_slice_mut> + ExactSizeIterator {
+                #chunks_mut_expr.map(|#pattern| #slice_mut_name { #( #cols, )* })
+            }
+
+            /// Swaps the elements at `a` and `b` in every column.
+            ///
+            /// # Panics
+            /// Panics if `a` or `b` are out of bounds.
+            pub fn swap(&mut self, a: usize, b: usize) {
+                #( self.#cols.swap(a, b); )*
+            }
+
+            /// Moves each element to the position that names it in `indices`, the
+            /// new-to-old mapping that `apply_index` takes, without validating it.
+            /// Follows each cycle once, marking the positions it has filled by
+            /// pointing them at themselves.
+            ///
+            /// `indices` must be a permutation of `0..len`, or this may never return.
+            fn apply_permutation(&mut self, mut indices: Vec<usize>) {
+                debug_assert_eq!(indices.len(), self.len());
+                for start in 0..indices.len() {
+                    let mut current = start;
+                    while indices[current] != current {
+                        let next = core::mem::replace(&mut indices[current], current);
+                        if next == start {
+                            break;
+                        }
+                        self.swap(current, next);
+                        current = next;
+                    }
+                }
+            }
+        }
+
+        impl #view_generics IntoIterator for #slice_mut_name #view_ty_generics #where_clause {
+            type Item = #ref_mut_name #view_ty_generics;
+            type IntoIter = #iter_mut_name #view_ty_generics;
+
+            fn into_iter(self) -> Self::IntoIter {
+                #iter_mut_name { inner: #into_iter_mut_expr }
+            }
+        }
+    }
+}
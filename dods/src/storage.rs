@@ -0,0 +1,766 @@
+use crate::attr::Storage;
+use crate::derive_attr;
+use crate::iter::{iterator_impls, zip_expr, zip_type};
+use crate::model::{Column, Soa};
+use proc_macro2::{Literal, TokenStream};
+use quote::{ToTokens, quote};
+
+/// Inherent methods of `FooSoA` emitted here, for either storage.
+pub(crate) const METHODS: &[&str] = &[
+    "new",
+    "with_capacity",
+    "push",
+    "insert",
+    "replace",
+    "remove",
+    "swap_remove",
+    "pop",
+    "debug_assert_in_sync",
+    "len",
+    "capacity",
+    "reserve",
+    "reserve_exact",
+    "shrink_to_fit",
+    "truncate",
+    "clear",
+    "append",
+    "split_off",
+    "as_slice",
+    "as_mut_slice",
+    "as_ptr",
+    "as_mut_ptr",
+    "buffer_layout",
+    "dangling",
+    "reallocate",
+    "release",
+];
+
+/// The container struct and the primitives every other method is built on: growing
+/// and shrinking, moving elements in and out, and borrowing the columns as slices.
+/// Also the owning iterator and `Clone`, which both depend on how the columns are held.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    // The container cannot be `Copy` and has its own impls of the standard traits
+    // (see `traits`), so those are not forwarded.
+    let derives = derive_attr(
+        &soa.attrs.derives,
+        &[
+            "Copy",
+            "Default",
+            "Clone",
+            "Debug",
+            "PartialEq",
+            "Eq",
+            "Hash",
+        ],
+    );
+    match soa.attrs.storage {
+        Storage::Vec => vec_storage(soa, derives),
+        Storage::Single => single_storage(soa, derives),
+    }
+}
+
+/// One `Vec` per column, each with its own allocation.
+fn vec_storage(soa: &Soa, derives: TokenStream) -> TokenStream {
+    let name = soa.name();
+    let vis = &soa.vis;
+    let soa_name = &soa.names.soa;
+    let slice_name = &soa.names.slice;
+    let slice_mut_name = &soa.names.slice_mut;
+    let ptr_name = &soa.names.ptr;
+    let ptr_mut_name = &soa.names.ptr_mut;
+    let into_iter_name = &soa.names.into_iter;
+    let generics = soa.generics();
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let soa_slice = soa.view_ty(slice_name);
+    let soa_slice_mut = soa.view_ty(slice_mut_name);
+    let clone_where = soa.where_columns(quote! { core::clone::Clone }, |c| c.vec_ty());
+
+    let members: Vec<_> = soa.columns.iter().map(|c| &c.member).collect();
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let vec_tys: Vec<_> = soa.columns.iter().map(|c| c.vec_ty()).collect();
+    let replace: Vec<_> = soa
+        .columns
+        .iter()
+        .map(|c| {
+            let (col, member) = (&c.ident, &c.member);
+            match c.nested {
+                None => quote! { std::mem::replace(&mut self.#col[index], element.#member) },
+                Some(_) => quote! { self.#col.replace(index, element.#member) },
+            }
+        })
+        .collect();
+    // Use the first column to check lengths/emptiness for the whole SoA
+    let first = cols[0];
+    let others = &cols[1..];
+
+    // With `#[soa(private_columns)]` the columns can only be changed through the
+    // container, which keeps them the same length; they are read through accessors.
+    let column_vis: Vec<_> = soa
+        .columns
+        .iter()
+        .map(|c| (!soa.private_columns()).then_some(c.vis))
+        .collect();
+
+    let into_iter_ty = zip_type(&soa.columns, |c| c.owned_iter_ty());
+    let into_iter_expr = zip_expr(&soa.columns, |col| quote! { self.#col.into_iter() });
+    let into_iter_impls = iterator_impls(
+        &soa.columns,
+        generics,
+        into_iter_name,
+        quote! { #name #ty_generics },
+        quote! { #name { #( #members: #cols, )* } },
+    );
+
+    quote! {
+        /// Struct-of-arrays container generated for the source struct.
+        #derives
+        #vis struct #soa_name #generics #where_clause {
+            #( #column_vis #cols: #vec_tys, )*
+        }
+
+        impl #impl_generics #soa_name #ty_generics #where_clause {
+            /// Creates a new, empty SoA.
+            pub fn new() -> Self {
+                Self {
+                    #( #cols: <#vec_tys>::new(), )*
+                }
+            }
+
+            /// Creates an empty SoA with capacity for at least `capacity` elements.
+            pub fn with_capacity(capacity: usize) -> Self {
+                Self {
+                    #( #cols: <#vec_tys>::with_capacity(capacity), )*
+                }
+            }
+
+            /// Appends a single `#name` to the SoA.
+            pub fn push(&mut self, item: #name #ty_generics) {
+                self.debug_assert_in_sync();
+                #( self.#cols.push(item.#members); )*
+            }
+
+            /// Inserts `element` at `index`, shifting later elements to the right.
+            pub fn insert(&mut self, index: usize, element: #name #ty_generics) {
+                self.debug_assert_in_sync();
+                #( self.#cols.insert(index, element.#members); )*
+            }
+
+            /// Replaces the element at `index` with `element`, returning the old value.
+            pub fn replace(&mut self, index: usize, element: #name #ty_generics) -> #name #ty_generics {
+                self.debug_assert_in_sync();
+                #name {
+                    #( #members: #replace, )*
+                }
+            }
+
+            /// Removes and returns the element at `index`, shifting later elements left.
+            pub fn remove(&mut self, index: usize) -> #name #ty_generics {
+                self.debug_assert_in_sync();
+                #name {
+                    #( #members: self.#cols.remove(index), )*
+                }
+            }
+
+            /// Removes the element at `index` in O(1) time.
+            /// It swaps the element at `index` with the last element and then pops.
+            /// Note: This does NOT preserve the order of elements.
+            pub fn swap_remove(&mut self, index: usize) -> #name #ty_generics {
+                self.debug_assert_in_sync();
+                #name {
+                    #( #members: self.#cols.swap_remove(index), )*
+                }
+            }
+
+            /// Removes the last element and returns it, or `None` if the SoA is empty.
+            pub fn pop(&mut self) -> Option<#name #ty_generics> {
+                self.debug_assert_in_sync();
+                if self.#first.is_empty() { return None; }
+                Some(#name {
+                    #( #members: self.#cols.pop().unwrap(), )*
+                })
+            }
+
+            /// Checks, in debug builds, that every column has the same length.
+            #[inline]
+            #[track_caller]
+            fn debug_assert_in_sync(&self) {
+                #(
+                    debug_assert_eq!(
+                        self.#others.len(),
+                        self.#first.len(),
+                        concat!(
+                            "column `", stringify!(#others), "` of ", stringify!(#soa_name),
+                            " is out of sync with column `", stringify!(#first), "`",
+                        ),
+                    );
+                )*
+            }
+
+            /// Returns the number of elements in the SoA.
+            pub fn len(&self) -> usize {
+                self.#first.len()
+            }
+
+            /// Returns the capacity of the underlying field vectors.
+            pub fn capacity(&self) -> usize {
+                self.#first.capacity()
+            }
+
+            /// Reserves capacity for at least `additional` more elements.
+            pub fn reserve(&mut self, additional: usize) {
+                self.debug_assert_in_sync();
+                #( self.#cols.reserve(additional); )*
+            }
+
+            /// Reserves the minimum capacity for `additional` more elements.
+            pub fn reserve_exact(&mut self, additional: usize) {
+                self.debug_assert_in_sync();
+                #( self.#cols.reserve_exact(additional); )*
+            }
+
+            /// Shrinks all field buffers as much as possible.
+            pub fn shrink_to_fit(&mut self) {
+                self.debug_assert_in_sync();
+                #( self.#cols.shrink_to_fit(); )*
+            }
+
+            /// Shortens the SoA to `len`, dropping excess elements.
+            pub fn truncate(&mut self, len: usize) {
+                self.debug_assert_in_sync();
+                #( self.#cols.truncate(len); )*
+            }
+
+            /// Clears all elements from the SoA.
+            pub fn clear(&mut self) {
+                self.debug_assert_in_sync();
+                #( self.#cols.clear(); )*
+            }
+
+            /// Appends all elements from `other`, leaving it empty.
+            pub fn append(&mut self, other: &mut Self) {
+                self.debug_assert_in_sync();
+                other.debug_assert_in_sync();
+                #( self.#cols.append(&mut other.#cols); )*
+            }
+
+            /// Splits the SoA into two at `at`, returning the tail.
+            pub fn split_off(&mut self, at: usize) -> Self {
+                self.debug_assert_in_sync();
+                Self {
+                    #( #cols: self.#cols.split_off(at), )*
+                }
+            }
+
+            /// Returns immutable slices of each field covering the full range.
+            pub fn as_slice(&self) -> #soa_slice {
+                #slice_name {
+                    #( #cols: self.#cols.as_slice(), )*
+                }
+            }
+
+            /// Returns mutable slices of each field covering the full range.
+            pub fn as_mut_slice(&mut self) -> #soa_slice_mut {
+                #slice_mut_name {
+                    #( #cols: self.#cols.as_mut_slice(), )*
+                }
+            }
+
+            /// Returns raw const pointers to each field buffer.
+            pub fn as_ptr(&self) -> #ptr_name #ty_generics {
+                #ptr_name {
+                    #( #cols: self.#cols.as_ptr(), )*
+                }
+            }
+
+            /// Returns raw mut pointers to each field buffer.
+            pub fn as_mut_ptr(&mut self) -> #ptr_mut_name #ty_generics {
+                #ptr_mut_name {
+                    #( #cols: self.#cols.as_mut_ptr(), )*
+                }
+            }
+        }
+
+        impl #impl_generics core::clone::Clone for #soa_name #ty_generics #clone_where {
+            fn clone(&self) -> Self {
+                Self {
+                    #( #cols: self.#cols.clone(), )*
+                }
+            }
+
+            fn clone_from(&mut self, source: &Self) {
+                #( self.#cols.clone_from(&source.#cols); )*
+            }
+        }
+
+        /// Owning iterator that moves the elements out of the SoA.
+        #vis struct #into_iter_name #generics #where_clause {
+            inner: #into_iter_ty,
+        }
+
+        #into_iter_impls
+
+        impl #impl_generics IntoIterator for #soa_name #ty_generics #where_clause {
+            type Item = #name #ty_generics;
+            type IntoIter = #into_iter_name #ty_generics;
+
+            fn into_iter(self) -> Self::IntoIter {
+                #into_iter_name { inner: #into_iter_expr }
+            }
+        }
+    }
+}
+
+/// Every column in one heap buffer, laid out back to back for the current capacity,
+/// so growing the container is a single allocation however many columns there are.
+fn single_storage(soa: &Soa, derives: TokenStream) -> TokenStream {
+    let name = soa.name();
+    let vis = &soa.vis;
+    let soa_name = &soa.names.soa;
+    let slice_name = &soa.names.slice;
+    let slice_mut_name = &soa.names.slice_mut;
+    let ptr_name = &soa.names.ptr;
+    let ptr_mut_name = &soa.names.ptr_mut;
+    let into_iter_name = &soa.names.into_iter;
+    let generics = soa.generics();
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let soa_slice = soa.view_ty(slice_name);
+    let soa_slice_mut = soa.view_ty(slice_mut_name);
+    let ty = |c: &Column| c.ty.to_token_stream();
+    let clone_where = soa.where_columns(quote! { core::clone::Clone }, ty);
+    let send_where = soa.where_columns(quote! { core::marker::Send }, ty);
+    let sync_where = soa.where_columns(quote! { core::marker::Sync }, ty);
+
+    let members: Vec<_> = soa.columns.iter().map(|c| &c.member).collect();
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let types: Vec<_> = soa.columns.iter().map(|c| c.ty).collect();
+    let count = Literal::usize_unsuffixed(soa.columns.len());
+    let indices: Vec<_> = (0..soa.columns.len())
+        .map(Literal::usize_unsuffixed)
+        .collect();
+    // The buffer holds the columns of each `#[soa(group = "...")]` next to each other.
+    let mut layout_order: Vec<_> = (0..soa.columns.len()).collect();
+    let groups = soa.groups();
+    layout_order.sort_by_key(|&i| {
+        let group = soa.columns[i].group.as_ref();
+        groups.iter().position(|g| Some(&g.name) == group)
+    });
+    let layout_types: Vec<_> = layout_order.iter().map(|&i| soa.columns[i].ty).collect();
+    let layout_indices: Vec<_> = layout_order
+        .iter()
+        .map(|&i| Literal::usize_unsuffixed(i))
+        .collect();
+
+    // Pointer to the start of each column in the buffer of the container `owner`.
+    let columns_of = |owner: TokenStream| -> Vec<TokenStream> {
+        types
+            .iter()
+            .zip(&indices)
+            .map(|(ty, index)| {
+                quote! { #owner.buf.as_ptr().wrapping_add(#owner.offsets[#index]).cast::<#ty>() }
+            })
+            .collect()
+    };
+    let columns = columns_of(quote! { self });
+    let other_columns = columns_of(quote! { other });
+    let iter_columns = columns_of(quote! { self.soa });
+
+    quote! {
+        /// Struct-of-arrays container generated for the source struct, keeping every
+        /// column in one heap buffer.
+        #derives
+        #vis struct #soa_name #generics #where_clause {
+            /// Start of the buffer, which holds `cap` elements of each column.
+            buf: core::ptr::NonNull<u8>,
+            /// Byte offset of each column from `buf`.
+            offsets: [usize; #count],
+            len: usize,
+            cap: usize,
+            marker: core::marker::PhantomData<( #( #types, )* )>,
+        }
+
+        impl #impl_generics #soa_name #ty_generics #where_clause {
+            /// Whether every column is zero-sized, in which case the buffer never
+            /// needs any memory.
+            const ZERO_SIZED: bool = true #( && core::mem::size_of::<#types>() == 0 )*;
+
+            /// Creates a new, empty SoA.
+            pub fn new() -> Self {
+                let (layout, offsets) = Self::buffer_layout(0).expect("capacity overflow");
+                Self {
+                    buf: Self::dangling(layout.align()),
+                    offsets,
+                    len: 0,
+                    cap: if Self::ZERO_SIZED { usize::MAX } else { 0 },
+                    marker: core::marker::PhantomData,
+                }
+            }
+
+            /// Creates an empty SoA with capacity for at least `capacity` elements.
+            pub fn with_capacity(capacity: usize) -> Self {
+                let mut soa = Self::new();
+                soa.reserve_exact(capacity);
+                soa
+            }
+
+            /// Appends a single `#name` to the SoA.
+            pub fn push(&mut self, item: #name #ty_generics) {
+                self.reserve(1);
+                // SAFETY: `reserve` left room for one more element in every column.
+                unsafe {
+                    #( #columns.add(self.len).write(item.#members); )*
+                }
+                self.len += 1;
+            }
+
+            /// Inserts `element` at `index`, shifting later elements to the right.
+            pub fn insert(&mut self, index: usize, element: #name #ty_generics) {
+                let len = self.len;
+                assert!(index <= len, "insertion index (is {index}) should be <= len (is {len})");
+                self.reserve(1);
+                // SAFETY: there is room for one more element, so the elements from
+                // `index` on can move one slot to the right in every column.
+                unsafe {
+                    #(
+                        let column = #columns;
+                        core::ptr::copy(column.add(index), column.add(index + 1), len - index);
+                        column.add(index).write(element.#members);
+                    )*
+                }
+                self.len = len + 1;
+            }
+
+            /// Replaces the element at `index` with `element`, returning the old value.
+            pub fn replace(&mut self, index: usize, element: #name #ty_generics) -> #name #ty_generics {
+                let slice = self.as_mut_slice();
+                #name {
+                    #( #members: std::mem::replace(&mut slice.#cols[index], element.#members), )*
+                }
+            }
+
+            /// Removes and returns the element at `index`, shifting later elements left.
+            pub fn remove(&mut self, index: usize) -> #name #ty_generics {
+                let len = self.len;
+                assert!(index < len, "removal index (is {index}) should be < len (is {len})");
+                // SAFETY: `index < len`, so every column has an element at `index` to
+                // move out, and the elements after it shift left over the gap.
+                let element = unsafe {
+                    #name {
+                        #( #members: {
+                            let column = #columns;
+                            let value = column.add(index).read();
+                            core::ptr::copy(column.add(index + 1), column.add(index), len - index - 1);
+                            value
+                        }, )*
+                    }
+                };
+                self.len = len - 1;
+                element
+            }
+
+            /// Removes the element at `index` in O(1) time.
+            /// It swaps the element at `index` with the last element and then pops.
+            /// Note: This does NOT preserve the order of elements.
+            pub fn swap_remove(&mut self, index: usize) -> #name #ty_generics {
+                let len = self.len;
+                assert!(index < len, "swap_remove index (is {index}) should be < len (is {len})");
+                // SAFETY: `index < len`, so every column has an element at `index` to
+                // move out, and the last element moves into the gap.
+                let element = unsafe {
+                    #name {
+                        #( #members: {
+                            let column = #columns;
+                            let value = column.add(index).read();
+                            core::ptr::copy(column.add(len - 1), column.add(index), 1);
+                            value
+                        }, )*
+                    }
+                };
+                self.len = len - 1;
+                element
+            }
+
+            /// Removes the last element and returns it, or `None` if the SoA is empty.
+            pub fn pop(&mut self) -> Option<#name #ty_generics> {
+                if self.len == 0 { return None; }
+                self.len -= 1;
+                // SAFETY: the old last element is initialized and no longer counted.
+                unsafe {
+                    Some(#name {
+                        #( #members: #columns.add(self.len).read(), )*
+                    })
+                }
+            }
+
+            /// Returns the number of elements in the SoA.
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns the number of elements the buffer can hold without reallocating.
+            pub fn capacity(&self) -> usize {
+                self.cap
+            }
+
+            /// Reserves capacity for at least `additional` more elements.
+            pub fn reserve(&mut self, additional: usize) {
+                let required = self.len.checked_add(additional).expect("capacity overflow");
+                if required > self.cap {
+                    self.reallocate(required.max(self.cap.saturating_mul(2)).max(4));
+                }
+            }
+
+            /// Reserves the minimum capacity for `additional` more elements.
+            pub fn reserve_exact(&mut self, additional: usize) {
+                let required = self.len.checked_add(additional).expect("capacity overflow");
+                if required > self.cap {
+                    self.reallocate(required);
+                }
+            }
+
+            /// Shrinks the buffer as much as possible.
+            pub fn shrink_to_fit(&mut self) {
+                if self.cap > self.len && !Self::ZERO_SIZED {
+                    self.reallocate(self.len);
+                }
+            }
+
+            /// Shortens the SoA to `len`, dropping excess elements.
+            pub fn truncate(&mut self, len: usize) {
+                if len >= self.len { return; }
+                let tail = self.len - len;
+                self.len = len;
+                // SAFETY: the `tail` elements after `len` are initialized and no
+                // longer counted.
+                unsafe {
+                    #(
+                        core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(#columns.add(len), tail));
+                    )*
+                }
+            }
+
+            /// Clears all elements from the SoA.
+            pub fn clear(&mut self) {
+                self.truncate(0);
+            }
+
+            /// Appends all elements from `other`, leaving it empty.
+            pub fn append(&mut self, other: &mut Self) {
+                let count = other.len;
+                self.reserve(count);
+                // SAFETY: `self` has room for `count` more elements, and `other` gives
+                // them up by dropping its length to zero.
+                unsafe {
+                    #( core::ptr::copy_nonoverlapping(#other_columns, #columns.add(self.len), count); )*
+                }
+                other.len = 0;
+                self.len += count;
+            }
+
+            /// Splits the SoA into two at `at`, returning the tail.
+            pub fn split_off(&mut self, at: usize) -> Self {
+                let len = self.len;
+                assert!(at <= len, "`at` split index (is {at}) should be <= len (is {len})");
+                let count = len - at;
+                let mut other = Self::with_capacity(count);
+                // SAFETY: `other` has room for the `count` elements after `at`, which
+                // `self` gives up by dropping its length to `at`.
+                unsafe {
+                    #( core::ptr::copy_nonoverlapping(#columns.add(at), #other_columns, count); )*
+                }
+                self.len = at;
+                other.len = count;
+                other
+            }
+
+            /// Returns immutable slices of each field covering the full range.
+            pub fn as_slice(&self) -> #soa_slice {
+                // SAFETY: each column holds `len` initialized elements.
+                unsafe {
+                    #slice_name {
+                        #( #cols: core::slice::from_raw_parts(#columns, self.len), )*
+                    }
+                }
+            }
+
+            /// Returns mutable slices of each field covering the full range.
+            pub fn as_mut_slice(&mut self) -> #soa_slice_mut {
+                // SAFETY: each column holds `len` initialized elements, and the
+                // columns do not overlap.
+                unsafe {
+                    #slice_mut_name {
+                        #( #cols: core::slice::from_raw_parts_mut(#columns, self.len), )*
+                    }
+                }
+            }
+
+            /// Returns raw const pointers to each column in the buffer.
+            pub fn as_ptr(&self) -> #ptr_name #ty_generics {
+                #ptr_name {
+                    #( #cols: #columns, )*
+                }
+            }
+
+            /// Returns raw mut pointers to each column in the buffer.
+            pub fn as_mut_ptr(&mut self) -> #ptr_mut_name #ty_generics {
+                #ptr_mut_name {
+                    #( #cols: #columns, )*
+                }
+            }
+
+            /// Layout of a buffer holding `capacity` elements of every column and the
+            /// byte offset of each column in it, or `None` if the size overflows.
+            fn buffer_layout(capacity: usize) -> Option<(core::alloc::Layout, [usize; #count])> {
+                let layout = core::alloc::Layout::new::<()>();
+                let mut offsets = [0; #count];
+                #(
+                    let (layout, offset) = layout
+                        .extend(core::alloc::Layout::array::<#layout_types>(capacity).ok()?)
+                        .ok()?;
+                    offsets[#layout_indices] = offset;
+                )*
+                Some((layout, offsets))
+            }
+
+            /// A well-aligned pointer that owns no memory, for an empty buffer.
+            fn dangling(align: usize) -> core::ptr::NonNull<u8> {
+                // SAFETY: an alignment is never zero.
+                unsafe { core::ptr::NonNull::new_unchecked(core::ptr::without_provenance_mut(align)) }
+            }
+
+            /// Moves the elements into a new buffer with room for `capacity` elements.
+            fn reallocate(&mut self, capacity: usize) {
+                let (layout, offsets) = Self::buffer_layout(capacity).expect("capacity overflow");
+                let buf = if layout.size() == 0 {
+                    Self::dangling(layout.align())
+                } else {
+                    // SAFETY: the layout has a non-zero size.
+                    let ptr = unsafe { std::alloc::alloc(layout) };
+                    core::ptr::NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(layout))
+                };
+                // SAFETY: both buffers have room for `len` elements of every column,
+                // and the old buffer is freed without dropping the moved elements.
+                unsafe {
+                    #(
+                        core::ptr::copy_nonoverlapping(
+                            #columns,
+                            buf.as_ptr().wrapping_add(offsets[#indices]).cast::<#types>(),
+                            self.len,
+                        );
+                    )*
+                }
+                self.release();
+                self.buf = buf;
+                self.offsets = offsets;
+                self.cap = capacity;
+            }
+
+            /// Frees the buffer without dropping any elements.
+            fn release(&mut self) {
+                let (layout, _) = Self::buffer_layout(self.cap).expect("capacity overflow");
+                if layout.size() != 0 {
+                    // SAFETY: a buffer with a non-zero size was allocated with this layout.
+                    unsafe { std::alloc::dealloc(self.buf.as_ptr(), layout) }
+                }
+            }
+        }
+
+        impl #impl_generics Drop for #soa_name #ty_generics #where_clause {
+            fn drop(&mut self) {
+                self.clear();
+                self.release();
+            }
+        }
+
+        // SAFETY: the container owns its elements the way a `Vec` per column would.
+        unsafe impl #impl_generics core::marker::Send for #soa_name #ty_generics #send_where {}
+        unsafe impl #impl_generics core::marker::Sync for #soa_name #ty_generics #sync_where {}
+
+        impl #impl_generics core::clone::Clone for #soa_name #ty_generics #clone_where {
+            fn clone(&self) -> Self {
+                let mut soa = Self::with_capacity(self.len);
+                soa.extend(self.iter().map(|item| #name {
+                    #( #members: core::clone::Clone::clone(item.#cols), )*
+                }));
+                soa
+            }
+        }
+
+        /// Owning iterator that moves the elements out of the SoA.
+        #vis struct #into_iter_name #generics #where_clause {
+            /// The drained container, whose length is zero so it only frees the buffer.
+            soa: #soa_name #ty_generics,
+            /// The elements not yet yielded.
+            start: usize,
+            end: usize,
+        }
+
+        impl #impl_generics Iterator for #into_iter_name #ty_generics #where_clause {
+            type Item = #name #ty_generics;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                if self.start == self.end { return None; }
+                let index = self.start;
+                self.start += 1;
+                // SAFETY: the elements in `start..end` are initialized and each is read once.
+                unsafe {
+                    Some(#name {
+                        #( #members: #iter_columns.add(index).read(), )*
+                    })
+                }
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let len = self.end - self.start;
+                (len, Some(len))
+            }
+        }
+
+        impl #impl_generics DoubleEndedIterator for #into_iter_name #ty_generics #where_clause {
+            #[inline]
+            fn next_back(&mut self) -> Option<Self::Item> {
+                if self.start == self.end { return None; }
+                self.end -= 1;
+                let index = self.end;
+                // SAFETY: the elements in `start..end` are initialized and each is read once.
+                unsafe {
+                    Some(#name {
+                        #( #members: #iter_columns.add(index).read(), )*
+                    })
+                }
+            }
+        }
+
+        impl #impl_generics ExactSizeIterator for #into_iter_name #ty_generics #where_clause {}
+
+        impl #impl_generics core::iter::FusedIterator for #into_iter_name #ty_generics #where_clause {}
+
+        impl #impl_generics Drop for #into_iter_name #ty_generics #where_clause {
+            fn drop(&mut self) {
+                let remaining = self.end - self.start;
+                // SAFETY: the elements in `start..end` were never yielded.
+                unsafe {
+                    #(
+                        core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(
+                            #iter_columns.add(self.start),
+                            remaining,
+                        ));
+                    )*
+                }
+            }
+        }
+
+        impl #impl_generics IntoIterator for #soa_name #ty_generics #where_clause {
+            type Item = #name #ty_generics;
+            type IntoIter = #into_iter_name #ty_generics;
+
+            fn into_iter(mut self) -> Self::IntoIter {
+                let end = self.len;
+                // The iterator takes over the elements; the container keeps the buffer.
+                self.len = 0;
+                #into_iter_name { soa: self, start: 0, end }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::model::Soa;
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::Member;
+use syn::ext::IdentExt;
+
+/// Standard trait impls for the container, so that `FooSoA` can stand in for
+/// `Vec<Foo>` in generic code. Each impl is available when every column type
+/// implements the trait. `Clone` depends on the storage, so it lives in `storage`.
+pub(crate) fn expand(soa: &Soa) -> TokenStream {
+    let soa_name = &soa.names.soa;
+    let (impl_generics, ty_generics, where_clause) = soa.generics().split_for_impl();
+    let cols: Vec<_> = soa.columns.iter().map(|c| &c.ident).collect();
+    let lt = &soa.lt;
+    let debug_where = soa.where_columns(quote! { core::fmt::Debug }, |c| c.ref_ty(lt));
+    let eq_where = soa.where_columns(quote! { core::cmp::PartialEq }, |c| c.slice_ty(lt));
+    let total_eq_where = soa.where_columns(quote! { core::cmp::Eq }, |c| c.slice_ty(lt));
+    let hash_where = soa.where_columns(quote! { core::hash::Hash }, |c| c.ref_ty(lt));
+    let elements = elements(soa);
+    let record = debug_record(soa, &elements);
+
+    quote! {
+        impl #impl_generics core::default::Default for #soa_name #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        /// Formats the SoA as a list of records, the same way `Vec<Foo>` would.
+        impl #impl_generics core::fmt::Debug for #soa_name #ty_generics #debug_where {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                struct Record<F>(F);
+                impl<F> core::fmt::Debug for Record<F>
+                where
+                    F: Fn(&mut core::fmt::Formatter<'_>) -> core::fmt::Result,
+                {
+                    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                        (self.0)(f)
+                    }
+                }
+
+                let slice = self.as_slice();
+                f.debug_list()
+                    .entries((0..self.len()).map(|i| {
+                        Record(move |f: &mut core::fmt::Formatter<'_>| #record)
+                    }))
+                    .finish()
+            }
+        }
+
+        impl #impl_generics core::cmp::PartialEq for #soa_name #ty_generics #eq_where {
+            fn eq(&self, other: &Self) -> bool {
+                let (this, other) = (self.as_slice(), other.as_slice());
+                true #( && this.#cols == other.#cols )*
+            }
+        }
+
+        impl #impl_generics core::cmp::Eq for #soa_name #ty_generics #total_eq_where {}
+
+        /// Hashes the same way as `Vec<Foo>` with a derived `Hash` on `Foo`.
+        impl #impl_generics core::hash::Hash for #soa_name #ty_generics #hash_where {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                let slice = self.as_slice();
+                state.write_usize(slice.len());
+                for i in 0..slice.len() {
+                    #( core::hash::Hash::hash(&#elements, state); )*
+                }
+            }
+        }
+    }
+}
+
+/// Formats `elements`, one per column, into `f` like a derived `Debug` on the source
+/// struct.
+pub(crate) fn debug_record(soa: &Soa, elements: &[TokenStream]) -> TokenStream {
+    let name = soa.name().unraw().to_string();
+    if soa.tuple {
+        quote! {
+            f.debug_tuple(#name)
+                #( .field(&#elements) )*
+                .finish()
+        }
+    } else {
+        let labels = soa.columns.iter().map(|c| match &c.member {
+            Member::Named(ident) => ident.unraw().to_string(),
+            Member::Unnamed(index) => index.index.to_string(),
+        });
+        quote! {
+            f.debug_struct(#name)
+                #( .field(#labels, &#elements) )*
+                .finish()
+        }
+    }
+}
+
+/// Element `i` of each column of `slice`.
+fn elements(soa: &Soa) -> Vec<TokenStream> {
+    soa.columns
+        .iter()
+        .map(|c| {
+            let col = &c.ident;
+            c.index(quote! { slice.#col }, quote! { i })
+        })
+        .collect()
+}
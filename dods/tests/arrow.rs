@@ -0,0 +1,155 @@
+#![cfg(feature = "arrow")]
+
+use arrow::array::{Array, AsArray, Float32Array, Int32Array, StringArray};
+use arrow::datatypes::{DataType, Field, Float32Type, Schema, UInt64Type};
+use arrow::record_batch::RecordBatch;
+use dods::SoA;
+use std::sync::Arc;
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(arrow)]
+struct SensorReading {
+    timestamp: u64,
+    temperature: f32,
+    station: String,
+    #[soa(bitpacked)]
+    calibrated: bool,
+    #[soa(nullable)]
+    pressure: Option<f32>,
+    note: Option<String>,
+}
+
+fn readings() -> SensorReadingSoA {
+    (0..4)
+        .map(|i| SensorReading {
+            timestamp: 100 + i,
+            temperature: 20.0 + i as f32,
+            station: format!("s{}", i % 2),
+            calibrated: i != 1,
+            pressure: (i != 2).then_some(1000.0 + i as f32),
+            note: (i == 3).then(|| "late".to_string()),
+        })
+        .collect()
+}
+
+#[test]
+fn soa_to_record_batch() {
+    let schema = SensorReadingSoA::arrow_schema();
+    assert_eq!(
+        schema
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>(),
+        [
+            "timestamp",
+            "temperature",
+            "station",
+            "calibrated",
+            "pressure",
+            "note"
+        ]
+    );
+    assert_eq!(schema.field(0).data_type(), &DataType::UInt64);
+    assert!(!schema.field(2).is_nullable());
+    assert_eq!(schema.field(4).data_type(), &DataType::Float32);
+    assert!(schema.field(4).is_nullable());
+
+    let station = readings();
+    let batch = station.to_record_batch();
+    assert_eq!(batch.num_rows(), 4);
+    assert_eq!(
+        batch.column(0).as_primitive::<UInt64Type>().values(),
+        &[100, 101, 102, 103]
+    );
+    assert_eq!(batch.column(2).as_string::<i32>().value(1), "s1");
+    assert_eq!(
+        batch.column(3).as_boolean().iter().collect::<Vec<_>>(),
+        [Some(true), Some(false), Some(true), Some(true)]
+    );
+    let pressure = batch.column(4).as_primitive::<Float32Type>();
+    assert_eq!(pressure.null_count(), 1);
+    assert!(pressure.is_null(2));
+    assert_eq!(pressure.value(3), 1003.0);
+    assert_eq!(batch.column(5).null_count(), 3);
+
+    assert_eq!(SensorReadingSoA::try_from(&batch).unwrap(), station);
+    assert_eq!(SensorReadingSoA::try_from(batch).unwrap(), station);
+}
+
+#[test]
+fn soa_try_from_record_batch_errors() {
+    #[derive(SoA, Debug, PartialEq)]
+    #[soa(arrow)]
+    struct Point {
+        x: i32,
+        y: Option<f32>,
+    }
+
+    let batch = |fields: Vec<Field>, columns: Vec<Arc<dyn Array>>| {
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+    };
+    let y = Field::new("y", DataType::Float32, true);
+    let ys = Arc::new(Float32Array::from(vec![Some(1.0), None]));
+
+    let points = PointSoA::try_from(batch(
+        vec![
+            Field::new("label", DataType::Utf8, false),
+            y.clone(),
+            Field::new("x", DataType::Int32, false),
+        ],
+        vec![
+            Arc::new(StringArray::from(vec!["a", "b"])),
+            ys.clone(),
+            Arc::new(Int32Array::from(vec![3, 4])),
+        ],
+    ))
+    .unwrap();
+    assert_eq!(points.x, [3, 4]);
+    assert_eq!(points.y, [Some(1.0), None]);
+
+    let err = PointSoA::try_from(batch(vec![y.clone()], vec![ys.clone()])).unwrap_err();
+    assert_eq!(err.to_string(), "Schema error: missing column `x`");
+
+    let err = PointSoA::try_from(batch(
+        vec![Field::new("x", DataType::Utf8, false), y.clone()],
+        vec![Arc::new(StringArray::from(vec!["a", "b"])), ys.clone()],
+    ))
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Schema error: column `x` is Utf8, expected Int32"
+    );
+
+    let err = PointSoA::try_from(batch(
+        vec![Field::new("x", DataType::Int32, true), y],
+        vec![Arc::new(Int32Array::from(vec![Some(1), None])), ys],
+    ))
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Schema error: column `x` has nulls, but is not an Option"
+    );
+}
+
+/// The generated code names `::arrow`, so a local `arrow` module does not break it.
+mod shadowed {
+    use dods::SoA;
+
+    #[allow(dead_code)]
+    mod arrow {}
+
+    #[derive(SoA)]
+    #[soa(arrow)]
+    pub struct Tick {
+        pub t: u64,
+    }
+}
+
+#[test]
+fn soa_arrow_with_local_arrow_module() {
+    let ticks: shadowed::TickSoA = (0..3).map(|t| shadowed::Tick { t }).collect();
+    let batch = ticks.to_record_batch();
+    assert_eq!(batch.num_rows(), 3);
+    assert_eq!(shadowed::TickSoA::try_from(batch).unwrap().t, [0, 1, 2]);
+}
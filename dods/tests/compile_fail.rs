@@ -0,0 +1,50 @@
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn arrow_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/arrow/*.rs");
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/mmap/*.rs");
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn csv_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/csv/*.rs");
+}
+
+/// `#[soa(rayon)]`, `#[soa(serde)]`, `#[soa(csv)]`, `#[soa(arrow)]` and `#[soa(mmap)]`
+/// without the feature they need.
+#[cfg(not(all(
+    feature = "rayon",
+    feature = "serde",
+    feature = "csv",
+    feature = "arrow",
+    feature = "mmap"
+)))]
+#[test]
+fn missing_feature() {
+    let t = trybuild::TestCases::new();
+    #[cfg(not(feature = "rayon"))]
+    t.compile_fail("tests/ui/missing_feature/rayon.rs");
+    #[cfg(not(feature = "serde"))]
+    t.compile_fail("tests/ui/missing_feature/serde.rs");
+    #[cfg(not(feature = "csv"))]
+    t.compile_fail("tests/ui/missing_feature/csv.rs");
+    #[cfg(not(feature = "arrow"))]
+    t.compile_fail("tests/ui/missing_feature/arrow.rs");
+    #[cfg(not(feature = "mmap"))]
+    t.compile_fail("tests/ui/missing_feature/mmap.rs");
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "csv")]
+
+use dods::SoA;
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(csv)]
+struct SensorReading {
+    #[soa(rename = "Time (s)")]
+    timestamp: u64,
+    temperature: f32,
+    station: String,
+    #[soa(bitpacked)]
+    calibrated: bool,
+    #[soa(nullable)]
+    pressure: Option<f32>,
+    note: Option<String>,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(csv, storage = "single")]
+struct Sample(
+    #[soa(name = "time")] u64,
+    #[soa(name = "value")] Option<f64>,
+);
+
+const CSV: &str = "\
+Time (s),temperature,station,calibrated,pressure,note
+100,20.5,north,true,1000.5,
+101,21,\"south, east\",false,,late
+";
+
+#[test]
+fn soa_read_and_write_csv() {
+    let station = SensorReadingSoA::read_csv(CSV.as_bytes()).unwrap();
+    assert_eq!(station.len(), 2);
+    assert_eq!(station.timestamp, [100, 101]);
+    assert_eq!(station.temperature, [20.5, 21.0]);
+    assert_eq!(station.station, ["north", "south, east"]);
+    assert_eq!(
+        station.calibrated.as_slice().iter().collect::<Vec<_>>(),
+        [&true, &false]
+    );
+    assert_eq!(
+        station.pressure.as_slice().iter().collect::<Vec<_>>(),
+        [Some(&1000.5), None]
+    );
+    assert_eq!(station.note, [None, Some("late".to_string())]);
+
+    let mut out = Vec::new();
+    station.write_csv(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), CSV);
+
+    // Columns are matched by header, in any order, and extra ones are skipped.
+    let shuffled = "\
+note,station,extra,pressure,calibrated,temperature,Time (s)
+,north,x,1000.5,true,20.5,100
+late,\"south, east\",y,,false,21,101
+";
+    assert_eq!(
+        SensorReadingSoA::read_csv(shuffled.as_bytes()).unwrap(),
+        station
+    );
+}
+
+#[test]
+fn soa_read_csv_reports_row_and_column() {
+    let bad = CSV.replace("21,", "warm,");
+    let err = SensorReadingSoA::read_csv(bad.as_bytes()).unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            SensorReadingCsvError::Parse {
+                row: 3,
+                column: "temperature",
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+    assert_eq!(
+        err.to_string(),
+        "row 3, column `temperature`: invalid float literal"
+    );
+
+    let err = SensorReadingSoA::read_csv("temperature\n1.0\n".as_bytes()).unwrap_err();
+    assert_eq!(err.to_string(), "the CSV has no `Time (s)` column");
+}
+
+#[test]
+fn single_storage_read_and_write_csv() {
+    let csv = "time,value\n1,0.5\n2,\n";
+    let samples = SampleSoA::read_csv(csv.as_bytes()).unwrap();
+    assert_eq!(samples.time(), [1, 2]);
+    assert_eq!(samples.value(), [Some(0.5), None]);
+
+    let mut out = Vec::new();
+    samples.write_csv(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), csv);
+}
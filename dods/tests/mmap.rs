@@ -0,0 +1,133 @@
+#![cfg(feature = "mmap")]
+
+use dods::SoA;
+use std::path::PathBuf;
+
+#[derive(SoA, Debug, PartialEq, Clone, Copy)]
+#[soa(mmap)]
+struct SensorReading {
+    timestamp: u64,
+    temperature: f32,
+    station: u16,
+    #[soa(name = "position")]
+    pos: [f64; 2],
+}
+
+#[derive(SoA, Debug, PartialEq, Clone, Copy)]
+#[soa(mmap)]
+struct Pressure {
+    timestamp: u64,
+    pressure: f32,
+}
+
+/// A path in the temp dir that is removed again when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        TempFile(std::env::temp_dir().join(format!("dods-{}-{}", std::process::id(), name)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn readings(n: u64) -> SensorReadingSoA {
+    (0..n)
+        .map(|i| SensorReading {
+            timestamp: 1_000 + i,
+            temperature: i as f32 * 0.5,
+            station: (i % 7) as u16,
+            pos: [i as f64, -(i as f64)],
+        })
+        .collect()
+}
+
+#[test]
+fn soa_write_to_and_mmap() {
+    let file = TempFile::new("readings.soa");
+    let station = readings(1_000);
+    station.write_to(&file.0).unwrap();
+
+    let mapped = unsafe { SensorReadingMmap::open(&file.0) }.unwrap();
+    assert_eq!(mapped.len(), 1_000);
+    let slice = mapped.as_slice();
+    assert_eq!(slice.timestamp, station.timestamp.as_slice());
+    assert_eq!(slice.temperature, station.temperature.as_slice());
+    assert_eq!(slice.station, station.station.as_slice());
+    assert_eq!(slice.position, station.position.as_slice());
+    for column in [
+        slice.timestamp.as_ptr() as usize,
+        slice.temperature.as_ptr() as usize,
+        slice.station.as_ptr() as usize,
+        slice.position.as_ptr() as usize,
+    ] {
+        assert_eq!(column % 64, 0);
+    }
+    let hot: f32 = slice.slice(500..).iter().map(|r| *r.temperature).sum();
+    assert_eq!(hot, (500..1_000).map(|i| i as f32 * 0.5).sum());
+
+    let empty = TempFile::new("empty.soa");
+    SensorReadingSoA::new().write_to(&empty.0).unwrap();
+    let mapped = unsafe { SensorReadingMmap::open(&empty.0) }.unwrap();
+    assert!(mapped.is_empty());
+    assert_eq!(mapped.as_slice().len(), 0);
+}
+
+#[test]
+fn soa_mmap_rejects_other_files() {
+    let file = TempFile::new("pressure.soa");
+    let pressure: PressureSoA = (0..10)
+        .map(|i| Pressure {
+            timestamp: i,
+            pressure: 1000.0,
+        })
+        .collect();
+    pressure.write_to(&file.0).unwrap();
+    let err = unsafe { SensorReadingMmap::open(&file.0) }.err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(
+        err.to_string(),
+        "the column file was not written by SensorReadingSoA"
+    );
+
+    std::fs::write(&file.0, b"timestamp,pressure\n").unwrap();
+    let err = unsafe { PressureMmap::open(&file.0) }.err().unwrap();
+    assert_eq!(err.to_string(), "not a PressureSoA column file");
+
+    // Cut off in the middle of the last column.
+    pressure.write_to(&file.0).unwrap();
+    let bytes = std::fs::read(&file.0).unwrap();
+    std::fs::write(&file.0, &bytes[..bytes.len() - 2]).unwrap();
+    let err = unsafe { PressureMmap::open(&file.0) }.err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "column `pressure` does not fit in the column file"
+    );
+}
+
+/// The generated code names `::memmap2`, so a local `memmap2` module does not break it.
+mod shadowed {
+    use dods::SoA;
+
+    #[allow(dead_code)]
+    mod memmap2 {}
+
+    #[derive(SoA)]
+    #[soa(mmap)]
+    pub struct Tick {
+        pub t: u64,
+    }
+}
+
+#[test]
+fn soa_mmap_with_local_memmap2_module() {
+    let file = TempFile::new("shadowed.col");
+    let ticks: shadowed::TickSoA = (0..3).map(|t| shadowed::Tick { t }).collect();
+    ticks.write_to(&file.0).unwrap();
+    let mapped = unsafe { shadowed::TickMmap::open(&file.0) }.unwrap();
+    assert_eq!(mapped.as_slice().t, [0, 1, 2]);
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "rayon")]
+
+use dods::SoA;
+use rayon::prelude::*;
+
+#[derive(SoA, Debug, PartialEq, Clone, Copy)]
+#[soa(rayon)]
+struct Vec2 {
+    x: f32,
+    y: f32,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(rayon)]
+struct Body {
+    id: u32,
+    #[soa(nested)]
+    pos: Vec2,
+    #[soa(nested)]
+    vel: Vec2,
+    #[soa(bitpacked)]
+    asleep: bool,
+    #[soa(nullable)]
+    charge: Option<f32>,
+}
+
+fn bodies(n: u32) -> BodySoA {
+    (0..n)
+        .map(|i| Body {
+            id: i,
+            pos: Vec2 {
+                x: i as f32,
+                y: 0.0,
+            },
+            vel: Vec2 { x: 1.0, y: -1.0 },
+            asleep: i % 5 == 0,
+            charge: (i % 2 == 0).then_some(i as f32),
+        })
+        .collect()
+}
+
+#[test]
+fn soa_par_iter_matches_iter() {
+    let soa = bodies(10_000);
+    let total: u64 = soa.par_iter().map(|b| *b.id as u64).sum();
+    assert_eq!(total, soa.iter().map(|b| *b.id as u64).sum());
+    assert_eq!(soa.par_iter().len(), 10_000);
+    assert_eq!(soa.par_iter().filter(|b| *b.asleep).count(), 2_000);
+    assert_eq!(soa.par_iter().filter_map(|b| b.charge).count(), 5_000);
+
+    let ids: Vec<u32> = soa.slice(100..200).par_iter().map(|b| *b.id).collect();
+    assert_eq!(ids, (100..200).collect::<Vec<_>>());
+    let xs: Vec<f32> = soa.as_slice().into_par_iter().map(|b| *b.pos.x).collect();
+    assert_eq!(xs.len(), 10_000);
+    assert_eq!(xs[7], 7.0);
+}
+
+#[test]
+fn soa_par_iter_mut_and_chunks_mut() {
+    let mut soa = bodies(10_000);
+    soa.par_iter_mut().for_each(|b| {
+        if !b.asleep.get() {
+            *b.pos.x += *b.vel.x;
+            *b.pos.y += *b.vel.y;
+        }
+        if let Some(charge) = b.charge {
+            *charge = -*charge;
+        }
+    });
+    assert_eq!(*soa.index(1).pos.x, 2.0);
+    assert_eq!(*soa.index(5).pos.x, 5.0);
+    assert_eq!(soa.index(4).charge, Some(&-4.0));
+
+    soa.par_chunks_mut(1_000)
+        .enumerate()
+        .for_each(|(i, mut chunk)| {
+            assert_eq!(chunk.len(), 1_000);
+            chunk.iter_mut().for_each(|b| *b.id = i as u32);
+        });
+    assert_eq!(*soa.index(4_321).id, 4);
+    assert_eq!(soa.par_chunks_mut(3_000).len(), 4);
+    assert_eq!(
+        soa.par_chunks_mut(3_000)
+            .map(|c| c.len())
+            .collect::<Vec<_>>(),
+        [3_000, 3_000, 3_000, 1_000]
+    );
+    assert_eq!(
+        soa.par_chunks_mut(3_000)
+            .rev()
+            .map(|c| c.len())
+            .collect::<Vec<_>>(),
+        [1_000, 3_000, 3_000, 3_000]
+    );
+    soa.par_chunks_mut(7)
+        .with_max_len(1)
+        .enumerate()
+        .for_each(|(i, mut chunk)| {
+            chunk.iter_mut().for_each(|b| *b.id = i as u32);
+        });
+    assert!((0..10_000).all(|i| *soa.index(i).id == (i / 7) as u32));
+    assert_eq!(soa.slice_mut(..0).par_chunks_mut(4).count(), 0);
+
+    let mut slice = soa.slice_mut(..10);
+    slice.par_iter_mut().for_each(|mut b| b.asleep.set(true));
+    assert_eq!(
+        slice.par_chunks_mut(4).map(|c| c.len()).collect::<Vec<_>>(),
+        [4, 4, 2]
+    );
+    assert_eq!(slice.par_iter().filter(|b| *b.asleep).count(), 10);
+}
+
+#[test]
+fn soa_par_sort_by_is_stable() {
+    let mut soa = bodies(5_000);
+    soa.par_sort_by(|a, b| (*a.id % 7).cmp(&(*b.id % 7)));
+    assert!(soa.is_sorted_by_key(|b| *b.id % 7));
+    assert!(soa.slice(..715).iter().map(|b| *b.id).is_sorted());
+    assert_eq!(soa.pos.as_slice().x[714], 4_998.0);
+    assert_eq!(soa.asleep.as_slice().count_ones(), 1_000);
+
+    let mut slice = soa.slice_mut(..715);
+    slice.par_sort_by(|a, b| b.id.cmp(a.id));
+    assert_eq!(*slice.index(0).id, 4_998);
+    assert_eq!(slice.index(0).charge, Some(&4_998.0));
+}
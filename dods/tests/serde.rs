@@ -0,0 +1,149 @@
+#![cfg(feature = "serde")]
+
+use dods::SoA;
+use serde::{Deserialize, Serialize};
+
+#[derive(SoA, Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[soa(derive(Debug, PartialEq), serde)]
+struct Position {
+    lat: f32,
+    lon: f32,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[soa(serde = "columns")]
+struct SensorReading {
+    timestamp: u64,
+    temperature: f32,
+    #[soa(nested)]
+    position: Position,
+    #[soa(bitpacked)]
+    calibrated: bool,
+    #[soa(nullable)]
+    pressure: Option<f32>,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(serde, storage = "single")]
+struct Pixel {
+    x: u16,
+    y: u16,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[soa(serde = "rows")]
+struct Sample(u32, #[soa(name = "label")] String);
+
+fn readings() -> SensorReadingSoA {
+    (0..3)
+        .map(|i| SensorReading {
+            timestamp: 100 + i,
+            temperature: 20.5 + i as f32,
+            position: Position {
+                lat: 1.0,
+                lon: i as f32,
+            },
+            calibrated: i != 1,
+            pressure: (i != 2).then_some(1000.0 + i as f32),
+        })
+        .collect()
+}
+
+#[test]
+fn soa_serializes_columns() {
+    let station = readings();
+    let json = serde_json::to_string(&station).unwrap();
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"timestamp":[100,101,102],"temperature":[20.5,21.5,22.5],"#,
+            r#""position":{"lat":[1.0,1.0,1.0],"lon":[0.0,1.0,2.0]},"#,
+            r#""calibrated":[true,false,true],"pressure":[1000.0,1001.0,null]}"#,
+        )
+    );
+    assert_eq!(
+        serde_json::from_str::<SensorReadingSoA>(&json).unwrap(),
+        station
+    );
+    assert_eq!(
+        serde_json::to_string(&station.slice(1..2)).unwrap(),
+        concat!(
+            r#"{"timestamp":[101],"temperature":[21.5],"position":{"lat":[1.0],"lon":[1.0]},"#,
+            r#""calibrated":[false],"pressure":[1001.0]}"#,
+        )
+    );
+
+    // A single element serializes like the source struct.
+    let first = SensorReading {
+        timestamp: 100,
+        temperature: 20.5,
+        position: Position { lat: 1.0, lon: 0.0 },
+        calibrated: true,
+        pressure: Some(1000.0),
+    };
+    assert_eq!(
+        serde_json::to_string(&station.index(0)).unwrap(),
+        serde_json::to_string(&first).unwrap()
+    );
+
+    let bytes = bincode::serialize(&station).unwrap();
+    assert_eq!(
+        bincode::deserialize::<SensorReadingSoA>(&bytes).unwrap(),
+        station
+    );
+}
+
+#[test]
+fn soa_deserialize_checks_column_lengths() {
+    let json = r#"{"lat":[1.0,2.0],"lon":[3.0]}"#;
+    let err = serde_json::from_str::<PositionSoA>(json).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("column `lon` has 1 elements, but column `lat` has 2"),
+        "{}",
+        err
+    );
+
+    let err = serde_json::from_str::<PositionSoA>(r#"{"lat":[1.0]}"#).unwrap_err();
+    assert!(
+        err.to_string().starts_with("missing field `lon`"),
+        "{}",
+        err
+    );
+
+    let soa: PositionSoA = serde_json::from_str(r#"{"lon":[3.0],"alt":[0],"lat":[1.0]}"#).unwrap();
+    assert_eq!((soa.lat, soa.lon), (vec![1.0], vec![3.0]));
+}
+
+#[test]
+fn single_storage_deserializes_columns() {
+    let pixels: PixelSoA = (0..3).map(|i| Pixel { x: i, y: i * 2 }).collect();
+    let json = serde_json::to_string(&pixels).unwrap();
+    assert_eq!(json, r#"{"x":[0,1,2],"y":[0,2,4]}"#);
+    assert_eq!(serde_json::from_str::<PixelSoA>(&json).unwrap(), pixels);
+
+    let err = serde_json::from_str::<PixelSoA>(r#"{"x":[0],"y":[]}"#).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("column `y` has 0 elements, but column `x` has 1"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn soa_serializes_rows() {
+    let samples: SampleSoA = [Sample(1, "a".into()), Sample(2, "b".into())]
+        .into_iter()
+        .collect();
+    let json = serde_json::to_string(&samples).unwrap();
+    assert_eq!(json, r#"[[1,"a"],[2,"b"]]"#);
+    assert_eq!(
+        json,
+        serde_json::to_string(&[Sample(1, "a".into()), Sample(2, "b".into())]).unwrap()
+    );
+    assert_eq!(serde_json::from_str::<SampleSoA>(&json).unwrap(), samples);
+
+    let bytes = bincode::serialize(&samples).unwrap();
+    assert_eq!(bincode::deserialize::<SampleSoA>(&bytes).unwrap(), samples);
+}
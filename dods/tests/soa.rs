@@ -1,3 +1,4 @@
+use core::ops::Bound;
 use dods::SoA;
 
 #[derive(SoA, Debug, PartialEq, Clone)]
@@ -7,6 +8,21 @@ struct SensorReading {
     timestamp: u64,
 }
 
+#[derive(SoA, Debug, PartialEq, Clone)]
+struct Particle<T: Copy + core::ops::Add<Output = T>> {
+    pos: T,
+    vel: T,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+struct Label<'a, const N: usize>
+where
+    [u8; N]: Copy,
+{
+    name: &'a str,
+    code: [u8; N],
+}
+
 #[test]
 fn soa_push_swap_remove_pop_len() {
     let mut soa = SensorReadingSoA::new();
@@ -109,7 +125,7 @@ fn soa_iter_mut() {
     soa.push(a.clone());
     soa.push(b.clone());
 
-    for mut r in soa.iter_mut() {
+    for r in soa.iter_mut() {
         *r.temperature += 1.0;
     }
 
@@ -329,3 +345,1251 @@ fn soa_append_split_off_and_sorting() {
     assert_eq!(*manual.get(1).unwrap().timestamp, 1);
     assert_eq!(*manual.get(2).unwrap().timestamp, 2);
 }
+
+fn scrambled_readings(n: u64) -> Vec<SensorReading> {
+    (0..n)
+        .map(|i| SensorReading {
+            temperature: (i * 7919 % 13) as f32,
+            pressure: i as f32,
+            timestamp: i * 2654435761 % 1009,
+        })
+        .collect()
+}
+
+#[test]
+fn soa_sorts_match_vec_sorts() {
+    let readings = scrambled_readings(500);
+    let soa: SensorReadingSoA = readings.iter().cloned().collect();
+
+    let mut expected = readings.clone();
+    expected.sort_by_key(|r| r.timestamp);
+    let mut by_field = soa.clone();
+    by_field.sort_by_field(|s| s.timestamp);
+    assert!(
+        by_field
+            .iter()
+            .map(|r| *r.timestamp)
+            .eq(expected.iter().map(|r| r.timestamp))
+    );
+    assert!(by_field.is_sorted_by_key(|r| *r.timestamp));
+    assert!(!soa.is_sorted_by_key(|r| *r.timestamp));
+
+    let mut expected = readings.clone();
+    expected.sort_by_cached_key(|r| (r.temperature as u32).to_string());
+    let mut cached = soa.clone();
+    cached.sort_by_cached_key(|r| (*r.temperature as u32).to_string());
+    assert_eq!(cached, expected.into_iter().collect());
+
+    let mut unstable = soa.clone();
+    unstable.sort_unstable_by(|a, b| a.pressure.total_cmp(b.pressure).reverse());
+    assert_eq!(*unstable.index(0).pressure, 499.0);
+    assert!(unstable.is_sorted_by(|a, b| a.pressure >= b.pressure));
+    unstable.sort_unstable_by_key(|r| *r.timestamp);
+    assert_eq!(unstable.timestamp, by_field.timestamp);
+
+    let mut permuted = soa.clone();
+    let indices: Vec<usize> = (0..500).map(|i| i * 7 % 500).collect();
+    permuted.apply_index(&indices);
+    assert!((0..500).all(|i| permuted.index(i).pressure == soa.index(indices[i]).pressure));
+
+    let mut repeated = indices.clone();
+    repeated[499] = repeated[0];
+    let mut untouched = soa.clone();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        untouched.apply_index(&repeated);
+    }));
+    assert!(result.is_err());
+    assert_eq!(untouched, soa);
+}
+
+#[test]
+fn soa_generic_type_params() {
+    fn step<T: Copy + core::ops::Add<Output = T>>(soa: &mut ParticleSoA<T>) {
+        for r in soa.iter_mut() {
+            *r.pos = *r.pos + *r.vel;
+        }
+    }
+
+    let mut singles = ParticleSoA::<f32>::new();
+    singles.push(Particle { pos: 1.0, vel: 0.5 });
+    step(&mut singles);
+    assert_eq!(*singles.index(0).pos, 1.5);
+
+    let mut doubles: ParticleSoA<f64> = ParticleSoA::with_capacity(1);
+    doubles.push(Particle {
+        pos: 2.0,
+        vel: 0.25,
+    });
+    step(&mut doubles);
+    let r: ParticleRef<'_, f64> = doubles.get(0).unwrap();
+    assert_eq!(*r.pos, 2.25);
+    assert_eq!(
+        doubles.pop(),
+        Some(Particle {
+            pos: 2.25,
+            vel: 0.25
+        })
+    );
+}
+
+#[test]
+fn soa_generic_lifetimes_and_consts() {
+    let owner = String::from("beta");
+    let mut soa: LabelSoA<'_, 2> = LabelSoA::new();
+    soa.push(Label {
+        name: owner.as_str(),
+        code: [2, 0],
+    });
+    soa.insert(
+        0,
+        Label {
+            name: "alpha",
+            code: [1, 0],
+        },
+    );
+
+    soa.sort_by_key(|r| core::cmp::Reverse(r.code[0]));
+    assert_eq!(*soa.first().unwrap().name, "beta");
+
+    let slice: LabelSlice<'_, '_, 2> = soa.as_slice();
+    assert_eq!(slice.code, [[2, 0], [1, 0]]);
+
+    let removed = soa.remove(1);
+    assert_eq!(
+        removed,
+        Label {
+            name: "alpha",
+            code: [1, 0]
+        }
+    );
+}
+
+#[derive(SoA, Debug, PartialEq, Clone, Copy)]
+struct Vertex(f32, f32, f32);
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+struct Edge(#[soa(name = "from")] u32, #[soa(name = "to")] u32, f32);
+
+#[test]
+fn soa_tuple_structs() {
+    let mut soa = VertexSoA::new();
+    soa.push(Vertex(1.0, 2.0, 3.0));
+    soa.push(Vertex(4.0, 5.0, 6.0));
+    assert_eq!(soa._1, [2.0, 5.0]);
+
+    let r: VertexRef<'_> = soa.index(1);
+    assert_eq!((*r._0, *r._1, *r._2), (4.0, 5.0, 6.0));
+
+    for r in soa.iter_mut() {
+        *r._2 *= 2.0;
+    }
+    let sum: f32 = soa.iter().map(|r| *r._2).sum();
+    assert_eq!(sum, 18.0);
+
+    assert_eq!(soa.replace(0, Vertex(0.0, 0.0, 0.0)), Vertex(1.0, 2.0, 6.0));
+    assert_eq!(soa.pop(), Some(Vertex(4.0, 5.0, 12.0)));
+    assert_eq!(soa.remove(0), Vertex(0.0, 0.0, 0.0));
+
+    let mut edges = EdgeSoA::new();
+    edges.push(Edge(0, 1, 0.5));
+    edges.insert(0, Edge(1, 2, 1.5));
+    assert_eq!(edges.from, [1, 0]);
+    assert_eq!(edges.to, [2, 1]);
+    assert_eq!(edges._2, [1.5, 0.5]);
+    assert_eq!(edges.swap_remove(0), Edge(1, 2, 1.5));
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(name = "Swarm", ref_name = "Bee", ref_mut_name = "BeeMut")]
+#[soa(slice_name = "SwarmSlice", derive(Debug, Clone, PartialEq))]
+struct Insect {
+    wings: u8,
+    legs: u8,
+}
+
+#[test]
+fn soa_container_attribute_names_and_derives() {
+    let mut swarm = Swarm::new();
+    swarm.push(Insect { wings: 4, legs: 6 });
+    swarm.push(Insect { wings: 2, legs: 6 });
+
+    let copy = swarm.clone();
+    assert_eq!(copy, swarm);
+    assert_eq!(format!("{:?}", copy.index(1)), "Bee { wings: 2, legs: 6 }");
+
+    let slice: SwarmSlice<'_> = swarm.slice(..1);
+    assert_eq!(slice.clone(), slice);
+
+    let bee: Bee<'_> = swarm.index(0);
+    assert_eq!(bee, swarm.first().unwrap());
+    let bee_mut: BeeMut<'_> = swarm.index_mut(0);
+    *bee_mut.wings = 0;
+    assert_eq!(format!("{:?}", bee_mut), "BeeMut { wings: 0, legs: 6 }");
+    assert_eq!(swarm.wings, [0, 2]);
+}
+
+mod registry {
+    use dods::SoA;
+
+    struct Secret(u32);
+
+    #[derive(SoA)]
+    pub(crate) struct Entry {
+        pub(crate) id: u32,
+        secret: Secret,
+    }
+
+    #[derive(SoA)]
+    #[soa(vis = "pub(crate)")]
+    pub struct Tag {
+        pub value: u8,
+    }
+
+    pub(crate) fn entries() -> EntrySoA {
+        let mut soa = EntrySoA::new();
+        soa.push(Entry {
+            id: 7,
+            secret: Secret(42),
+        });
+        soa
+    }
+
+    pub(crate) fn secret_sum(soa: &EntrySoA) -> u32 {
+        soa.secret.iter().map(|s| s.0).sum()
+    }
+}
+
+#[test]
+fn soa_mirrors_visibility() {
+    let soa = registry::entries();
+    assert_eq!(soa.id, [7]);
+    assert_eq!(registry::secret_sum(&soa), 42);
+
+    let mut tags = registry::TagSoA::new();
+    tags.push(registry::Tag { value: 3 });
+    assert_eq!(tags.value, [3]);
+}
+
+#[derive(SoA, Debug, PartialEq, Eq, Hash, Clone)]
+struct Cell(u32, char);
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+struct Board {
+    cells: CellSoA,
+}
+
+#[test]
+fn soa_standard_traits() {
+    fn hash_of<T: core::hash::Hash>(value: &T) -> u64 {
+        use core::hash::{BuildHasher, BuildHasherDefault};
+        BuildHasherDefault::<std::collections::hash_map::DefaultHasher>::default().hash_one(value)
+    }
+
+    let mut board = Board::default();
+    assert!(board.cells.is_empty());
+    board.cells.push(Cell(1, 'a'));
+    board.cells.push(Cell(2, 'b'));
+
+    let copy = board.clone();
+    assert_eq!(copy, board);
+    assert_eq!(
+        hash_of(&copy.cells),
+        hash_of(&vec![Cell(1, 'a'), Cell(2, 'b')])
+    );
+
+    let mut other = CellSoA::new();
+    other.clone_from(&board.cells);
+    other.index_mut(1)._1.make_ascii_uppercase();
+    assert_ne!(other, board.cells);
+
+    assert_eq!(format!("{:?}", other), "[Cell(1, 'a'), Cell(2, 'B')]");
+
+    let mut readings = SensorReadingSoA::default();
+    readings.push(SensorReading {
+        temperature: 1.5,
+        pressure: 2.0,
+        timestamp: 3,
+    });
+    assert_eq!(
+        format!("{:?}", readings),
+        format!(
+            "{:?}",
+            vec![SensorReading {
+                temperature: 1.5,
+                pressure: 2.0,
+                timestamp: 3,
+            }]
+        )
+    );
+}
+
+#[test]
+fn soa_collect_extend_and_into_iter() {
+    let readings: Vec<SensorReading> = (0..4)
+        .map(|i| SensorReading {
+            temperature: i as f32,
+            pressure: 100.0 + i as f32,
+            timestamp: i,
+        })
+        .collect();
+
+    let mut soa: SensorReadingSoA = readings.iter().take(2).cloned().collect();
+    assert_eq!(soa.len(), 2);
+
+    soa.extend(readings.iter().skip(2).cloned());
+    assert_eq!(soa.timestamp, [0, 1, 2, 3]);
+
+    let mut total = 0;
+    for r in &soa {
+        total += *r.timestamp;
+    }
+    assert_eq!(total, 6);
+
+    for r in &mut soa {
+        *r.pressure -= 100.0;
+    }
+    assert_eq!(soa.pressure, [0.0, 1.0, 2.0, 3.0]);
+
+    let iter = soa.iter();
+    assert_eq!(iter.len(), 4);
+    assert_eq!(
+        soa.iter().rev().map(|r| *r.timestamp).collect::<Vec<_>>(),
+        [3, 2, 1, 0]
+    );
+    assert_eq!(*soa.iter().nth(2).unwrap().temperature, 2.0);
+
+    let mut owned = soa.into_iter();
+    assert_eq!(owned.len(), 4);
+    assert_eq!(owned.next_back().map(|r| r.timestamp), Some(3));
+    let rest: Vec<SensorReading> = owned.collect();
+    assert_eq!(rest.len(), 3);
+    assert_eq!(rest[1].pressure, 1.0);
+
+    let vertices: VertexSoA = [Vertex(1.0, 2.0, 3.0), Vertex(4.0, 5.0, 6.0)]
+        .into_iter()
+        .collect();
+    assert_eq!(vertices.into_iter().map(|v| v.0).sum::<f32>(), 5.0);
+}
+
+fn mean_temperature(readings: SensorReadingSlice<'_>) -> f32 {
+    readings.iter().map(|r| *r.temperature).sum::<f32>() / readings.len() as f32
+}
+
+#[test]
+fn soa_slice_api() {
+    let mut soa: SensorReadingSoA = (0..10)
+        .map(|i| SensorReading {
+            temperature: i as f32,
+            pressure: 0.0,
+            timestamp: i,
+        })
+        .collect();
+
+    let all = soa.as_slice();
+    assert_eq!(all.len(), 10);
+    assert!(!all.is_empty());
+    assert_eq!(mean_temperature(all), 4.5);
+    assert_eq!(*all.first().unwrap().timestamp, 0);
+    assert_eq!(*all.last().unwrap().timestamp, 9);
+    assert!(all.get(10).is_none());
+
+    let (head, tail) = all.split_at(4);
+    assert_eq!(head.len(), 4);
+    assert_eq!(*tail.index(0).timestamp, 4);
+    let inner = tail.slice(1..=2);
+    assert_eq!(inner.timestamp, [5, 6]);
+    assert!(inner.slice(2..).is_empty());
+    assert!(inner.slice(2..).last().is_none());
+
+    let sizes: Vec<usize> = all.chunks(4).map(|c| c.len()).collect();
+    assert_eq!(sizes, [4, 4, 2]);
+    let means: Vec<f32> = all.chunks(5).map(mean_temperature).collect();
+    assert_eq!(means, [2.0, 7.0]);
+    assert_eq!(all.into_iter().next_back().map(|r| *r.timestamp), Some(9));
+
+    let mut view = soa.slice_mut(2..8);
+    assert_eq!(view.len(), 6);
+    *view.first_mut().unwrap().pressure = 1.0;
+    *view.last_mut().unwrap().pressure = 2.0;
+    *view.index_mut(1).pressure = 3.0;
+    view.swap(0, 1);
+    assert_eq!(*view.get(0).unwrap().timestamp, 3);
+    assert_eq!(view.as_slice().pressure, [3.0, 1.0, 0.0, 0.0, 0.0, 2.0]);
+
+    {
+        let (mut left, mut right) = view.split_at_mut(3);
+        for r in left.iter_mut() {
+            *r.pressure += 10.0;
+        }
+        *right.get_mut(0).unwrap().pressure = -1.0;
+        assert!(right.get_mut(3).is_none());
+    }
+    for mut chunk in view.chunks_mut(2) {
+        *chunk.index_mut(0).timestamp += 100;
+    }
+    let mut narrowed = view.slice_mut(4..);
+    narrowed
+        .reborrow()
+        .index_mut(1)
+        .temperature
+        .clone_from(&-7.0);
+    for r in view {
+        *r.temperature += 0.5;
+    }
+
+    assert_eq!(
+        soa.pressure,
+        [0.0, 0.0, 13.0, 11.0, 10.0, -1.0, 0.0, 2.0, 0.0, 0.0]
+    );
+    assert_eq!(soa.timestamp, [0, 1, 103, 2, 104, 5, 106, 7, 8, 9]);
+    assert_eq!(soa.temperature[7], -6.5);
+    assert_eq!(soa.slice(..).iter().count(), 10);
+}
+
+fn three_readings() -> SensorReadingSoA {
+    (0..3)
+        .map(|i| SensorReading {
+            temperature: i as f32,
+            pressure: 0.0,
+            timestamp: i,
+        })
+        .collect()
+}
+
+#[test]
+fn soa_get_slice_checks_ranges() {
+    let mut soa = three_readings();
+    assert_eq!(soa.get_slice(1..).unwrap().timestamp, [1, 2]);
+    assert_eq!(soa.get_slice(..=2).unwrap().len(), 3);
+    assert!(soa.get_slice(3..).unwrap().is_empty());
+    assert!(soa.get_slice(..=usize::MAX).is_none());
+    assert!(
+        soa.get_slice((Bound::Excluded(usize::MAX), Bound::Unbounded))
+            .is_none()
+    );
+    #[allow(clippy::reversed_empty_ranges)]
+    let inverted = soa.get_slice(2..1);
+    assert!(inverted.is_none());
+    assert!(soa.get_slice(..4).is_none());
+
+    let view = soa.get_slice_mut(1..2).unwrap();
+    view.pressure[0] = 5.0;
+    assert!(soa.get_slice_mut(0..=3).is_none());
+
+    let mut view = soa.as_mut_slice();
+    assert!(view.get_slice_mut(2..4).is_none());
+    assert_eq!(view.get_slice(1..).unwrap().pressure, [5.0, 0.0]);
+    assert_eq!(view.as_slice().get_slice(..1).unwrap().timestamp, [0]);
+}
+
+#[test]
+#[should_panic(expected = "range 0..4 out of bounds for soa::SensorReadingSoA of length 3")]
+fn soa_slice_past_end_names_type_and_bounds() {
+    three_readings().slice(..4);
+}
+
+#[test]
+#[should_panic(expected = "range 2..1 out of bounds for soa::SensorReadingSoA of length 3")]
+fn soa_slice_inverted_range_panics() {
+    three_readings().slice((Bound::Included(2), Bound::Excluded(1)));
+}
+
+#[test]
+#[should_panic(expected = "out of bounds for soa::SensorReadingSoA of length 2")]
+fn soa_slice_view_overflowing_range_panics() {
+    let mut soa = three_readings();
+    soa.slice_mut(1..).slice_mut(..=usize::MAX);
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(private_columns)]
+pub struct Sample {
+    pub value: f64,
+    pub(crate) weight: f64,
+}
+
+#[test]
+fn soa_private_columns_accessors() {
+    let mut soa = SampleSoA::new();
+    soa.push(Sample {
+        value: 1.0,
+        weight: 0.5,
+    });
+    soa.push(Sample {
+        value: 3.0,
+        weight: 1.5,
+    });
+
+    assert_eq!(soa.value(), [1.0, 3.0]);
+    soa.weight_mut()[1] = 2.0;
+    assert_eq!(soa.weight(), [0.5, 2.0]);
+    for w in soa.weight_mut() {
+        *w *= 2.0;
+    }
+    let weighted: f64 = soa.iter().map(|s| s.value * s.weight).sum();
+    assert_eq!(weighted, 13.0);
+    assert_eq!(
+        soa.pop(),
+        Some(Sample {
+            value: 3.0,
+            weight: 4.0
+        })
+    );
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(
+    expected = "column `pressure` of SensorReadingSoA is out of sync with column `temperature`"
+)]
+fn soa_debug_asserts_columns_in_sync() {
+    let mut soa = three_readings();
+    soa.temperature.push(1.0);
+    soa.pop();
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(storage = "single")]
+struct Packet {
+    flags: u8,
+    payload: std::rc::Rc<String>,
+    stamp: f64,
+    port: u16,
+}
+
+fn packet(i: u16, payload: &std::rc::Rc<String>) -> Packet {
+    Packet {
+        flags: i as u8,
+        payload: payload.clone(),
+        stamp: i as f64 / 2.0,
+        port: 8000 + i,
+    }
+}
+
+#[test]
+fn soa_single_storage_keeps_the_vec_api() {
+    let payload = std::rc::Rc::new(String::from("ping"));
+    let mut soa = PacketSoA::with_capacity(2);
+    assert_eq!(soa.capacity(), 2);
+    for i in 0..10 {
+        soa.push(packet(i, &payload));
+    }
+    assert_eq!(soa.len(), 10);
+    assert!(soa.capacity() >= 10);
+    assert_eq!(soa.port()[9], 8009);
+    assert_eq!(*soa.index(4).stamp, 2.0);
+    assert_eq!(
+        soa.as_ptr().stamp as usize % core::mem::align_of::<f64>(),
+        0
+    );
+    assert_eq!(soa.as_ptr().port as usize % core::mem::align_of::<u16>(), 0);
+
+    soa.insert(0, packet(100, &payload));
+    assert_eq!(soa.remove(1), packet(0, &payload));
+    assert_eq!(soa.swap_remove(0), packet(100, &payload));
+    assert_eq!(soa.flags(), [9, 1, 2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(soa.replace(0, packet(0, &payload)), packet(9, &payload));
+
+    let mut tail = soa.split_off(6);
+    assert_eq!(tail.port(), [8006, 8007, 8008]);
+    tail.append(&mut soa);
+    assert!(soa.is_empty());
+    assert_eq!(tail.flags(), [6, 7, 8, 0, 1, 2, 3, 4, 5]);
+
+    tail.sort_by_key(|p| *p.flags);
+    let copy = tail.clone();
+    assert_eq!(copy, tail);
+    assert_eq!(copy.slice(2..4).flags, [2, 3]);
+    tail.iter_mut().for_each(|p| *p.port += 1);
+    assert_eq!(tail.port()[0], 8001);
+
+    tail.truncate(5);
+    tail.shrink_to_fit();
+    assert_eq!(tail.capacity(), 5);
+    assert_eq!(tail.pop().map(|p| p.flags), Some(4));
+    assert_eq!(std::rc::Rc::strong_count(&payload), 1 + 4 + 9);
+
+    let mut owned = copy.into_iter();
+    assert_eq!(owned.next().map(|p| p.port), Some(8000));
+    assert_eq!(owned.next_back().map(|p| p.port), Some(8008));
+    drop(owned);
+    drop(tail);
+    assert_eq!(std::rc::Rc::strong_count(&payload), 1);
+}
+
+#[derive(SoA, Debug, PartialEq, Clone, Copy)]
+#[soa(storage = "single")]
+struct Marker((), [u64; 0]);
+
+#[test]
+fn soa_single_storage_zero_sized_columns() {
+    let mut soa: MarkerSoA = (0..3).map(|_| Marker((), [])).collect();
+    assert_eq!(soa.len(), 3);
+    assert_eq!(soa.capacity(), usize::MAX);
+    assert_eq!(soa.pop(), Some(Marker((), [])));
+    assert_eq!(soa.iter().count(), 2);
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(aosoa = 4)]
+struct Body {
+    x: f32,
+    y: f32,
+    name: String,
+}
+
+fn body(i: usize) -> Body {
+    Body {
+        x: i as f32,
+        y: -(i as f32),
+        name: format!("b{i}"),
+    }
+}
+
+#[test]
+fn soa_aosoa_chunks_and_scalar_access() {
+    let mut bodies: BodyAoSoA = (0..10).map(body).collect();
+    assert_eq!(BodyAoSoA::LANES, 4);
+    assert_eq!(bodies.len(), 10);
+    assert_eq!(bodies.chunks().len(), 3);
+    assert!(bodies.capacity() >= 12);
+
+    let xs: Vec<[f32; 4]> = bodies.chunks().map(|chunk| chunk.x).collect();
+    assert_eq!(
+        xs,
+        [
+            [0.0, 1.0, 2.0, 3.0],
+            [4.0, 5.0, 6.0, 7.0],
+            [8.0, 9.0, 0.0, 0.0]
+        ]
+    );
+    for chunk in bodies.chunks_mut() {
+        for (x, y) in chunk.x.iter_mut().zip(&chunk.y) {
+            *x += *y * 2.0;
+        }
+    }
+    assert_eq!(*bodies.index(5).x, -5.0);
+    assert_eq!(bodies.get(6).map(|b| b.name.as_str()), Some("b6"));
+    assert!(bodies.get(10).is_none());
+    bodies.index_mut(9).name.push('!');
+    assert_eq!(
+        bodies.iter().rev().map(|b| b.name.clone()).next(),
+        Some(String::from("b9!"))
+    );
+
+    assert_eq!(bodies.pop().map(|b| b.name), Some(String::from("b9!")));
+    assert_eq!(bodies.pop().map(|b| b.y), Some(-8.0));
+    assert_eq!(bodies.chunks().len(), 2);
+    bodies.push(body(20));
+    assert_eq!(bodies.chunks().last().unwrap().name[0], "b20");
+    assert_eq!(bodies.iter().len(), 9);
+
+    let mut bodies: BodyAoSoA = (0..6).map(body).collect();
+    bodies.insert(1, body(10));
+    assert_eq!(bodies.remove(4).name, "b3");
+    assert_eq!(bodies.swap_remove(0).name, "b0");
+    let names = |bodies: &BodyAoSoA| bodies.iter().map(|b| b.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names(&bodies), ["b5", "b10", "b1", "b2", "b4"]);
+    bodies.sort_by(|a, b| a.x.total_cmp(b.x));
+    assert_eq!(names(&bodies), ["b1", "b2", "b4", "b5", "b10"]);
+    bodies.sort_unstable_by_key(|b| core::cmp::Reverse(b.name.len()));
+    assert_eq!(*bodies.index(0).x, 10.0);
+    bodies.extend_from_slice(&[body(7), body(8)]);
+    bodies.truncate(5);
+    assert_eq!(bodies.len(), 5);
+    assert_eq!(bodies.chunks().len(), 2);
+    assert_eq!(bodies.chunks().last().unwrap().name[1..], ["", "", ""]);
+
+    let mut bodies: BodyAoSoA = (0..6).map(body).collect();
+    for b in bodies.iter_mut().rev().take(3) {
+        *b.x += 100.0;
+    }
+    let xs: Vec<f32> = bodies.iter().map(|b| *b.x).collect();
+    assert_eq!(xs, [0.0, 1.0, 2.0, 103.0, 104.0, 105.0]);
+    assert_eq!(bodies.replace(1, body(11)).name, "b1");
+    assert_eq!(bodies.first().map(|b| b.name.as_str()), Some("b0"));
+    assert_eq!(bodies.last().map(|b| *b.y), Some(-5.0));
+    bodies.last_mut().unwrap().name.push('!');
+    *bodies.first_mut().unwrap().y = 1.0;
+    bodies.reserve(10);
+    assert!(bodies.capacity() >= 16);
+
+    let mut tail = bodies.split_off(4);
+    assert_eq!(names(&bodies), ["b0", "b11", "b2", "b3"]);
+    assert_eq!(names(&tail), ["b4", "b5!"]);
+    let mut rest = tail.split_off(1);
+    assert_eq!(names(&rest), ["b5!"]);
+    assert_eq!(tail.chunks().last().unwrap().name[1], "");
+    tail.append(&mut rest);
+    assert!(rest.is_empty() && rest.chunks().len() == 0);
+    bodies.append(&mut tail);
+    assert_eq!(bodies.chunks().len(), 2);
+    bodies.pop();
+    let mut more: BodyAoSoA = (6..9).map(body).collect();
+    bodies.append(&mut more);
+    assert_eq!(
+        names(&bodies),
+        ["b0", "b11", "b2", "b3", "b4", "b6", "b7", "b8"]
+    );
+
+    let mut copy = bodies.clone();
+    assert_eq!(copy, bodies);
+    assert_eq!(copy.chunks().next(), bodies.chunks().next());
+    bodies.index_mut(7).name.clear();
+    assert_ne!(copy, bodies);
+    assert_eq!(
+        format!("{:?}", copy.split_off(7)),
+        r#"[Body { x: 8.0, y: -8.0, name: "b8" }]"#
+    );
+    assert!(format!("{:?}", copy.chunks().next().unwrap()).starts_with("BodyChunk { x: [0.0,"));
+}
+
+#[derive(SoA)]
+#[soa(aosoa = 2u8)]
+struct Pair(u8, u8);
+
+#[test]
+fn soa_aosoa_suffixed_lanes() {
+    let pairs: PairAoSoA = (0..3).map(|i| Pair(i, i * 2)).collect();
+    assert_eq!(PairAoSoA::LANES, 2);
+    assert_eq!(pairs.chunks().len(), 2);
+    assert_eq!(*pairs.index(2)._1, 4);
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+struct Entity {
+    id: u32,
+    #[soa(group = "hot")]
+    pos: f32,
+    #[soa(group = "hot")]
+    vel: f32,
+    #[soa(group = "cold")]
+    name: String,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(storage = "single")]
+struct Tagged<T> {
+    #[soa(group = "hot")]
+    value: T,
+    #[soa(group = "cold")]
+    tag: u8,
+    #[soa(group = "hot")]
+    weight: f64,
+}
+
+#[test]
+fn soa_field_groups() {
+    let mut entities: EntitySoA = (0..4)
+        .map(|i| Entity {
+            id: i,
+            pos: i as f32,
+            vel: 1.0,
+            name: format!("e{i}"),
+        })
+        .collect();
+
+    for EntityHotRefMut { pos, vel } in entities.iter_hot_mut() {
+        *pos += *vel;
+    }
+    assert_eq!(entities.pos, [1.0, 2.0, 3.0, 4.0]);
+    let names: Vec<&str> = entities.iter_cold().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, ["e0", "e1", "e2", "e3"]);
+
+    let entity = entities.index(2);
+    assert_eq!(
+        (*entity.id, *entity.pos, entity.name.as_str()),
+        (2, 3.0, "e2")
+    );
+
+    let mut tail = entities.slice_mut(2..);
+    assert_eq!(tail.iter_hot().len(), 2);
+    tail.iter_cold_mut().for_each(|e| e.name.push('!'));
+    assert_eq!(entities.slice(1..).iter_cold().next().unwrap().name, "e1");
+    assert_eq!(entities.name[3], "e3!");
+
+    let mut tagged = TaggedSoA::new();
+    for i in 0..3u8 {
+        tagged.push(Tagged {
+            value: i as i64,
+            tag: i,
+            weight: 0.5,
+        });
+    }
+    let total: f64 = tagged.iter_hot().map(|t| *t.value as f64 * *t.weight).sum();
+    assert_eq!(total, 1.5);
+    tagged.iter_cold_mut().for_each(|t| *t.tag *= 2);
+    assert_eq!(tagged.tag(), [0, 2, 4]);
+}
+
+#[test]
+fn soa_project_iterates_chosen_columns() {
+    let mut soa = three_readings();
+
+    for (temperature, pressure) in dods::soa_project!(soa => temperature, mut pressure) {
+        *pressure -= *temperature;
+    }
+    assert_eq!(soa.pressure, [0.0, -1.0, -2.0]);
+
+    let stamps: Vec<u64> = dods::soa_project!(soa => timestamp).copied().collect();
+    assert_eq!(stamps, [0, 1, 2]);
+
+    let tail = soa.slice(1..);
+    let pairs: Vec<_> = dods::soa_project!(tail => timestamp, temperature).collect();
+    assert_eq!(pairs, [(&1, &1.0), (&2, &2.0)]);
+
+    let mut head = soa.slice_mut(..2);
+    dods::soa_project!(head => mut timestamp).for_each(|t| *t *= 10);
+    assert_eq!(soa.timestamp, [0, 10, 2]);
+
+    let mut samples: SampleSoA = (0..4)
+        .map(|i| Sample {
+            value: i as f64,
+            weight: 1.0,
+        })
+        .collect();
+    let projected = dods::soa_project!(samples => mut weight, value);
+    assert_eq!(projected.len(), 4);
+    projected
+        .rev()
+        .take(1)
+        .for_each(|(weight, value)| *weight = *value);
+    assert_eq!(samples.weight(), [1.0, 1.0, 1.0, 3.0]);
+}
+
+#[derive(SoA, Debug, PartialEq, Clone, Copy)]
+#[soa(derive(Debug, PartialEq))]
+struct Vec3 {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(derive(Debug, PartialEq))]
+struct Mote {
+    #[soa(nested)]
+    pos: Vec3,
+    mass: f32,
+}
+
+fn mote(i: usize) -> Mote {
+    let i = i as f32;
+    Mote {
+        pos: Vec3 {
+            x: i,
+            y: i * 2.0,
+            z: -i,
+        },
+        mass: 1.0 + i,
+    }
+}
+
+#[test]
+fn soa_nested_columns() {
+    let mut motes: MoteSoA = (0..4).map(mote).collect();
+    assert_eq!(motes.pos.x, [0.0, 1.0, 2.0, 3.0]);
+    assert_eq!(motes.pos.z, [0.0, -1.0, -2.0, -3.0]);
+
+    let p = motes.index(1);
+    assert_eq!((*p.pos.x, *p.pos.y, *p.mass), (1.0, 2.0, 2.0));
+
+    for p in motes.iter_mut() {
+        *p.pos.x += *p.mass;
+    }
+    assert_eq!(motes.pos.x, [1.0, 3.0, 5.0, 7.0]);
+
+    let old = motes.replace(0, mote(9));
+    assert_eq!(old.pos.x, 1.0);
+    assert_eq!(motes.pos.y[0], 18.0);
+
+    motes.sort_by(|a, b| a.pos.z.total_cmp(b.pos.z));
+    assert_eq!(motes.mass, [10.0, 4.0, 3.0, 2.0]);
+
+    let tail = motes.slice(2..);
+    assert_eq!(tail.pos.len(), 2);
+    assert_eq!(
+        tail.index(0).pos,
+        Vec3Ref {
+            x: &5.0,
+            y: &4.0,
+            z: &-2.0
+        }
+    );
+    motes.slice_mut(1..).swap(0, 2);
+    assert_eq!(motes.pos.x, [9.0, 3.0, 5.0, 7.0]);
+
+    let copy = motes.clone();
+    assert_eq!(copy, motes);
+    let last = motes.pop().unwrap();
+    assert_eq!(
+        (last.pos, last.mass),
+        (
+            Vec3 {
+                x: 7.0,
+                y: 6.0,
+                z: -3.0
+            },
+            4.0
+        )
+    );
+    let rest: Vec<_> = motes.into_iter().collect();
+    assert_eq!(rest.len(), 3);
+    assert_eq!(
+        rest[0].pos,
+        Vec3 {
+            x: 9.0,
+            y: 18.0,
+            z: -9.0
+        }
+    );
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(derive(Debug, PartialEq))]
+enum Event {
+    Click { x: i32, y: i32 },
+    Key { code: u32 },
+    Scroll(f32),
+    Quit,
+}
+
+#[test]
+fn soa_enum_variants() {
+    let mut events: EventSoA = [
+        Event::Click { x: 1, y: 2 },
+        Event::Key { code: 7 },
+        Event::Click { x: 3, y: 4 },
+        Event::Scroll(0.5),
+        Event::Quit,
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(events.len(), 5);
+    assert_eq!(
+        events.tags(),
+        [
+            EventTag::Click,
+            EventTag::Key,
+            EventTag::Click,
+            EventTag::Scroll,
+            EventTag::Quit
+        ]
+    );
+
+    assert_eq!(
+        events.index(2),
+        EventRef::Click(EventClickRef { x: &3, y: &4 })
+    );
+    assert_eq!(events.get(4), Some(EventRef::Quit));
+    assert_eq!(events.get(5), None);
+    assert_eq!(events.click().x, [1, 3]);
+
+    let clicks: Vec<i32> = events.iter_click().map(|c| *c.x + *c.y).collect();
+    assert_eq!(clicks, [3, 7]);
+    events.iter_click_mut().for_each(|c| *c.y *= 10);
+    if let EventRefMut::Key(key) = events.index_mut(1) {
+        *key.code += 1;
+    }
+    for event in &mut events {
+        if let EventRefMut::Scroll(scroll) = event {
+            *scroll._0 *= 2.0;
+        }
+    }
+
+    let tags: Vec<_> = events
+        .iter()
+        .rev()
+        .map(|e| match e {
+            EventRef::Click(c) => *c.y,
+            EventRef::Key(k) => *k.code as i32,
+            EventRef::Scroll(s) => *s._0 as i32,
+            EventRef::Quit => -1,
+        })
+        .collect();
+    assert_eq!(tags, [-1, 1, 40, 8, 20]);
+
+    assert_eq!(events.pop(), Some(Event::Quit));
+    assert_eq!(events.pop(), Some(Event::Scroll(1.0)));
+    assert_eq!(events.pop(), Some(Event::Click { x: 3, y: 40 }));
+    assert_eq!(events.click().x, [1]);
+    events.clear();
+    assert!(events.is_empty() && events.iter_key().next().is_none());
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(derive(Debug, PartialEq))]
+struct Account {
+    id: u32,
+    #[soa(bitpacked)]
+    active: bool,
+    #[soa(bitpacked)]
+    admin: bool,
+}
+
+fn account(i: u32) -> Account {
+    Account {
+        id: i,
+        active: !i.is_multiple_of(3),
+        admin: i == 70,
+    }
+}
+
+#[test]
+fn soa_bitpacked_columns() {
+    let mut accounts: AccountSoA = (0..100).map(account).collect();
+    assert_eq!(accounts.count_where_active(), 66);
+    assert_eq!(accounts.count_where_admin(), 1);
+    assert_eq!(accounts.active.len(), 100);
+    assert!(accounts.active.capacity() >= 100);
+
+    let a = accounts.index(70);
+    assert_eq!((*a.id, *a.active, *a.admin), (70, true, true));
+    assert_eq!(
+        accounts.get(3).unwrap(),
+        AccountRef {
+            id: &3,
+            active: &false,
+            admin: &false
+        }
+    );
+
+    let mut b = accounts.index_mut(3);
+    assert!(!b.active.get() && !*b.active);
+    b.active.set(true);
+    assert_eq!(accounts.count_where_active(), 67);
+
+    for mut a in accounts.slice_mut(60..80).iter_mut() {
+        let admin = a.admin.get();
+        a.active.set(admin);
+    }
+    assert_eq!(accounts.count_where_active(), 67 - 13 + 1);
+    assert_eq!(accounts.slice(60..80).active.count_ones(), 1);
+    assert_eq!(
+        accounts.slice(63..65).active.iter().collect::<Vec<_>>(),
+        [&false, &false]
+    );
+
+    assert!(accounts.remove(70).admin);
+    accounts.insert(0, account(70));
+    assert_eq!(accounts.admin.as_slice().iter().position(|a| *a), Some(0));
+    accounts.swap_remove(0);
+    assert_eq!(accounts.count_where_admin(), 0);
+
+    accounts.sort_by_key(|a| (!*a.active, *a.id));
+    let active = accounts.count_where_active();
+    assert!(accounts.slice(..active).iter().all(|a| *a.active));
+    assert!(accounts.slice(active..).iter().all(|a| !*a.active));
+
+    let tail = accounts.split_off(90);
+    assert_eq!(tail.len(), 9);
+    assert_eq!(tail.clone(), tail);
+    let last = accounts.pop().unwrap();
+    assert!(!last.active);
+    let owned: Vec<Account> = tail.into_iter().collect();
+    assert!(owned.iter().all(|a| !a.active));
+    assert_eq!(
+        format!("{:?}", accounts.as_slice().active.slice(0..2)),
+        "[true, true]"
+    );
+}
+
+#[test]
+fn soa_bitpacked_insert_and_remove_across_words() {
+    let mut accounts: AccountSoA = (0..130).map(account).collect();
+    let mut expected: Vec<bool> = (0..130).map(|i| account(i).active).collect();
+    let actual = |accounts: &AccountSoA| accounts.iter().map(|a| *a.active).collect::<Vec<_>>();
+    for (index, value) in [(0, true), (63, false), (64, true), (65, true), (133, false)] {
+        accounts.insert(
+            index,
+            Account {
+                id: 0,
+                active: value,
+                admin: false,
+            },
+        );
+        expected.insert(index, value);
+        assert_eq!(actual(&accounts), expected);
+    }
+    for index in [134, 0, 63, 64, 127, 128, 127] {
+        assert_eq!(accounts.remove(index).active, expected.remove(index));
+        assert_eq!(actual(&accounts), expected);
+    }
+    assert_eq!(
+        accounts.count_where_active(),
+        expected.iter().filter(|a| **a).count()
+    );
+    while let Some(account) = accounts.pop() {
+        assert_eq!(account.active, expected.pop().unwrap());
+    }
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+#[soa(derive(Debug, PartialEq))]
+struct Reading {
+    id: u32,
+    #[soa(nullable)]
+    value: Option<f64>,
+}
+
+fn reading(i: u32) -> Reading {
+    Reading {
+        id: i,
+        value: (i % 4 == 1).then_some(i as f64),
+    }
+}
+
+#[test]
+fn soa_nullable_columns() {
+    let mut readings: ReadingSoA = (0..100).map(reading).collect();
+    assert_eq!(readings.value.len(), 100);
+    assert_eq!(readings.value.null_count(), 75);
+    assert_eq!(readings.index(5).value, Some(&5.0));
+    assert_eq!(
+        readings.get(6).unwrap(),
+        ReadingRef {
+            id: &6,
+            value: None
+        }
+    );
+    assert_eq!(readings.value.as_slice().values()[6], 0.0);
+
+    let valid: Vec<_> = readings.value.as_slice().iter_valid().collect();
+    assert_eq!(valid.len(), 25);
+    assert_eq!(valid[..2], [(1, &1.0), (5, &5.0)]);
+    assert_eq!(readings.slice(60..70).value.null_count(), 7);
+
+    *readings.index_mut(1).value.unwrap() = 10.0;
+    assert!(readings.index_mut(2).value.is_none());
+    for (_, value) in readings.value.as_mut_slice().iter_valid_mut() {
+        *value *= 2.0;
+    }
+    assert_eq!(readings.index(1).value, Some(&20.0));
+    assert_eq!(readings.value.replace(2, Some(4.0)), None);
+    assert_eq!(readings.value.null_count(), 74);
+
+    assert_eq!(readings.remove(2).value, Some(4.0));
+    readings.insert(0, reading(0));
+    assert_eq!(readings.swap_remove(0).value, None);
+    readings.sort_by(|a, b| b.value.partial_cmp(&a.value).unwrap());
+    assert_eq!(readings.index(0).value, Some(&194.0));
+    assert!(readings.slice(25..).iter().all(|r| r.value.is_none()));
+
+    let tail = readings.split_off(20);
+    assert_eq!(tail.clone(), tail);
+    assert_eq!(readings.pop().unwrap().value, Some(42.0));
+    let owned: Vec<Reading> = tail.into_iter().collect();
+    assert_eq!(owned.iter().filter(|r| r.value.is_some()).count(), 5);
+    assert_eq!(
+        format!("{:?}", readings.as_slice().value.slice(0..2)),
+        "[Some(194.0), Some(186.0)]"
+    );
+}
+
+#[derive(SoA, Debug, PartialEq, Clone)]
+struct Gauge<T> {
+    id: u32,
+    #[soa(nullable)]
+    value: Option<T>,
+}
+
+#[test]
+fn soa_generic_nullable_column() {
+    let mut gauges: GaugeSoA<String> = (0..4)
+        .map(|i| Gauge {
+            id: i,
+            value: (i % 2 == 0).then(|| i.to_string()),
+        })
+        .collect();
+    gauges.insert(1, Gauge { id: 9, value: None });
+    let old = gauges.replace(
+        1,
+        Gauge {
+            id: 9,
+            value: Some("nine".to_string()),
+        },
+    );
+    assert_eq!(old.value, None);
+    assert_eq!(gauges.index(1).value.map(String::as_str), Some("nine"));
+    assert_eq!(gauges.value.null_count(), 2);
+    assert_eq!(gauges.value.as_slice().values()[2], "");
+}
+
+#[test]
+fn soa_retain_dedup_drain_and_extract_if() {
+    let mut soa: ParticleSoA<i32> = (0..10).map(|i| Particle { pos: i, vel: i % 3 }).collect();
+    soa.retain(|p| *p.vel != 0);
+    assert_eq!(soa.pos, [1, 2, 4, 5, 7, 8]);
+    assert_eq!(soa.vel, [1, 2, 1, 2, 1, 2]);
+    soa.retain_mut(|p| {
+        *p.vel *= 10;
+        *p.pos < 8
+    });
+    assert_eq!(soa.vel, [10, 20, 10, 20, 10]);
+
+    soa.dedup_by_key(|p| *p.pos / 2);
+    assert_eq!(soa.pos, [1, 2, 4, 7]);
+    soa.dedup_by(|a, b| {
+        *b.vel += *a.vel;
+        *a.pos - *b.pos == 2
+    });
+    assert_eq!(soa.pos, [1, 2, 7]);
+    assert_eq!(soa.vel, [30, 40, 10]);
+
+    let mut dup: ParticleSoA<i32> = [1, 1, 2, 1, 1]
+        .into_iter()
+        .map(|pos| Particle { pos, vel: 0 })
+        .collect();
+    dup.dedup();
+    assert_eq!(dup.pos, [1, 2, 1]);
+
+    let mut soa: ParticleSoA<i32> = (0..10).map(|i| Particle { pos: i, vel: -i }).collect();
+    let drained: Vec<_> = soa.drain(2..5).map(|p| p.pos).collect();
+    assert_eq!(drained, [2, 3, 4]);
+    assert_eq!(soa.pos, [0, 1, 5, 6, 7, 8, 9]);
+    drop(soa.drain(..=1));
+    assert_eq!(soa.vel, [-5, -6, -7, -8, -9]);
+
+    let odd: Vec<_> = soa
+        .extract_if(1.., |p| {
+            *p.vel -= 1;
+            *p.pos % 2 == 1
+        })
+        .map(|p| (p.pos, p.vel))
+        .collect();
+    assert_eq!(odd, [(7, -8), (9, -10)]);
+    assert_eq!(soa.pos, [5, 6, 8]);
+    assert_eq!(soa.vel, [-5, -7, -9]);
+
+    // The nullable column moves through its view's `swap`.
+    let mut readings: ReadingSoA = (0..12).map(reading).collect();
+    let drained: Vec<_> = readings.drain(3..7).map(|r| (r.id, r.value)).collect();
+    assert_eq!(drained, [(3, None), (4, None), (5, Some(5.0)), (6, None)]);
+    assert_eq!(readings.id, [0, 1, 2, 7, 8, 9, 10, 11]);
+    let valid: Vec<_> = readings
+        .extract_if(.., |r| r.value.is_some())
+        .map(|r| r.id)
+        .collect();
+    assert_eq!(valid, [1, 9]);
+    assert_eq!(readings.id, [0, 2, 7, 8, 10, 11]);
+    assert_eq!(readings.value.null_count(), 6);
+
+    let payload = std::rc::Rc::new(String::from("ping"));
+    let mut packets: PacketSoA = (0..8).map(|i| packet(i, &payload)).collect();
+    packets.retain(|p| *p.port % 2 == 0);
+    assert_eq!(packets.port(), [8000, 8002, 8004, 8006]);
+    assert_eq!(packets.drain(1..3).len(), 2);
+    assert_eq!(packets.flags(), [0, 6]);
+    assert_eq!(std::rc::Rc::strong_count(&payload), 1 + 2);
+
+    let mut accounts: AccountSoA = (0..100).map(account).collect();
+    accounts.retain(|a| *a.active);
+    assert_eq!(accounts.len(), 66);
+    assert_eq!(accounts.count_where_active(), 66);
+    assert_eq!(accounts.count_where_admin(), 1);
+
+    let mut readings: ReadingSoA = (0..20).map(reading).collect();
+    readings.retain(|r| r.value.is_some());
+    assert_eq!(readings.id, [1, 5, 9, 13, 17]);
+    assert_eq!(readings.value.null_count(), 0);
+}
@@ -0,0 +1,10 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(private_columns)]
+struct Segment {
+    start: u32,
+    len: u32,
+}
+
+fn main() {}
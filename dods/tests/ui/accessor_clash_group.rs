@@ -0,0 +1,13 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(private_columns)]
+struct Particle {
+    #[soa(group = "motion")]
+    pos: [f32; 3],
+    #[soa(group = "motion")]
+    vel: [f32; 3],
+    iter_motion: u32,
+}
+
+fn main() {}
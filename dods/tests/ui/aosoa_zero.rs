@@ -0,0 +1,10 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(aosoa = 0)]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+fn main() {}
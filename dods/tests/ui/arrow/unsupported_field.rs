@@ -0,0 +1,19 @@
+use dods::SoA;
+
+#[derive(SoA)]
+struct Vec2 {
+    x: f32,
+    y: f32,
+}
+
+#[derive(SoA)]
+#[soa(arrow)]
+struct Body {
+    id: u32,
+    #[soa(nested)]
+    pos: Vec2,
+    tags: Vec<String>,
+    mass: Option<usize>,
+}
+
+fn main() {}
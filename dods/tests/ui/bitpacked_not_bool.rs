@@ -0,0 +1,10 @@
+use dods::SoA;
+
+#[derive(SoA)]
+struct Flags {
+    id: u32,
+    #[soa(bitpacked)]
+    mask: u8,
+}
+
+fn main() {}
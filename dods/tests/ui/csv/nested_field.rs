@@ -0,0 +1,19 @@
+use dods::SoA;
+
+#[derive(SoA)]
+struct Vec2 {
+    x: f32,
+    y: f32,
+}
+
+#[derive(SoA)]
+#[soa(csv)]
+struct Particle {
+    id: u32,
+    #[soa(nested)]
+    pos: Vec2,
+    #[soa(nested)]
+    vel: Vec2,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use dods::SoA;
+
+#[derive(SoA)]
+struct Empty {}
+
+fn main() {}
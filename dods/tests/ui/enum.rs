@@ -0,0 +1,9 @@
+use dods::SoA;
+
+#[derive(SoA)]
+enum Event<T> {
+    Click { x: i32, y: i32 },
+    Custom(T),
+}
+
+fn main() {}
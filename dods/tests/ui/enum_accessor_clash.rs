@@ -0,0 +1,9 @@
+use dods::SoA;
+
+#[derive(SoA)]
+enum Command {
+    Push { value: u32 },
+    Clear,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(serde = "columns")]
+enum Event {
+    Click { x: i32, y: i32 },
+    Scroll(f32),
+}
+
+fn main() {}
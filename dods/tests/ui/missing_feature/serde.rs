@@ -0,0 +1,10 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(serde = "rows")]
+struct Particle {
+    pos: f32,
+    vel: f32,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(mmap)]
+struct Particle {
+    id: u32,
+    pos: [f32; 3],
+    alive: bool,
+    name: String,
+    weights: [f32; N],
+}
+
+const N: usize = 4;
+
+fn main() {}
@@ -0,0 +1,17 @@
+use dods::SoA;
+
+#[derive(SoA)]
+struct Vec2 {
+    x: f32,
+    y: f32,
+}
+
+#[derive(SoA)]
+#[soa(storage = "single")]
+struct Particle {
+    #[soa(nested)]
+    pos: Vec2,
+    mass: f32,
+}
+
+fn main() {}
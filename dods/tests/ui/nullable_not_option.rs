@@ -0,0 +1,10 @@
+use dods::SoA;
+
+#[derive(SoA)]
+struct Sample {
+    id: u32,
+    #[soa(nullable)]
+    value: f32,
+}
+
+fn main() {}
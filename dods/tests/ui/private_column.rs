@@ -0,0 +1,15 @@
+mod inner {
+    use dods::SoA;
+
+    #[derive(SoA)]
+    pub struct Account {
+        pub id: u32,
+        balance: i64,
+    }
+}
+
+fn main() {
+    let soa = inner::AccountSoA::new();
+    let _ = soa.id;
+    let _ = soa.balance;
+}
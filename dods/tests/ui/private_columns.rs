@@ -0,0 +1,15 @@
+mod inner {
+    use dods::SoA;
+
+    #[derive(SoA)]
+    #[soa(private_columns)]
+    pub struct Reading {
+        pub temperature: f32,
+    }
+}
+
+fn main() {
+    let mut soa = inner::ReadingSoA::new();
+    soa.temperature_mut()[0] = 1.0;
+    soa.temperature.push(1.0);
+}
@@ -0,0 +1,12 @@
+use dods::{SoA, soa_project};
+
+#[derive(SoA)]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+fn main() {
+    let mut points = PointSoA::new();
+    for _ in soa_project!(points => mut x, y, x) {}
+}
@@ -0,0 +1,9 @@
+use dods::SoA;
+
+#[derive(SoA)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use dods::SoA;
+
+#[derive(SoA)]
+struct Marker;
+
+fn main() {}
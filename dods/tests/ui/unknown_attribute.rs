@@ -0,0 +1,9 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(visibility = "pub")]
+struct Sample {
+    value: f32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use dods::SoA;
+
+#[derive(SoA)]
+#[soa(storage = "linked")]
+struct Point {
+    x: f32,
+    y: f32,
+}
+
+fn main() {}
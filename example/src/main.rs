@@ -39,7 +39,7 @@ fn main() {
 }
 
 fn apply_heat_wave(station: &mut SensorReadingSoA, increase: f32) {
-    for mut r in station.iter_mut() {
+    for r in station.iter_mut() {
         *r.temperature += increase;
     }
 }